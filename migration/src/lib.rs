@@ -1,12 +1,38 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20261018_000001_create_account_platform_tokens;
+mod m20261018_000002_create_account_transfers;
+mod m20261018_000003_create_oauth_clients;
+mod m20261018_000004_create_signing_keys;
+mod m20261018_000005_create_api_clients;
+mod m20261018_000006_hash_account_secrets;
+mod m20261018_000007_create_account_sessions;
+mod m20261018_000008_create_roles;
+mod m20261018_000009_create_account_bans;
+mod m20261018_000010_create_account_events;
+mod m20261018_000011_create_webhooks;
+mod m20261018_000012_add_account_subject;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20261018_000001_create_account_platform_tokens::Migration),
+            Box::new(m20261018_000002_create_account_transfers::Migration),
+            Box::new(m20261018_000003_create_oauth_clients::Migration),
+            Box::new(m20261018_000004_create_signing_keys::Migration),
+            Box::new(m20261018_000005_create_api_clients::Migration),
+            Box::new(m20261018_000006_hash_account_secrets::Migration),
+            Box::new(m20261018_000007_create_account_sessions::Migration),
+            Box::new(m20261018_000008_create_roles::Migration),
+            Box::new(m20261018_000009_create_account_bans::Migration),
+            Box::new(m20261018_000010_create_account_events::Migration),
+            Box::new(m20261018_000011_create_webhooks::Migration),
+            Box::new(m20261018_000012_add_account_subject::Migration),
+        ]
     }
 }
@@ -0,0 +1,122 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountPlatformTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccountPlatformTokens::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountPlatformTokens::Account)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountPlatformTokens::Platform)
+                            .big_integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountPlatformTokens::AccessToken)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountPlatformTokens::RefreshToken)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AccountPlatformTokens::Scopes).text().not_null())
+                    .col(
+                        ColumnDef::new(AccountPlatformTokens::ExpiresAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountPlatformTokens::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountPlatformTokens::UpdatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountPlatformTokens::DeletedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .index(
+                        Index::create()
+                            .name("aptokens-account")
+                            .table(AccountPlatformTokens::Table)
+                            .col(AccountPlatformTokens::Account),
+                    )
+                    .index(
+                        Index::create()
+                            .name("aptokens-expires")
+                            .table(AccountPlatformTokens::Table)
+                            .col(AccountPlatformTokens::ExpiresAt),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AccountPlatformTokens::Table, AccountPlatformTokens::Account)
+                            .to(Accounts::Table, Accounts::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AccountPlatformTokens::Table, AccountPlatformTokens::Platform)
+                            .to(AccountPlatforms::Table, AccountPlatforms::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AccountPlatformTokens::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Accounts {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum AccountPlatforms {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum AccountPlatformTokens {
+    Table,
+    Id,
+    Account,
+    Platform,
+    AccessToken,
+    RefreshToken,
+    Scopes,
+    ExpiresAt,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
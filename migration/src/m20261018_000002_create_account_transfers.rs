@@ -0,0 +1,106 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountTransfers::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccountTransfers::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AccountTransfers::Account).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(AccountTransfers::RequestedBy)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AccountTransfers::Platform).string_len(32).not_null())
+                    .col(
+                        ColumnDef::new(AccountTransfers::OldPlatformUser)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountTransfers::NewPlatformUser)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AccountTransfers::ArchivedData).text().not_null())
+                    .col(ColumnDef::new(AccountTransfers::Reason).text().not_null())
+                    .col(ColumnDef::new(AccountTransfers::Status).string_len(16).not_null())
+                    .col(
+                        ColumnDef::new(AccountTransfers::ReviewedBy)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountTransfers::ReviewedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AccountTransfers::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(AccountTransfers::UpdatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(AccountTransfers::DeletedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("atransfers-account")
+                            .table(AccountTransfers::Table)
+                            .col(AccountTransfers::Account),
+                    )
+                    .index(
+                        Index::create()
+                            .name("atransfers-status")
+                            .table(AccountTransfers::Table)
+                            .col(AccountTransfers::Status),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AccountTransfers::Table, AccountTransfers::Account)
+                            .to(Accounts::Table, Accounts::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AccountTransfers::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Accounts {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum AccountTransfers {
+    Table,
+    Id,
+    Account,
+    RequestedBy,
+    Platform,
+    OldPlatformUser,
+    NewPlatformUser,
+    ArchivedData,
+    Reason,
+    Status,
+    ReviewedBy,
+    ReviewedAt,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
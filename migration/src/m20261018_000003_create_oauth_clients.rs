@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthClients::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OauthClients::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(OauthClients::ClientId).string_len(64).not_null())
+                    .col(ColumnDef::new(OauthClients::ClientSecret).text().not_null())
+                    .col(ColumnDef::new(OauthClients::Name).string_len(255).not_null())
+                    .col(ColumnDef::new(OauthClients::RedirectUris).text().not_null())
+                    .col(ColumnDef::new(OauthClients::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(OauthClients::UpdatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(OauthClients::DeletedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("oclients-client-id")
+                            .table(OauthClients::Table)
+                            .col(OauthClients::ClientId)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OauthClients::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum OauthClients {
+    Table,
+    Id,
+    ClientId,
+    ClientSecret,
+    Name,
+    RedirectUris,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SigningKeys::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SigningKeys::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(SigningKeys::Kid).string_len(64).not_null())
+                    .col(ColumnDef::new(SigningKeys::Algorithm).string_len(16).not_null())
+                    .col(ColumnDef::new(SigningKeys::PrivateKey).text().not_null())
+                    .col(ColumnDef::new(SigningKeys::PublicX).string_len(128).not_null())
+                    .col(ColumnDef::new(SigningKeys::PublicY).string_len(128).not_null())
+                    .col(ColumnDef::new(SigningKeys::RetiredAt).big_integer().not_null())
+                    .col(ColumnDef::new(SigningKeys::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(SigningKeys::UpdatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(SigningKeys::DeletedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("skeys-kid")
+                            .table(SigningKeys::Table)
+                            .col(SigningKeys::Kid)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SigningKeys::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SigningKeys {
+    Table,
+    Id,
+    Kid,
+    Algorithm,
+    PrivateKey,
+    PublicX,
+    PublicY,
+    RetiredAt,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiClients::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ApiClients::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ApiClients::Name).string_len(255).not_null())
+                    .col(ColumnDef::new(ApiClients::KeyHash).char_len(64).not_null())
+                    .col(ColumnDef::new(ApiClients::PreviousKeyHash).char_len(64).not_null())
+                    .col(
+                        ColumnDef::new(ApiClients::PreviousKeyExpiresAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ApiClients::Scopes).string_len(255).not_null())
+                    .col(ColumnDef::new(ApiClients::ExpiresAt).big_integer().not_null())
+                    .col(ColumnDef::new(ApiClients::LastUsedAt).big_integer().not_null())
+                    .col(ColumnDef::new(ApiClients::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(ApiClients::UpdatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(ApiClients::DeletedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("aclients-key-hash")
+                            .table(ApiClients::Table)
+                            .col(ApiClients::KeyHash),
+                    )
+                    .index(
+                        Index::create()
+                            .name("aclients-previous-key-hash")
+                            .table(ApiClients::Table)
+                            .col(ApiClients::PreviousKeyHash),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiClients::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ApiClients {
+    Table,
+    Id,
+    Name,
+    KeyHash,
+    PreviousKeyHash,
+    PreviousKeyExpiresAt,
+    Scopes,
+    ExpiresAt,
+    LastUsedAt,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
@@ -0,0 +1,72 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Account secrets are stored as a sha256 hash from now on, which needs a wider column.
+/// The legacy columns hold the previous token and the hash of its secret while existing accounts are rotated over
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .modify_column(ColumnDef::new(Accounts::TokenSecret).char_len(64).not_null())
+                    .add_column(
+                        ColumnDef::new(Accounts::LegacyToken)
+                            .char_len(32)
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Accounts::LegacyTokenSecret)
+                            .char_len(64)
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("accounts-legacy-token")
+                    .table(Accounts::Table)
+                    .col(Accounts::LegacyToken)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("accounts-legacy-token")
+                    .table(Accounts::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .drop_column(Accounts::LegacyToken)
+                    .drop_column(Accounts::LegacyTokenSecret)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Accounts {
+    Table,
+    TokenSecret,
+    LegacyToken,
+    LegacyTokenSecret,
+}
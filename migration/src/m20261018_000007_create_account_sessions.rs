@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountSessions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccountSessions::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AccountSessions::SessionId).string_len(64).not_null())
+                    .col(ColumnDef::new(AccountSessions::Account).big_integer().not_null())
+                    .col(ColumnDef::new(AccountSessions::Data).text().not_null())
+                    .col(ColumnDef::new(AccountSessions::UserAgent).string_len(512).not_null())
+                    .col(ColumnDef::new(AccountSessions::Ip).string_len(64).not_null())
+                    .col(ColumnDef::new(AccountSessions::ExpiresAt).big_integer().not_null())
+                    .col(ColumnDef::new(AccountSessions::LastSeenAt).big_integer().not_null())
+                    .col(ColumnDef::new(AccountSessions::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(AccountSessions::UpdatedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("asessions-session-id")
+                            .table(AccountSessions::Table)
+                            .col(AccountSessions::SessionId)
+                            .unique(),
+                    )
+                    .index(
+                        Index::create()
+                            .name("asessions-account")
+                            .table(AccountSessions::Table)
+                            .col(AccountSessions::Account),
+                    )
+                    .index(
+                        Index::create()
+                            .name("asessions-expires-at")
+                            .table(AccountSessions::Table)
+                            .col(AccountSessions::ExpiresAt),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AccountSessions::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AccountSessions {
+    Table,
+    Id,
+    SessionId,
+    Account,
+    Data,
+    UserAgent,
+    Ip,
+    ExpiresAt,
+    LastSeenAt,
+    CreatedAt,
+    UpdatedAt,
+}
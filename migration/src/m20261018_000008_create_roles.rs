@@ -0,0 +1,153 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Roles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Roles::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Roles::Name).string_len(32).not_null())
+                    .col(ColumnDef::new(Roles::Description).string_len(255).not_null())
+                    .col(ColumnDef::new(Roles::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(Roles::UpdatedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("roles-name")
+                            .table(Roles::Table)
+                            .col(Roles::Name)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RolePermissions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RolePermissions::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RolePermissions::Role).big_integer().not_null())
+                    .col(ColumnDef::new(RolePermissions::Permission).string_len(64).not_null())
+                    .col(ColumnDef::new(RolePermissions::CreatedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("rpermissions-role-permission")
+                            .table(RolePermissions::Table)
+                            .col(RolePermissions::Role)
+                            .col(RolePermissions::Permission)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(RolePermissions::Table, RolePermissions::Role)
+                            .to(Roles::Table, Roles::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountRoles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccountRoles::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AccountRoles::Account).big_integer().not_null())
+                    .col(ColumnDef::new(AccountRoles::Role).big_integer().not_null())
+                    .col(ColumnDef::new(AccountRoles::GrantedBy).big_integer().not_null())
+                    .col(ColumnDef::new(AccountRoles::CreatedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("aroles-account-role")
+                            .table(AccountRoles::Table)
+                            .col(AccountRoles::Account)
+                            .col(AccountRoles::Role)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AccountRoles::Table, AccountRoles::Account)
+                            .to(Accounts::Table, Accounts::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AccountRoles::Table, AccountRoles::Role)
+                            .to(Roles::Table, Roles::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AccountRoles::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RolePermissions::Table).to_owned())
+            .await?;
+        manager.drop_table(Table::drop().table(Roles::Table).to_owned()).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Accounts {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Roles {
+    Table,
+    Id,
+    Name,
+    Description,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum RolePermissions {
+    Table,
+    Id,
+    Role,
+    Permission,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum AccountRoles {
+    Table,
+    Id,
+    Account,
+    Role,
+    GrantedBy,
+    CreatedAt,
+}
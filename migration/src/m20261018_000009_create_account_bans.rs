@@ -0,0 +1,70 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountBans::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccountBans::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AccountBans::Account).big_integer().not_null())
+                    .col(ColumnDef::new(AccountBans::Reason).text().not_null())
+                    .col(ColumnDef::new(AccountBans::Actor).big_integer().not_null())
+                    .col(ColumnDef::new(AccountBans::ExpiresAt).big_integer().not_null())
+                    .col(ColumnDef::new(AccountBans::LiftedBy).big_integer().not_null())
+                    .col(ColumnDef::new(AccountBans::LiftedAt).big_integer().not_null())
+                    .col(ColumnDef::new(AccountBans::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(AccountBans::UpdatedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("abans-account")
+                            .table(AccountBans::Table)
+                            .col(AccountBans::Account),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AccountBans::Table, AccountBans::Account)
+                            .to(Accounts::Table, Accounts::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AccountBans::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Accounts {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum AccountBans {
+    Table,
+    Id,
+    Account,
+    Reason,
+    Actor,
+    ExpiresAt,
+    LiftedBy,
+    LiftedAt,
+    CreatedAt,
+    UpdatedAt,
+}
@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountEvents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccountEvents::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AccountEvents::Account).big_integer().not_null())
+                    .col(ColumnDef::new(AccountEvents::Event).string_len(64).not_null())
+                    .col(ColumnDef::new(AccountEvents::Platform).string_len(32).not_null())
+                    .col(ColumnDef::new(AccountEvents::PlatformUser).string_len(255).not_null())
+                    .col(ColumnDef::new(AccountEvents::Actor).big_integer().not_null())
+                    .col(ColumnDef::new(AccountEvents::Ip).string_len(64).not_null())
+                    .col(ColumnDef::new(AccountEvents::Before).text().not_null())
+                    .col(ColumnDef::new(AccountEvents::After).text().not_null())
+                    .col(ColumnDef::new(AccountEvents::CreatedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("aevents-account-created-at")
+                            .table(AccountEvents::Table)
+                            .col(AccountEvents::Account)
+                            .col(AccountEvents::CreatedAt),
+                    )
+                    .index(
+                        Index::create()
+                            .name("aevents-platform-created-at")
+                            .table(AccountEvents::Table)
+                            .col(AccountEvents::Platform)
+                            .col(AccountEvents::CreatedAt),
+                    )
+                    .index(
+                        Index::create()
+                            .name("aevents-created-at")
+                            .table(AccountEvents::Table)
+                            .col(AccountEvents::CreatedAt),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AccountEvents::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AccountEvents {
+    Table,
+    Id,
+    Account,
+    Event,
+    Platform,
+    PlatformUser,
+    Actor,
+    Ip,
+    Before,
+    After,
+    CreatedAt,
+}
@@ -0,0 +1,118 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookSubscriptions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WebhookSubscriptions::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(WebhookSubscriptions::Name).string_len(64).not_null())
+                    .col(ColumnDef::new(WebhookSubscriptions::Url).string_len(2048).not_null())
+                    .col(ColumnDef::new(WebhookSubscriptions::Secret).text().not_null())
+                    .col(ColumnDef::new(WebhookSubscriptions::Events).string_len(255).not_null())
+                    .col(ColumnDef::new(WebhookSubscriptions::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(WebhookSubscriptions::UpdatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(WebhookSubscriptions::DeletedAt).big_integer().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookDeliveries::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WebhookDeliveries::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(WebhookDeliveries::Subscription).big_integer().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::Event).string_len(64).not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::Payload).text().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::Status).string_len(16).not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::Attempts).integer().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::NextAttemptAt).big_integer().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::ResponseStatus).integer().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::Error).text().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::DeliveredAt).big_integer().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::UpdatedAt).big_integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("wdeliveries-status-next-attempt")
+                            .table(WebhookDeliveries::Table)
+                            .col(WebhookDeliveries::Status)
+                            .col(WebhookDeliveries::NextAttemptAt),
+                    )
+                    .index(
+                        Index::create()
+                            .name("wdeliveries-subscription-created")
+                            .table(WebhookDeliveries::Table)
+                            .col(WebhookDeliveries::Subscription)
+                            .col(WebhookDeliveries::CreatedAt),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(WebhookDeliveries::Table, WebhookDeliveries::Subscription)
+                            .to(WebhookSubscriptions::Table, WebhookSubscriptions::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebhookDeliveries::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(WebhookSubscriptions::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum WebhookSubscriptions {
+    Table,
+    Id,
+    Name,
+    Url,
+    Secret,
+    Events,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
+
+#[derive(DeriveIden)]
+enum WebhookDeliveries {
+    Table,
+    Id,
+    Subscription,
+    Event,
+    Payload,
+    Status,
+    Attempts,
+    NextAttemptAt,
+    ResponseStatus,
+    Error,
+    DeliveredAt,
+    CreatedAt,
+    UpdatedAt,
+}
@@ -0,0 +1,12 @@
+INSERT INTO account_platform_tokens
+(`account`, `platform`, `access_token`, `refresh_token`, `scopes`, `expires_at`, `created_at`, `updated_at`, `deleted_at`)
+VALUES (?,?,?,?,?,?,?,?,?)
+ON DUPLICATE KEY
+UPDATE
+    `account` = values(`account`),
+    `access_token` = values(`access_token`),
+    `refresh_token` = values(`refresh_token`),
+    `scopes` = values(`scopes`),
+    `expires_at` = values(`expires_at`),
+    `updated_at` = values(`created_at`),
+    `deleted_at` = values(`deleted_at`)
\ No newline at end of file
@@ -50,17 +50,63 @@ twitch_data AS (
         account_platforms.id = membership_data.platform AND
         account_platforms.account = membership_data.account AND
         membership_data.key = 'display_name'
+),
+steam_data AS (
+    SELECT
+        account_platforms.account AS account,
+        membership_data.platform AS platform,
+        membership_data.value AS display_name
+    FROM source_platform
+    INNER JOIN account_platforms ON
+        source_platform.account = account_platforms.account AND
+        account_platforms.platform = 'steam'
+    INNER JOIN account_platform_data AS membership_data ON
+        account_platforms.id = membership_data.platform AND
+        account_platforms.account = membership_data.account AND
+        membership_data.key = 'display_name'
+),
+battlenet_data AS (
+    SELECT
+        account_platforms.account AS account,
+        membership_data.platform AS platform,
+        membership_data.value AS display_name
+    FROM source_platform
+    INNER JOIN account_platforms ON
+        source_platform.account = account_platforms.account AND
+        account_platforms.platform = 'battlenet'
+    INNER JOIN account_platform_data AS membership_data ON
+        account_platforms.id = membership_data.platform AND
+        account_platforms.account = membership_data.account AND
+        membership_data.key = 'battletag'
+),
+ban_data AS (
+    SELECT
+        account_bans.account AS account,
+        IF(MIN(account_bans.expires_at) = 0, 'banned', 'suspended') AS status,
+        IF(MIN(account_bans.expires_at) = 0, 0, MAX(account_bans.expires_at)) AS expires_at
+    FROM source_platform
+    INNER JOIN account_bans ON source_platform.account = account_bans.account
+    WHERE account_bans.lifted_at = 0
+    AND (account_bans.expires_at = 0 OR account_bans.expires_at > UNIX_TIMESTAMP())
+    GROUP BY account_bans.account
 )
 SELECT
     accounts.token AS account_token,
     discord_data.username AS username,
     discord_data.display_name AS discord,
     bungie_data.display_name AS bungie,
-    COALESCE(twitch_data.display_name, '') AS twitch
+    COALESCE(twitch_data.display_name, '') AS twitch,
+    COALESCE(steam_data.display_name, '') AS steam,
+    COALESCE(battlenet_data.display_name, '') AS battlenet,
+    COALESCE(ban_data.status, '') AS ban_status,
+    COALESCE(ban_data.expires_at, 0) AS banned_until
 FROM source_platform
 INNER JOIN accounts ON source_platform.account = accounts.id
 INNER JOIN bungie_data ON
     accounts.id = bungie_data.account AND
     source_platform.id = bungie_data.platform
 INNER JOIN discord_data ON accounts.id = discord_data.account
-LEFT JOIN twitch_data ON accounts.id = twitch_data.account
\ No newline at end of file
+LEFT JOIN twitch_data ON accounts.id = twitch_data.account
+LEFT JOIN steam_data ON accounts.id = steam_data.account
+LEFT JOIN battlenet_data ON accounts.id = battlenet_data.account
+LEFT JOIN ban_data ON accounts.id = ban_data.account
\ No newline at end of file
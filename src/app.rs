@@ -1,3 +1,14 @@
+pub mod audit;
+pub mod bungie;
+pub mod crypto;
 pub mod discord;
 pub mod extension;
+pub mod jwt;
+pub mod oidc;
+pub mod platform;
+pub mod policy;
+pub mod rate_limit;
+pub mod redirect;
 pub mod session;
+pub mod twitch;
+pub mod webhook;
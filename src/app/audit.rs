@@ -0,0 +1,46 @@
+use crate::app::session::{self, store::ClientIp, SessionKey};
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use axum_sessions::SessionHandle;
+use levelcrush::{axum, axum_sessions, tokio};
+
+/// Who is behind the request that is currently being handled
+#[derive(Clone, Debug, Default)]
+pub struct AuditContext {
+    /// token of the account logged into the session, empty for jobs and anonymous requests
+    pub actor: String,
+    pub ip: String,
+}
+
+tokio::task_local! {
+    static CONTEXT: AuditContext;
+}
+
+/// The context of the request being handled. Jobs and anything spawned off a request get an empty context
+pub fn current() -> AuditContext {
+    CONTEXT.try_with(|context| context.clone()).unwrap_or_default()
+}
+
+/// Makes the session account and client ip available to the audit log for the rest of the request.
+/// Has to sit inside the session layer
+pub async fn scope<B>(req: Request<B>, next: Next<B>) -> Response {
+    let actor = match req.extensions().get::<SessionHandle>() {
+        Some(session_handle) => {
+            let session = session_handle.read().await;
+            session::read::<String>(SessionKey::Account, &session).unwrap_or_default()
+        }
+        None => String::new(),
+    };
+
+    let context = AuditContext {
+        actor,
+        ip: req
+            .extensions()
+            .get::<ClientIp>()
+            .map(|ClientIp(ip)| ip.clone())
+            .unwrap_or_default(),
+    };
+
+    CONTEXT.scope(context, next.run(req)).await
+}
@@ -0,0 +1,315 @@
+use crate::app::extension::AccountExtension;
+use crate::database::platform_data::NewAccountPlatformData;
+use levelcrush::app::ApplicationState;
+use levelcrush::{tokio, tracing};
+use tokio::join;
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct BungieUserData {
+    #[serde(rename = "membershipId")]
+    pub membership_id: String,
+
+    #[serde(rename = "uniqueName")]
+    pub unique_name: String,
+
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+
+    #[serde(default, rename = "xboxDisplayName")]
+    pub xbox_display_name: String,
+
+    #[serde(default, rename = "blizzardDisplayName")]
+    pub blizzard_display_name: String,
+
+    #[serde(default, rename = "steamDisplayName")]
+    pub steam_display_name: String,
+
+    #[serde(default, rename = "twitchDisplayName")]
+    pub twitch_display_name: String,
+
+    #[serde(default, rename = "stadiaDisplayName")]
+    pub stadia_display_name: String,
+
+    #[serde(default, rename = "egsDisplayName")]
+    pub egs_display_name: String,
+
+    #[serde(default, rename = "psnDisplayName")]
+    pub psn_display_name: String,
+
+    #[serde(default, rename = "cachedBungieGlobalDisplayName")]
+    pub global_display_name: String,
+
+    #[serde(default, rename = "cachedBungieGlobalDisplayNameCode")]
+    pub global_display_name_code: i32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct BungieMembership {
+    #[serde(rename = "LastSeenDisplayName")]
+    pub last_seen_display_name: String,
+
+    #[serde(rename = "applicableMembershipTypes")]
+    pub applicable_membership_types: Vec<i32>,
+
+    #[serde(rename = "membershipId")]
+    pub membership_id: String,
+
+    #[serde(rename = "membershipType")]
+    pub membership_type: i32,
+
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+
+    #[serde(rename = "bungieGlobalDisplayName")]
+    pub global_display_name: String,
+
+    #[serde(rename = "bungieGlobalDisplayNameCode")]
+    pub global_display_name_code: i32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct BungieMembershipData {
+    #[serde(rename = "destinyMemberships")]
+    pub memberships: Vec<BungieMembership>,
+
+    #[serde(rename = "primaryMembershipId")]
+    pub primary_membership_id: String,
+
+    #[serde(rename = "bungieNetUser")]
+    pub net_user: BungieUserData,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct BungieResponse<T> {
+    #[serde(rename = "Response")]
+    pub response: T,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct BungieGroup {
+    #[serde(rename = "groupId")]
+    pub group_id: String,
+    pub name: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct BungieGroupMembership {
+    pub group: BungieGroup,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct BungieGroupsForMember {
+    #[serde(default)]
+    pub results: Vec<BungieGroupMembership>,
+}
+
+/// user information fetched from bungie after validating
+#[derive(Default, Debug)]
+pub struct BungieIdentity {
+    pub user: BungieUserData,
+    pub memberships: BungieMembershipData,
+    /// group ids of the clans the user is in. None when bungie could not tell us
+    pub clans: Option<Vec<String>>,
+}
+
+/// platform data key holding a comma seperated list of the clan group ids the bungie user is in
+pub const CLAN_IDS_KEY: &str = "clan_ids";
+
+/// this is setup by comparing bungie net platform membershiop types
+/// and comparing to raid report platforms
+/// https://bungie-net.github.io/#/components/schemas/BungieMembershipType
+fn get_membership_name(membership_type: i32) -> &'static str {
+    match membership_type {
+        0 => "none", // 0 is verified to be none
+        1 => "xb",   // 1 is verified to be xbox
+        2 => "ps",   // 2 is verified to be playstation
+        _ => "pc", // other numbers either result in epic game store, steam, battle.net, or unknown numbers. Values like -1 are not possible and value 254 is reserved and not used
+    }
+}
+
+/// Fetches the bungie.net user and their destiny memberships.
+/// Both endpoints are public, so the access token is optional and only the api key is required
+pub async fn fetch_identity(
+    membership_id: &str,
+    access_token: Option<&str>,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<BungieIdentity> {
+    let api_key = state.extension.bungie_api_key.clone();
+
+    // construct our endpoint urls that we will need to run
+    let bungie_user_endpoint = format!(
+        "https://www.bungie.net/Platform/User/GetBungieNetUserById/{}/",
+        membership_id
+    );
+    let bungie_membership_endpoint = format!(
+        "https://www.bungie.net/Platform/User/GetMembershipsById/{}/-1/",
+        membership_id
+    );
+
+    // 254 is the bungie.net membership type, 0/1 asks for every clan
+    let bungie_clan_endpoint = format!(
+        "https://www.bungie.net/Platform/GroupV2/User/254/{}/0/1/",
+        membership_id
+    );
+
+    let mut user_request = state
+        .extension
+        .http_client
+        .get(bungie_user_endpoint)
+        .header("X-API-KEY", api_key.as_str())
+        .header("Accept", "application/json");
+
+    let mut membership_request = state
+        .extension
+        .http_client
+        .get(bungie_membership_endpoint)
+        .header("X-API-KEY", api_key.as_str())
+        .header("Accept", "application/json");
+
+    let mut clan_request = state
+        .extension
+        .http_client
+        .get(bungie_clan_endpoint)
+        .header("X-API-KEY", api_key.as_str())
+        .header("Accept", "application/json");
+
+    if let Some(access_token) = access_token {
+        user_request = user_request.bearer_auth(access_token);
+        membership_request = membership_request.bearer_auth(access_token);
+        clan_request = clan_request.bearer_auth(access_token);
+    }
+
+    let user_request_future = user_request.send();
+    let membership_request_future = membership_request.send();
+    let clan_request_future = clan_request.send();
+
+    let (user_response, membership_response, clan_response) =
+        join!(user_request_future, membership_request_future, clan_request_future);
+
+    // so long as we have made a call to both the user endpoint and membership endpoints we can continue on here
+    let user_data = match user_response {
+        Ok(response) => response.json::<BungieResponse<BungieUserData>>().await.ok()?,
+        Err(err) => {
+            tracing::error!("Request Error: {}", err);
+            return None;
+        }
+    };
+
+    let membership_data = match membership_response {
+        Ok(response) => response
+            .json::<BungieResponse<BungieMembershipData>>()
+            .await
+            .ok()?,
+        Err(err) => {
+            tracing::error!("Request Error: {}", err);
+            return None;
+        }
+    };
+
+    // clans are nice to have, so a failure here does not stop us from returning the user
+    let clans = match clan_response {
+        Ok(response) => match response.json::<BungieResponse<BungieGroupsForMember>>().await {
+            Ok(groups) => Some(
+                groups
+                    .response
+                    .results
+                    .into_iter()
+                    .map(|membership| membership.group.group_id)
+                    .collect(),
+            ),
+            Err(err) => {
+                tracing::error!("Could not parse clan response for bungie! {}", err);
+                None
+            }
+        },
+        Err(err) => {
+            tracing::error!("Request Error: {}", err);
+            None
+        }
+    };
+
+    Some(BungieIdentity {
+        user: user_data.response,
+        memberships: membership_data.response,
+        clans,
+    })
+}
+
+/// The platform data stored for a bungie user
+pub fn platform_data(identity: &BungieIdentity) -> Vec<NewAccountPlatformData> {
+    let user_data = &identity.user;
+    let membership_data = &identity.memberships;
+    let mut data = vec![
+        NewAccountPlatformData {
+            key: "bungie_id".to_string(),
+            value: user_data.membership_id.to_string(),
+        },
+        NewAccountPlatformData {
+            key: "primary_membership_id".to_string(),
+            value: membership_data.primary_membership_id.clone(),
+        },
+        NewAccountPlatformData {
+            key: "display_name".to_string(),
+            value: user_data.display_name.clone(),
+        },
+        NewAccountPlatformData {
+            key: "unique_name".to_string(),
+            value: user_data.unique_name.clone(),
+        },
+    ];
+
+    let mut membership_types = Vec::new();
+    // now loop through memberships and add some information about them as well into our metadata
+    for membership in membership_data.memberships.iter() {
+        // perform a check to see if this is the primary membership , this will only ever trigger once
+        let is_primary_membership = membership_data.primary_membership_id == membership.membership_id;
+        if is_primary_membership {
+            let primary_platform_type = membership.membership_type;
+            let primary_platform_name = get_membership_name(primary_platform_type);
+
+            data.push(NewAccountPlatformData {
+                key: "primary_platform".to_string(),
+                value: primary_platform_type.to_string(),
+            });
+
+            data.push(NewAccountPlatformData {
+                key: "primary_platform_abbr".to_string(),
+                value: primary_platform_name.to_string(),
+            })
+        }
+
+        let membership_key = format!("membership_{}", membership.membership_type);
+        let membership_id_key = format!("{}_id", membership_key);
+        let membership_display_name_key = format!("{}_display_name", membership_key);
+
+        // store membership id
+        data.push(NewAccountPlatformData {
+            key: membership_id_key,
+            value: membership.membership_id.clone(),
+        });
+
+        // store membership display name
+        data.push(NewAccountPlatformData {
+            key: membership_display_name_key,
+            value: membership.display_name.clone(),
+        });
+
+        membership_types.push(membership.membership_type.to_string());
+    }
+
+    // also store as a comma seperated list the membership types we have tied to this account platform
+    data.push(NewAccountPlatformData {
+        key: "memberships".to_string(),
+        value: membership_types.join(","),
+    });
+
+    // only overwrite the clans we know about when bungie actually answered
+    if let Some(clans) = &identity.clans {
+        data.push(NewAccountPlatformData {
+            key: CLAN_IDS_KEY.to_string(),
+            value: clans.join(","),
+        });
+    }
+
+    data
+}
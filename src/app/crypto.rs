@@ -0,0 +1,115 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use levelcrush::tracing;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::pkcs8::{EncodePrivateKey, LineEnding};
+use sha2::{Digest, Sha256};
+
+/// size of the nonce that is prepended to every encrypted value
+const NONCE_LENGTH: usize = 12;
+
+/// the secret can be any length, so we hash it down to the 256 bits AES-256 requires
+fn cipher(secret: &str) -> Aes256Gcm {
+    let digest = Sha256::digest(secret.as_bytes());
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&digest))
+}
+
+/// Encrypts the value with AES-256-GCM and returns the nonce + ciphertext as base64
+pub fn encrypt(value: &str, secret: &str) -> Option<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    match cipher(secret).encrypt(&nonce, value.as_bytes()) {
+        Ok(ciphertext) => {
+            let mut output = nonce.to_vec();
+            output.extend(ciphertext);
+            Some(STANDARD.encode(output))
+        }
+        Err(err) => {
+            tracing::error!("Unable to encrypt value: {}", err);
+            None
+        }
+    }
+}
+
+/// Decrypts a value produced by `encrypt`. Returns None if the value was tampered with or the secret does not match
+pub fn decrypt(value: &str, secret: &str) -> Option<String> {
+    let bytes = STANDARD.decode(value).ok()?;
+    if bytes.len() < NONCE_LENGTH {
+        return None;
+    }
+
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+    match cipher(secret).decrypt(Nonce::from_slice(nonce), ciphertext) {
+        Ok(plaintext) => String::from_utf8(plaintext).ok(),
+        Err(err) => {
+            tracing::error!("Unable to decrypt value: {}", err);
+            None
+        }
+    }
+}
+
+/// Generates a url safe token from `length` bytes of the OS random number generator
+pub fn random_token(length: usize) -> String {
+    let mut bytes = vec![0u8; length];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The S256 code challenge for a PKCE code verifier. https://datatracker.ietf.org/doc/html/rfc7636#section-4.2
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Hex encoded sha256 of a secret. Only used for high entropy values we generate ourselves, so no salt is needed
+pub fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Hex encoded HMAC-SHA256 of the value. Used where the receiving end is expected to check the signature themselves
+pub fn sign_hex(value: &str, key: &str) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(value.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Compares two secrets without returning early on the first mismatched byte
+pub fn constant_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// A freshly generated P-256 key. The public point is split into the base64url coordinates a JWK expects
+pub struct EcKeyPair {
+    pub private_pem: String,
+    pub x: String,
+    pub y: String,
+}
+
+/// Generates a P-256 key for signing ES256 tokens
+pub fn generate_es256() -> Option<EcKeyPair> {
+    let secret = p256::SecretKey::random(&mut OsRng);
+    let private_pem = match secret.to_pkcs8_pem(LineEnding::LF) {
+        Ok(pem) => pem.to_string(),
+        Err(err) => {
+            tracing::error!("Unable to encode signing key: {}", err);
+            return None;
+        }
+    };
+
+    let point = secret.public_key().to_encoded_point(false);
+    Some(EcKeyPair {
+        private_pem,
+        x: URL_SAFE_NO_PAD.encode(point.x()?),
+        y: URL_SAFE_NO_PAD.encode(point.y()?),
+    })
+}
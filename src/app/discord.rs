@@ -1,17 +1,23 @@
+pub mod client;
+
 use super::extension::AccountExtension;
 use crate::routes::responses::DiscordUserGuildsResponse;
 use crate::{
     routes::{
-        platform::OAuthLoginValidationRequest,
-        responses::{DiscordUserResponse, DiscordValidationResponse},
+        platform::{OAuthLoginValidationRequest, OAuthRefreshRequest},
+        responses::{DiscordGuildMember, DiscordRole, DiscordUserResponse, DiscordValidationResponse},
     },
     sync,
     sync::discord::MemberSyncResult,
 };
-use levelcrush::{app::ApplicationState, axum::headers::AccessControlRequestMethod, env, tracing};
+use levelcrush::cache::{CacheDuration, CacheValue};
+use levelcrush::reqwest::StatusCode;
+use levelcrush::{app::ApplicationState, tracing};
+use std::collections::HashMap;
 
 pub async fn validate_oauth(
     oauth_code: &str,
+    code_verifier: &str,
     state: &ApplicationState<AccountExtension>,
 ) -> Option<DiscordValidationResponse> {
     let client_id = state.extension.discord_client_id.clone();
@@ -31,15 +37,14 @@ pub async fn validate_oauth(
                 code: oauth_code.to_string(),
                 redirect_uri: authorize_redirect.clone(),
                 scope: scopes,
+                code_verifier: code_verifier.to_string(),
             })
             .unwrap_or_default(),
         )
         .header("Content-Type", "application/x-www-form-urlencoded")
-        .header("Accept", "application/json")
-        .send()
-        .await;
+        .header("Accept", "application/json");
 
-    if let Ok(response) = request {
+    if let Some(response) = state.extension.discord_client.send(request).await {
         let json = response.json::<DiscordValidationResponse>().await;
         if let Ok(data) = json {
             Some(data)
@@ -53,6 +58,41 @@ pub async fn validate_oauth(
     }
 }
 
+/// trades in a refresh token for a new set of oauth tokens
+pub async fn refresh_oauth(
+    refresh_token: &str,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<DiscordValidationResponse> {
+    let request = state
+        .extension
+        .http_client
+        .post("https://discord.com/api/oauth2/token")
+        .body(
+            serde_urlencoded::to_string(OAuthRefreshRequest {
+                client_id: state.extension.discord_client_id.clone(),
+                client_secret: state.extension.discord_client_secret.clone(),
+                grant_type: "refresh_token".to_string(),
+                refresh_token: refresh_token.to_string(),
+            })
+            .unwrap_or_default(),
+        )
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json");
+
+    if let Some(response) = state.extension.discord_client.send(request).await {
+        let json = response.json::<DiscordValidationResponse>().await;
+        if let Ok(data) = json {
+            Some(data)
+        } else {
+            let err = json.err().unwrap();
+            tracing::error!("Could not parse oauth refresh response! {}", err);
+            None
+        }
+    } else {
+        None
+    }
+}
+
 /// queries a discord user directly by their discord id
 pub async fn member_api(discord_id: &str, state: &ApplicationState<AccountExtension>) -> Option<DiscordUserResponse> {
     let bot_token = state.extension.discord_bot_token.clone();
@@ -64,11 +104,9 @@ pub async fn member_api(discord_id: &str, state: &ApplicationState<AccountExtens
         .extension
         .http_client
         .get(&endpoint)
-        .header("Authorization", bot_auth)
-        .send()
-        .await;
+        .header("Authorization", bot_auth);
 
-    if let Ok(request) = request {
+    if let Some(request) = state.extension.discord_client.send(request).await {
         let json = request.json::<DiscordUserResponse>().await;
         if let Ok(json) = json {
             Some(json)
@@ -85,13 +123,123 @@ pub async fn member_api(discord_id: &str, state: &ApplicationState<AccountExtens
 /// fetches a discord member by their id and syncs the result
 pub async fn member(discord_id: &str, state: &ApplicationState<AccountExtension>) -> Option<MemberSyncResult> {
     let discord_response = member_api(discord_id, state).await;
-    if let Some(user_response) = discord_response {
-        sync::discord::member(user_response, &state).await
+    let sync_result = if let Some(user_response) = discord_response {
+        sync::discord::member(user_response, state).await
+    } else {
+        None
+    };
+
+    if sync_result.is_some() {
+        member_roles(discord_id, state).await;
+    }
+    sync_result
+}
+
+/// queries the roles defined in a guild with the bot token.
+/// Every member we sync needs these, so they are cached for a few minutes
+pub async fn guild_roles_api(guild_id: &str, state: &ApplicationState<AccountExtension>) -> Option<Vec<DiscordRole>> {
+    let cache_key = format!("guild_roles||{}", guild_id);
+    if let Some(roles) = state.extension.discord_roles.access(&cache_key).await {
+        return Some(roles);
+    }
+
+    let bot_auth = format!("Bot {}", state.extension.discord_bot_token);
+    let endpoint = format!("https://discord.com/api/v10/guilds/{}/roles", guild_id);
+    let request = state
+        .extension
+        .http_client
+        .get(&endpoint)
+        .header("Authorization", bot_auth);
+
+    let response = state.extension.discord_client.send(request).await?;
+    if !response.status().is_success() {
+        tracing::error!("Unable to fetch roles for guild {}: {}", guild_id, response.status());
+        return None;
+    }
+
+    let json = response.json::<Vec<DiscordRole>>().await;
+    if let Ok(roles) = json {
+        // the cache shares its storage between clones, so this still lands in the extension cache
+        let mut roles_cache = state.extension.discord_roles.clone();
+        roles_cache
+            .write(
+                cache_key,
+                CacheValue::with_duration(roles.clone(), CacheDuration::FiveMinutes, CacheDuration::FiveMinutes),
+            )
+            .await;
+        Some(roles)
     } else {
+        let err = json.err().unwrap();
+        tracing::error!("Error occurred while parsing guild roles response:\r\n{}", err);
         None
     }
 }
 
+/// queries the member object of a discord user inside a guild with the bot token.
+/// Users that are not in the guild come back as a default member without a `joined_at`
+pub async fn guild_member_api(
+    guild_id: &str,
+    discord_id: &str,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<DiscordGuildMember> {
+    let bot_auth = format!("Bot {}", state.extension.discord_bot_token);
+    let endpoint = format!("https://discord.com/api/v10/guilds/{}/members/{}", guild_id, discord_id);
+    let request = state
+        .extension
+        .http_client
+        .get(&endpoint)
+        .header("Authorization", bot_auth);
+
+    let response = state.extension.discord_client.send(request).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Some(DiscordGuildMember::default());
+    } else if !response.status().is_success() {
+        tracing::error!(
+            "Unable to fetch member {} in guild {}: {}",
+            discord_id,
+            guild_id,
+            response.status()
+        );
+        return None;
+    }
+
+    let json = response.json::<DiscordGuildMember>().await;
+    if let Ok(member) = json {
+        Some(member)
+    } else {
+        let err = json.err().unwrap();
+        tracing::error!("Error occurred while parsing guild member response:\r\n{}", err);
+        None
+    }
+}
+
+/// Fetches the roles the discord user has in each of our allowed guilds and stores them on their discord platform.
+/// Guilds the user is not a member of are left out. If discord fails us on any guild nothing is written,
+/// so a bad response never wipes out roles we already know about
+pub async fn member_roles(
+    discord_id: &str,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<HashMap<String, Vec<DiscordRole>>> {
+    let mut guild_roles = HashMap::new();
+    for guild_id in state.extension.allowed_discords.iter().filter(|id| !id.is_empty()) {
+        let member = guild_member_api(guild_id, discord_id, state).await?;
+        if member.joined_at.is_empty() {
+            continue;
+        }
+
+        let roles = guild_roles_api(guild_id, state)
+            .await?
+            .into_iter()
+            .filter(|role| member.roles.contains(&role.id.clone().unwrap_or_default()))
+            .collect::<Vec<DiscordRole>>();
+
+        guild_roles.insert(guild_id.clone(), roles);
+    }
+
+    sync::discord::roles(discord_id, &guild_roles, state).await;
+    Some(guild_roles)
+}
+
 /// query discord api with oauth authentication
 pub async fn member_oauth_api(
     access_token: &str,
@@ -101,11 +249,9 @@ pub async fn member_oauth_api(
         .extension
         .http_client
         .get("https://discord.com/api/v10/users/@me")
-        .bearer_auth(access_token)
-        .send()
-        .await;
+        .bearer_auth(access_token);
 
-    if let Ok(result) = request {
+    if let Some(result) = state.extension.discord_client.send(request).await {
         let json = result.json::<DiscordUserResponse>().await;
         if let Ok(data) = json {
             Some(data)
@@ -119,31 +265,29 @@ pub async fn member_oauth_api(
     }
 }
 
-/// Query the discord api directly and get the currently logged in users guild list
+/// Query the discord api directly and get the currently logged in users guild list. None when discord could not tell us
 pub async fn member_oauth_guilds_api(
     access_token: &str,
     state: &ApplicationState<AccountExtension>,
-) -> DiscordUserGuildsResponse {
+) -> Option<DiscordUserGuildsResponse> {
     let request = state
         .extension
         .http_client
         .get("https://discord.com/api/v10/users/@me/guilds")
-        .bearer_auth(access_token)
-        .send()
-        .await;
+        .bearer_auth(access_token);
 
-    if let Ok(response) = request {
+    if let Some(response) = state.extension.discord_client.send(request).await {
         let raw_json = response.text().await.unwrap_or_default();
         let json = serde_json::from_str(&raw_json);
         if let Ok(data) = json {
-            data
+            Some(data)
         } else {
             let err = json.err().unwrap();
             tracing::error!("Failed to parse incoming json for User Guild request {err:?}\r\n{raw_json}");
-            Vec::new()
+            None
         }
     } else {
-        Vec::new()
+        None
     }
 }
 
@@ -152,7 +296,17 @@ pub async fn member_oauth_guilds_api(
 pub async fn member_oauth(access_token: &str, state: &ApplicationState<AccountExtension>) -> Option<MemberSyncResult> {
     let oauth_response = member_oauth_api(access_token, state).await;
     if let Some(user_response) = oauth_response {
-        sync::discord::member(user_response, &state).await
+        let discord_id = user_response.id.clone().unwrap_or_default();
+        let sync_result = sync::discord::member(user_response, state).await;
+        if sync_result.is_some() {
+            member_roles(&discord_id, state).await;
+
+            // a failed lookup keeps the guilds we already know about instead of wiping them out
+            if let Some(guilds) = member_oauth_guilds_api(access_token, state).await {
+                sync::discord::oauth_guilds(&discord_id, &guilds, state).await;
+            }
+        }
+        sync_result
     } else {
         None
     }
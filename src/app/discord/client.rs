@@ -0,0 +1,331 @@
+use crate::app::crypto;
+use levelcrush::reqwest::{self, header, header::HeaderMap, Request, RequestBuilder, Response, StatusCode};
+use levelcrush::tokio::sync::Mutex;
+use levelcrush::tokio::time::{sleep, Duration, Instant};
+use levelcrush::tracing;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// how many times a request is retried after being rate limited before giving up
+const MAX_RETRIES: usize = 5;
+
+/// the longest we wait on a rate limit. Requests are made while a browser is waiting on us, so anything longer is given up on
+const MAX_WAIT: Duration = Duration::from_secs(5);
+
+/// discord allows 50 requests per second across all routes for a bot
+/// https://discord.com/developers/docs/topics/rate-limits#global-rate-limit
+const GLOBAL_REQUESTS_PER_SECOND: u32 = 50;
+
+/// path segments that are "major parameters". Discord keeps separate buckets per id for these
+const MAJOR_PARAMETERS: [&str; 3] = ["channels", "guilds", "webhooks"];
+
+#[derive(serde::Deserialize, Default, Debug)]
+struct DiscordRateLimitResponse {
+    #[serde(default)]
+    retry_after: f64,
+
+    #[serde(default)]
+    global: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct DiscordBucket {
+    remaining: u64,
+    reset: Instant,
+}
+
+/// Who a request counts against. Discord limits the bot and every user token on their own
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum DiscordLimitScope {
+    Bot,
+    /// hash of the oauth token the request is made with, or of the body of a token exchange
+    Token(String),
+}
+
+/// The buckets discord told us about for one scope
+#[derive(Debug, Default)]
+struct DiscordScopeLimits {
+    /// route key => bucket hash returned by discord
+    routes: HashMap<String, String>,
+    /// bucket hash and major parameter => bucket
+    buckets: HashMap<String, DiscordBucket>,
+    global_reset: Option<Instant>,
+}
+
+impl DiscordScopeLimits {
+    /// anything still limiting requests. Token scopes without any are dropped so they do not pile up
+    fn is_limited(&self, now: Instant) -> bool {
+        self.global_reset.is_some_and(|reset| reset > now) || self.buckets.values().any(|bucket| bucket.reset > now)
+    }
+}
+
+#[derive(Debug)]
+struct DiscordRateLimits {
+    bot: DiscordScopeLimits,
+    tokens: HashMap<String, DiscordScopeLimits>,
+    /// our own window for the global limit of the bot
+    window_start: Instant,
+    window_count: u32,
+}
+
+impl Default for DiscordRateLimits {
+    fn default() -> Self {
+        DiscordRateLimits {
+            bot: DiscordScopeLimits::default(),
+            tokens: HashMap::new(),
+            window_start: Instant::now(),
+            window_count: 0,
+        }
+    }
+}
+
+impl DiscordRateLimits {
+    fn scope(&mut self, scope: &DiscordLimitScope) -> &mut DiscordScopeLimits {
+        match scope {
+            DiscordLimitScope::Bot => &mut self.bot,
+            DiscordLimitScope::Token(token) => self.tokens.entry(token.clone()).or_default(),
+        }
+    }
+}
+
+/// Http client for the discord api that respects the rate limits discord hands back.
+///
+/// Per route buckets are tracked through the `X-RateLimit-*` headers and the global limit is tracked locally
+/// and through any global 429 we receive. Rate limited requests are retried after `retry_after`.
+/// Requests made on behalf of a user with their oauth token are limited per token and stay out of the global window of the bot
+#[derive(Clone, Debug, Default)]
+pub struct DiscordClient {
+    http_client: reqwest::Client,
+    limits: Arc<Mutex<DiscordRateLimits>>,
+}
+
+impl DiscordClient {
+    pub fn new(http_client: reqwest::Client) -> DiscordClient {
+        DiscordClient {
+            http_client,
+            limits: Arc::new(Mutex::new(DiscordRateLimits::default())),
+        }
+    }
+
+    /// Sends the request, waiting on any known rate limits first and retrying when discord rate limits us anyway
+    pub async fn send(&self, request: RequestBuilder) -> Option<Response> {
+        let request = match request.build() {
+            Ok(request) => request,
+            Err(err) => {
+                tracing::error!("Unable to build discord request: {}", err);
+                return None;
+            }
+        };
+
+        let route = route_key(request.method().as_str(), request.url().path());
+        let major = major_parameter(request.url().path());
+        let scope = limit_scope(&request);
+        for attempt in 0..=MAX_RETRIES {
+            if !self.wait(&scope, &route, &major).await {
+                return None;
+            }
+
+            let response = match request.try_clone() {
+                Some(attempt_request) => self.http_client.execute(attempt_request).await,
+                None => {
+                    tracing::error!("Unable to clone discord request for {}", route);
+                    return None;
+                }
+            };
+
+            let response = match response {
+                Ok(response) => response,
+                Err(err) => {
+                    tracing::error!("Request Error: {}", err);
+                    return None;
+                }
+            };
+
+            self.update(&scope, &route, &major, response.headers()).await;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                return Some(response);
+            }
+
+            let is_global_header = response.headers().get("X-RateLimit-Global").is_some();
+            let retry_after_header = header_f64(response.headers(), "Retry-After").unwrap_or(1.0);
+            let body = response.json::<DiscordRateLimitResponse>().await.unwrap_or_default();
+            let retry_after = if body.retry_after > 0.0 {
+                body.retry_after
+            } else {
+                retry_after_header
+            };
+            let retry_after = Duration::try_from_secs_f64(retry_after).unwrap_or(Duration::MAX);
+
+            if is_global_header || body.global {
+                let mut limits = self.limits.lock().await;
+                limits.scope(&scope).global_reset = Some(Instant::now() + retry_after);
+            }
+
+            if retry_after > MAX_WAIT {
+                tracing::error!("Rate limited by discord on {} for {:?}, giving up", route, retry_after);
+                return None;
+            }
+
+            tracing::warn!(
+                "Rate limited by discord on {} (attempt {}). Retrying after {:?}",
+                route,
+                attempt + 1,
+                retry_after
+            );
+            sleep(retry_after).await;
+        }
+
+        tracing::error!("Gave up on {} after {} retries", route, MAX_RETRIES);
+        None
+    }
+
+    /// sleeps until the global limit and the bucket tied to this route allow another request.
+    /// Returns false without waiting when that would take longer than `MAX_WAIT`
+    async fn wait(&self, scope: &DiscordLimitScope, route: &str, major: &str) -> bool {
+        let delay = {
+            let mut limits = self.limits.lock().await;
+            let now = Instant::now();
+            let mut delay = Duration::ZERO;
+
+            // our own sliding window for the global limit, discord does not tell us about it until we hit it
+            if *scope == DiscordLimitScope::Bot {
+                if now.duration_since(limits.window_start) >= Duration::from_secs(1) {
+                    limits.window_start = now;
+                    limits.window_count = 0;
+                }
+                if limits.window_count >= GLOBAL_REQUESTS_PER_SECOND {
+                    let window_end = limits.window_start + Duration::from_secs(1);
+                    delay = delay.max(window_end.saturating_duration_since(now));
+                    limits.window_start = window_end;
+                    limits.window_count = 0;
+                }
+                limits.window_count += 1;
+            }
+
+            let scope_limits = limits.scope(scope);
+            if let Some(global_reset) = scope_limits.global_reset {
+                if global_reset > now {
+                    delay = delay.max(global_reset - now);
+                } else {
+                    scope_limits.global_reset = None;
+                }
+            }
+
+            if let Some(bucket_hash) = scope_limits.routes.get(route).cloned() {
+                // once a bucket has reset, the next response will tell us the real remaining count
+                let bucket_id = bucket_key(&bucket_hash, major);
+                if let Some(bucket) = scope_limits.buckets.get_mut(&bucket_id).filter(|bucket| bucket.reset > now) {
+                    if bucket.remaining == 0 {
+                        delay = delay.max(bucket.reset - now);
+                    } else {
+                        // reserve our spot so concurrent requests do not all assume there is room
+                        bucket.remaining -= 1;
+                    }
+                }
+            }
+
+            delay
+        };
+
+        if delay > MAX_WAIT {
+            tracing::error!("Discord rate limit for {} resets in {:?}, giving up", route, delay);
+            return false;
+        }
+
+        if !delay.is_zero() {
+            tracing::info!("Waiting {:?} on discord rate limit for {}", delay, route);
+            sleep(delay).await;
+        }
+        true
+    }
+
+    /// records the rate limit information discord returned for this route
+    async fn update(&self, scope: &DiscordLimitScope, route: &str, major: &str, headers: &HeaderMap) {
+        let mut limits = self.limits.lock().await;
+        let now = Instant::now();
+
+        if let Some(bucket_hash) = headers.get("X-RateLimit-Bucket").and_then(|v| v.to_str().ok()) {
+            let remaining = header_f64(headers, "X-RateLimit-Remaining").unwrap_or(0.0) as u64;
+            let reset_after = header_f64(headers, "X-RateLimit-Reset-After").unwrap_or(0.0);
+
+            let scope_limits = limits.scope(scope);
+            scope_limits.routes.insert(route.to_string(), bucket_hash.to_string());
+            scope_limits.buckets.insert(
+                bucket_key(bucket_hash, major),
+                DiscordBucket {
+                    remaining,
+                    reset: now + Duration::from_secs_f64(reset_after.max(0.0)),
+                },
+            );
+        }
+
+        limits.tokens.retain(|_, token_limits| token_limits.is_limited(now));
+    }
+}
+
+/// Requests with a user oauth token, and the exchanges that hand those tokens out, are limited per token instead of against the bot.
+/// Exchanges carry the code or refresh token in their body, so that is what they are told apart by
+fn limit_scope(request: &Request) -> DiscordLimitScope {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if let Some(bearer) = bearer {
+        return DiscordLimitScope::Token(crypto::hash_secret(bearer));
+    }
+
+    if request.url().path().ends_with("/oauth2/token") {
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|body| String::from_utf8_lossy(body).to_string())
+            .unwrap_or_default();
+        return DiscordLimitScope::Token(crypto::hash_secret(&body));
+    }
+
+    DiscordLimitScope::Bot
+}
+
+/// Discord shares a bucket hash between routes, but still counts every major parameter on its own
+fn bucket_key(bucket_hash: &str, major: &str) -> String {
+    format!("{}:{}", bucket_hash, major)
+}
+
+fn header_f64(headers: &HeaderMap, name: &str) -> Option<f64> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<f64>().ok())
+}
+
+/// The major parameter in the path, like `guilds/123`. Empty when there is none
+fn major_parameter(path: &str) -> String {
+    let segments = path.split('/').collect::<Vec<&str>>();
+    segments
+        .windows(2)
+        .find(|pair| MAJOR_PARAMETERS.contains(&pair[0]) && !pair[1].is_empty())
+        .map(|pair| pair.join("/"))
+        .unwrap_or_default()
+}
+
+/// Discord groups rate limits by route. Ids are stripped out of the path unless they belong to a major parameter
+/// so `/users/123` and `/users/456` share a route while `/guilds/1/members` and `/guilds/2/members` do not
+fn route_key(method: &str, path: &str) -> String {
+    let mut previous = "";
+    let segments = path
+        .split('/')
+        .map(|segment| {
+            let is_id = !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit());
+            let key = if is_id && !MAJOR_PARAMETERS.contains(&previous) {
+                ":id"
+            } else {
+                segment
+            };
+            previous = segment;
+            key
+        })
+        .collect::<Vec<&str>>();
+
+    format!("{} {}", method, segments.join("/"))
+}
@@ -1,5 +1,14 @@
 use crate::{
-    database::account::AccountLinkedPlatformsResult, routes::profile::ProfileView, sync::discord::MemberSyncResult,
+    app::{
+        discord::client::DiscordClient,
+        oidc::{AccessGrant, AuthorizationGrant},
+        policy::AccessRule,
+        rate_limit::{RateLimit, RateLimitGroup, RateLimiter},
+        redirect::RedirectPolicy,
+    },
+    database::account::{Account, AccountLinkedPlatformsResult},
+    database::signing_key::SigningKey, routes::profile::ProfileView, routes::responses::DiscordRole,
+    sync::discord::MemberSyncResult,
 };
 use levelcrush::{
     alias::UnixTimestamp,
@@ -16,18 +25,28 @@ use levelcrush::{
     tracing,
     uuid::Uuid,
 };
+use std::collections::HashMap;
 use std::sync::Arc;
 
 #[derive(Clone, Debug, Default)]
 pub struct AccountExtension {
     pub http_client: reqwest::Client,
+    pub discord_client: DiscordClient,
     pub profiles: MemoryCache<ProfileView>,
     pub mass_searches: MemoryCache<Vec<AccountLinkedPlatformsResult>>,
     pub searches: MemoryCache<AccountLinkedPlatformsResult>,
     pub challenges: MemoryCache<ProfileView>,
     pub link_gens: MemoryCache<MemberSyncResult>,
+    pub discord_roles: MemoryCache<Vec<DiscordRole>>,
+    pub merge_codes: MemoryCache<Account>,
+    pub oauth_codes: MemoryCache<AuthorizationGrant>,
+    pub oauth_tokens: MemoryCache<AccessGrant>,
+    pub signing_keys: MemoryCache<Vec<SigningKey>>,
     pub guard: RetryLock,
     pub allowed_discords: Vec<String>,
+    pub access_policy: AccessRule,
+    pub redirect_policy: RedirectPolicy,
+    pub rate_limiter: RateLimiter,
     pub discord_client_id: String,
     pub discord_client_secret: String,
     pub discord_validate_url: String,
@@ -38,11 +57,19 @@ pub struct AccountExtension {
     pub twitch_client_id: String,
     pub twitch_client_secret: String,
     pub twitch_validate_url: String,
+    pub steam_api_key: String,
+    pub steam_validate_url: String,
+    pub battlenet_client_id: String,
+    pub battlenet_client_secret: String,
+    pub battlenet_validate_url: String,
+    pub battlenet_region: String,
     pub server_port: u16,
     pub server_secret: String,
     pub server_host: String,
+    /// how many proxies sit in front of the server and append to X-Forwarded-For
+    pub trusted_proxies: usize,
     pub fallback_url: String,
-    pub account_key: String,
+    pub token_key: String,
 }
 
 impl AccountExtension {
@@ -55,6 +82,7 @@ impl AccountExtension {
             .expect("Failed to initialize TLS or get system configuration");
 
         AccountExtension {
+            discord_client: DiscordClient::new(http_client.clone()),
             http_client,
             ..Default::default()
         }
@@ -100,13 +128,21 @@ impl AccountExtension {
             .unwrap_or_default()
             .parse::<u16>()
             .unwrap_or(3001);
+        let trusted_proxies = app_settings
+            .get_global("server.trusted_proxies")
+            .unwrap_or_default()
+            .parse::<usize>()
+            .unwrap_or(0);
 
         let discord_client_id = app_settings.get_global("discord.client_id").unwrap_or_default();
         let discord_client_secret = app_settings.get_global("discord.client_secret").unwrap_or_default();
         let discord_oauth_validate = app_settings.get_global("discord.validate_url").unwrap_or_default();
         let allowed_discords = app_settings.get_global("discord.server_list").unwrap_or_default();
+        let access_policy = app_settings.get_global("account.access_policy").unwrap_or_default();
 
         let fallback_url = app_settings.get_global("server.fallback_url").unwrap_or_default();
+        let redirect_origins = app_settings.get_global("server.redirect_origins").unwrap_or_default();
+        let redirect_paths = app_settings.get_global("server.redirect_paths").unwrap_or_default();
 
         let bungie_id = app_settings.get_global("bungie.client_id").unwrap_or_default();
         let bungie_client_secret = app_settings.get_global("bungie.client_secret").unwrap_or_default();
@@ -117,15 +153,47 @@ impl AccountExtension {
         let twitch_client_secret = app_settings.get_global("twitch.client_secret").unwrap_or_default();
         let twitch_validate_url = app_settings.get_global("twitch.validate_url").unwrap_or_default();
 
+        let steam_api_key = app_settings.get_global("steam.api_key").unwrap_or_default();
+        let steam_validate_url = app_settings.get_global("steam.validate_url").unwrap_or_default();
+
+        let battlenet_client_id = app_settings.get_global("battlenet.client_id").unwrap_or_default();
+        let battlenet_client_secret = app_settings.get_global("battlenet.client_secret").unwrap_or_default();
+        let battlenet_validate_url = app_settings.get_global("battlenet.validate_url").unwrap_or_default();
+        let battlenet_region = app_settings
+            .get_global("battlenet.region")
+            .unwrap_or_else(|| "us".to_string());
+
         let server_host = app_settings.get_global("server.host").unwrap_or_default();
 
-        let account_key = app_settings.get_global("account.key").unwrap_or_default();
+        let token_key = app_settings.get_global("account.token_key").unwrap_or_default();
+
+        // requests/seconds per route group, see `RateLimit`
+        let mut rate_limits = HashMap::new();
+        let mut rate_limit_settings = Vec::new();
+        for group in RateLimitGroup::ALL.into_iter() {
+            let setting = format!("rate_limit.{}", group);
+            let mut value = app_settings.get_global(&setting).unwrap_or_default();
+            let limit = if value.trim().is_empty() {
+                value = group.default_limit().to_string();
+                group.default_limit()
+            } else {
+                RateLimit::parse(&value).unwrap_or_else(|| {
+                    tracing::error!("Invalid {}, expected requests/seconds: {}", setting, value);
+                    group.default_limit()
+                })
+            };
+            rate_limits.insert(group, limit);
+            rate_limit_settings.push((setting, value));
+        }
+
         // save settings back in. This makes sure they exist
 
         let sp_setting = server_port.to_string();
-        let handles = vec![
+        let tp_setting = trusted_proxies.to_string();
+        let mut handles = vec![
             app_settings.set_global("server.port", &sp_setting).await?,
             app_settings.set_global("server.secret", &server_secret).await?,
+            app_settings.set_global("server.trusted_proxies", &tp_setting).await?,
             app_settings.set_global("discord.client_id", &discord_client_id).await?,
             app_settings
                 .set_global("discord.client_secret", &discord_client_secret)
@@ -134,7 +202,11 @@ impl AccountExtension {
                 .set_global("discord.validate_url", &discord_oauth_validate)
                 .await?,
             app_settings.set_global("server.fallback_url", &fallback_url).await?,
-            app_settings.set_global("account.key", &account_key).await?,
+            app_settings
+                .set_global("server.redirect_origins", &redirect_origins)
+                .await?,
+            app_settings.set_global("server.redirect_paths", &redirect_paths).await?,
+            app_settings.set_global("account.token_key", &token_key).await?,
             app_settings.set_global("server.host", &server_host).await?,
             app_settings.set_global("bungie.client_id", &bungie_id).await?,
             app_settings
@@ -151,7 +223,25 @@ impl AccountExtension {
             app_settings
                 .set_global("discord.server_list", &allowed_discords)
                 .await?,
+            app_settings.set_global("account.access_policy", &access_policy).await?,
+            app_settings.set_global("steam.api_key", &steam_api_key).await?,
+            app_settings
+                .set_global("steam.validate_url", &steam_validate_url)
+                .await?,
+            app_settings
+                .set_global("battlenet.client_id", &battlenet_client_id)
+                .await?,
+            app_settings
+                .set_global("battlenet.client_secret", &battlenet_client_secret)
+                .await?,
+            app_settings
+                .set_global("battlenet.validate_url", &battlenet_validate_url)
+                .await?,
+            app_settings.set_global("battlenet.region", &battlenet_region).await?,
         ];
+        for (setting, value) in rate_limit_settings.iter() {
+            handles.push(app_settings.set_global(setting, value).await?);
+        }
 
         // set inside the extension
         app_state.extension.discord_client_id = discord_client_id;
@@ -159,8 +249,12 @@ impl AccountExtension {
         app_state.extension.discord_validate_url = discord_oauth_validate;
         app_state.extension.server_port = server_port;
         app_state.extension.server_secret = server_secret;
+        app_state.extension.trusted_proxies = trusted_proxies;
+        app_state.extension.redirect_policy =
+            RedirectPolicy::from_settings(&redirect_origins, &redirect_paths, &fallback_url);
         app_state.extension.fallback_url = fallback_url;
-        app_state.extension.account_key = account_key;
+        app_state.extension.rate_limiter = RateLimiter::new(rate_limits);
+        app_state.extension.token_key = token_key;
         app_state.extension.server_host = server_host;
         app_state.extension.bungie_client_id = bungie_id;
         app_state.extension.bungie_client_secret = bungie_client_secret;
@@ -168,11 +262,30 @@ impl AccountExtension {
         app_state.extension.twitch_client_id = twitch_client_id;
         app_state.extension.twitch_client_secret = twitch_client_secret;
         app_state.extension.twitch_validate_url = twitch_validate_url;
+        app_state.extension.steam_api_key = steam_api_key;
+        app_state.extension.steam_validate_url = steam_validate_url;
+        app_state.extension.battlenet_client_id = battlenet_client_id;
+        app_state.extension.battlenet_client_secret = battlenet_client_secret;
+        app_state.extension.battlenet_validate_url = battlenet_validate_url;
+        app_state.extension.battlenet_region = battlenet_region;
         app_state.extension.allowed_discords = allowed_discords
             .split(',')
             .map(|v| v.to_string())
             .collect::<Vec<String>>();
 
+        // without a configured policy we fall back to membership in any of the allowed discords
+        app_state.extension.access_policy = if access_policy.trim().is_empty() {
+            AccessRule::from_guilds(&app_state.extension.allowed_discords)
+        } else {
+            match serde_json::from_str::<AccessRule>(&access_policy) {
+                Ok(rule) => rule,
+                Err(err) => {
+                    tracing::error!("Invalid account.access_policy, nobody will be allowed in: {}", err);
+                    AccessRule::default()
+                }
+            }
+        };
+
         // wait on all handles to finish
         levelcrush::futures::future::join_all(handles).await;
 
@@ -0,0 +1,145 @@
+use crate::app::extension::AccountExtension;
+use crate::database;
+use crate::database::account::Account;
+use crate::database::signing_key::{SigningKey, SIGNING_ALGORITHM};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use levelcrush::app::ApplicationState;
+use levelcrush::cache::{CacheDuration, CacheValue};
+use levelcrush::tracing;
+use levelcrush::util::unix_timestamp;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// how long an access token minted from a session is good for, in seconds
+pub const SESSION_TOKEN_LIFETIME: i64 = 900;
+
+/// the `aud` of access tokens minted from a session. Consumers must reject tokens with any other audience,
+/// which keeps id tokens (whose audience is a client id) from being passed off as access tokens
+pub const SESSION_TOKEN_AUDIENCE: &str = "levelcrush:session";
+
+const CACHE_KEY_SIGNING_KEYS: &str = "signing_keys";
+
+/// What downstream services learn about the account without calling back to us
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct AccessTokenClaims {
+    pub iss: String,
+    /// always `SESSION_TOKEN_AUDIENCE`
+    pub aud: String,
+    /// the account subject, which stays the same when the account token is rotated
+    pub sub: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub admin: bool,
+    /// platform name => the id of the user on that platform
+    pub platforms: HashMap<String, String>,
+}
+
+/// A public key in the form described by https://datatracker.ietf.org/doc/html/rfc7517
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub key_use: String,
+    pub kid: String,
+    pub x: String,
+    pub y: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+impl From<&SigningKey> for Jwk {
+    fn from(key: &SigningKey) -> Self {
+        Jwk {
+            kty: "EC".to_string(),
+            crv: "P-256".to_string(),
+            alg: key.algorithm.clone(),
+            key_use: "sig".to_string(),
+            kid: key.kid.clone(),
+            x: key.public_x.clone(),
+            y: key.public_y.clone(),
+        }
+    }
+}
+
+/// The published signing keys. Kept in memory for a minute so a rotation done by another process is picked up quickly
+pub async fn keys(state: &ApplicationState<AccountExtension>) -> Vec<SigningKey> {
+    if let Some(keys) = state.extension.signing_keys.access(CACHE_KEY_SIGNING_KEYS).await {
+        return keys;
+    }
+
+    let keys = database::signing_key::published(state).await;
+    let mut keys_cache = state.extension.signing_keys.clone();
+    keys_cache
+        .write(
+            CACHE_KEY_SIGNING_KEYS.to_string(),
+            CacheValue::with_duration(keys.clone(), CacheDuration::Minute, CacheDuration::Minute),
+        )
+        .await;
+    keys
+}
+
+pub async fn jwks(state: &ApplicationState<AccountExtension>) -> JwkSet {
+    JwkSet {
+        keys: keys(state).await.iter().map(Jwk::from).collect(),
+    }
+}
+
+/// Signs the claims with the active key. The key id is set in the header so consumers can find the key in the jwks
+pub async fn sign<T: Serialize>(claims: &T, state: &ApplicationState<AccountExtension>) -> Option<String> {
+    let keys = keys(state).await;
+    let key = match keys.iter().find(|key| key.retired_at == 0 && key.algorithm == SIGNING_ALGORITHM) {
+        Some(key) => key,
+        None => {
+            tracing::error!("There is no active signing key. Run the signing key rotation job");
+            return None;
+        }
+    };
+
+    let private_key = database::signing_key::private_key(key, state)?;
+    let encoding_key = match EncodingKey::from_ec_pem(private_key.as_bytes()) {
+        Ok(encoding_key) => encoding_key,
+        Err(err) => {
+            tracing::error!("Unable to load signing key {}: {}", key.kid, err);
+            return None;
+        }
+    };
+
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some(key.kid.clone());
+
+    match jsonwebtoken::encode(&header, claims, &encoding_key) {
+        Ok(token) => Some(token),
+        Err(err) => {
+            tracing::error!("Unable to sign token: {}", err);
+            None
+        }
+    }
+}
+
+/// Issues a short lived access token for the account. Returns the token and when it expires
+pub async fn access_token(account: &Account, state: &ApplicationState<AccountExtension>) -> Option<(String, i64)> {
+    let platforms = database::platform::all_from_account(account, state)
+        .await
+        .into_iter()
+        .map(|platform| (platform.platform, platform.platform_user))
+        .collect::<HashMap<String, String>>();
+
+    let now = unix_timestamp();
+    let claims = AccessTokenClaims {
+        iss: state.extension.server_host.clone(),
+        aud: SESSION_TOKEN_AUDIENCE.to_string(),
+        sub: account.subject.clone(),
+        iat: now,
+        exp: now + SESSION_TOKEN_LIFETIME,
+        admin: account.admin == 1,
+        platforms,
+    };
+
+    let token = sign(&claims, state).await?;
+    Some((token, claims.exp))
+}
@@ -0,0 +1,144 @@
+use crate::app::extension::AccountExtension;
+use crate::app::{crypto, jwt};
+use crate::database::account::Account;
+use crate::routes::profile::ProfileView;
+use crate::routes::responses::DiscordRole;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use std::collections::HashMap;
+
+/// how long an access token handed to a client is good for, in seconds. Matches the cache duration of `oauth_tokens`
+pub const OAUTH_ACCESS_TOKEN_LIFETIME: i64 = 600;
+
+/// how long an id token is good for, in seconds
+pub const ID_TOKEN_LIFETIME: i64 = 600;
+
+pub const SCOPE_OPENID: &str = "openid";
+
+/// display name and admin flag
+pub const SCOPE_PROFILE: &str = "profile";
+
+/// linked platform data and discord roles
+pub const SCOPE_PLATFORMS: &str = "platforms";
+
+pub const SUPPORTED_SCOPES: [&str; 3] = [SCOPE_OPENID, SCOPE_PROFILE, SCOPE_PLATFORMS];
+
+/// What a client was granted through `/oauth/authorize`, kept until the code is traded in at `/oauth/token`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct AuthorizationGrant {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub account_token: String,
+    pub scopes: Vec<String>,
+    pub nonce: Option<String>,
+    /// S256 challenge, empty when the client did not use pkce
+    pub code_challenge: String,
+    pub auth_time: i64,
+}
+
+impl AuthorizationGrant {
+    /// the verifier has to match the challenge sent to `/oauth/authorize`. Clients that did not send one cannot send a verifier either
+    pub fn verify_pkce(&self, code_verifier: &str) -> bool {
+        if self.code_challenge.is_empty() {
+            code_verifier.is_empty()
+        } else {
+            crypto::constant_eq(&crypto::pkce_challenge(code_verifier), &self.code_challenge)
+        }
+    }
+}
+
+/// What an access token issued at `/oauth/token` lets the client read from `/oauth/userinfo`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct AccessGrant {
+    pub client_id: String,
+    pub account_token: String,
+    pub scopes: Vec<String>,
+}
+
+/// Claims about the account. Only the claims covered by the granted scopes are filled in
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct UserInfo {
+    /// the account subject, which stays the same when the account token is rotated
+    pub sub: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_admin: Option<bool>,
+
+    /// platform name => platform data, the same shape as `ProfileView::platforms`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platforms: Option<HashMap<String, HashMap<String, String>>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discord_roles: Option<HashMap<String, Vec<DiscordRole>>>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub aud: String,
+    pub exp: i64,
+    pub iat: i64,
+    pub auth_time: i64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+
+    #[serde(flatten)]
+    pub user: UserInfo,
+}
+
+/// Only keeps the scopes we know about. The order is kept so the scope we hand back matches what was asked for
+pub fn parse_scopes(scope: &str) -> Vec<String> {
+    let mut scopes = Vec::new();
+    for scope in scope.split_whitespace() {
+        if SUPPORTED_SCOPES.contains(&scope) && !scopes.iter().any(|s| s == scope) {
+            scopes.push(scope.to_string());
+        }
+    }
+    scopes
+}
+
+/// Builds the claims for the account out of its profile
+pub fn user_info(account: &Account, profile: &ProfileView, scopes: &[String]) -> UserInfo {
+    let has_scope = |scope: &str| scopes.iter().any(|s| s == scope);
+
+    let mut user = UserInfo {
+        sub: account.subject.clone(),
+        ..Default::default()
+    };
+
+    if has_scope(SCOPE_PROFILE) {
+        user.name = Some(profile.display_name.clone());
+        user.is_admin = Some(profile.is_admin);
+    }
+
+    if has_scope(SCOPE_PLATFORMS) {
+        user.platforms = Some(profile.platforms.clone());
+        user.discord_roles = Some(profile.discord_roles.clone());
+    }
+
+    user
+}
+
+/// Signs an id token for the client with the active signing key
+pub async fn id_token(
+    grant: &AuthorizationGrant,
+    user: UserInfo,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<String> {
+    let now = unix_timestamp();
+    let claims = IdTokenClaims {
+        iss: state.extension.server_host.clone(),
+        aud: grant.client_id.clone(),
+        exp: now + ID_TOKEN_LIFETIME,
+        iat: now,
+        auth_time: grant.auth_time,
+        nonce: grant.nonce.clone(),
+        user,
+    };
+
+    jwt::sign(&claims, state).await
+}
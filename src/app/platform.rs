@@ -0,0 +1,177 @@
+use crate::app::{bungie, crypto, twitch};
+use crate::app::extension::AccountExtension;
+use crate::app::session::SessionKey;
+use crate::database::platform::{AccountPlatform, AccountPlatformType};
+use crate::database::platform_data::NewAccountPlatformData;
+use crate::{app, database};
+use axum_sessions::async_session::Session;
+use levelcrush::app::ApplicationState;
+use levelcrush::{axum_sessions, tracing};
+use levelcrush::util::unix_timestamp;
+
+/// how long the user has to complete an authorization before the state is no longer accepted
+pub const OAUTH_STATE_LIFETIME: i64 = 600;
+
+/// bytes of randomness behind the state and the PKCE code verifier.
+/// 32 bytes encodes to a 43 character verifier, the minimum length allowed by RFC 7636
+const OAUTH_RANDOM_BYTES: usize = 32;
+
+/// Everything we need to remember between sending the user off to a platform and the platform sending them back
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct PlatformOAuthState {
+    pub state: String,
+    pub code_verifier: String,
+    pub expires_at: i64,
+}
+
+impl PlatformOAuthState {
+    /// Creates a random state and PKCE code verifier that expire after `OAUTH_STATE_LIFETIME`
+    pub fn new() -> PlatformOAuthState {
+        PlatformOAuthState {
+            state: crypto::random_token(OAUTH_RANDOM_BYTES),
+            code_verifier: crypto::random_token(OAUTH_RANDOM_BYTES),
+            expires_at: unix_timestamp() + OAUTH_STATE_LIFETIME,
+        }
+    }
+
+    /// S256 code challenge sent along with the authorize url
+    pub fn code_challenge(&self) -> String {
+        crypto::pkce_challenge(&self.code_verifier)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= unix_timestamp()
+    }
+
+    /// Removes the oauth state from the session so it can only ever be used once.
+    /// Expired states are treated as if they were never there
+    pub fn take(key: SessionKey, session: &mut Session) -> Option<PlatformOAuthState> {
+        let oauth_state = app::session::read::<PlatformOAuthState>(key, session);
+        session.remove(key.into());
+        oauth_state.filter(|oauth_state| !oauth_state.is_expired())
+    }
+}
+
+/// A link that was put on hold because the platform user is already linked to another account.
+/// Kept in the session until the user confirms they want to move the platform over to their account
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct PendingPlatformLink {
+    pub platform: String,
+    pub platform_user: String,
+    /// the account the platform user is currently linked to
+    pub account: i64,
+    pub data: Vec<NewAccountPlatformData>,
+    pub expires_at: i64,
+}
+
+impl PendingPlatformLink {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= unix_timestamp()
+    }
+}
+
+/// Tokens handed to us by a platform that are kept so we can call the platform on behalf of the user later
+#[derive(Clone, Debug, Default)]
+pub struct PlatformTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// seconds until the access token expires, 0 when the platform did not tell us
+    pub expires_in: i64,
+    pub scopes: Vec<String>,
+}
+
+/// Everything that is unique about a platform that can be linked to an account.
+///
+/// The generic router in `routes::platform::provider` takes care of the rest (state checks, session keys, linking and cache busting)
+/// so adding a new platform only requires implementing this trait and mounting `provider::router::<T>()`
+#[async_trait::async_trait]
+pub trait PlatformProvider: Send + Sync + 'static {
+    /// response returned by the platform after trading in the oauth code
+    type Validation: Send + Sync;
+
+    /// user information fetched from the platform after validation
+    type Identity: Send + Sync;
+
+    /// the platform type that is stored in `account_platforms.platform`
+    fn platform() -> AccountPlatformType;
+
+    /// session key used to hold the oauth state between login and validate
+    fn session_state_key() -> SessionKey;
+
+    /// session key used to hold the url we return the user to after validating
+    fn session_caller_key() -> SessionKey;
+
+    /// construct the url we send the user to in order to authorize with the platform.
+    /// Platforms without PKCE support can ignore the code challenge
+    fn authorize_url(oauth_state: &str, code_challenge: &str, state: &ApplicationState<AccountExtension>) -> String;
+
+    /// trade the oauth code returned from the platform for a validation response.
+    /// The code verifier is the one the code challenge in `authorize_url` was derived from
+    async fn exchange_token(
+        code: &str,
+        code_verifier: &str,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Option<Self::Validation>;
+
+    /// fetch the user information tied to the validation response
+    async fn identity(
+        validation: &Self::Validation,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Option<Self::Identity>;
+
+    /// the platform user id that is stored in `account_platforms.platform_user`
+    fn platform_user(identity: &Self::Identity) -> String;
+
+    /// map the identity into the key/value pairs that are stored in `account_platform_data`
+    fn platform_data(identity: &Self::Identity) -> Vec<NewAccountPlatformData>;
+
+    /// tokens from the validation response that should be persisted. Platforms without oauth tokens can leave this as is
+    fn tokens(_validation: &Self::Validation) -> Option<PlatformTokens> {
+        None
+    }
+
+    /// trade in a refresh token for a fresh set of tokens. Platforms that do not hand out refresh tokens can leave this as is
+    async fn refresh_token(_refresh_token: &str, _state: &ApplicationState<AccountExtension>) -> Option<PlatformTokens> {
+        None
+    }
+}
+
+/// Fetches the platform user again and rewrites its platform data, the same way the update jobs do.
+/// Only discord, bungie and twitch can be looked up without the user, anything else returns false
+pub async fn resync(account_platform: &AccountPlatform, state: &ApplicationState<AccountExtension>) -> bool {
+    let platform_user = account_platform.platform_user.as_str();
+    let data = match account_platform.platform.parse::<AccountPlatformType>() {
+        // discord syncs the member and its guild roles in one go
+        Ok(AccountPlatformType::Discord) => return app::discord::member(platform_user, state).await.is_some(),
+        Ok(AccountPlatformType::Bungie) => bungie::fetch_identity(platform_user, None, state)
+            .await
+            .map(|identity| bungie::platform_data(&identity)),
+        Ok(AccountPlatformType::Twitch) => match twitch::app_access_token(state).await {
+            Some(app_token) => twitch::fetch_users(&[platform_user.to_string()], &app_token, state)
+                .await
+                .first()
+                .map(twitch::platform_data),
+            None => None,
+        },
+        _ => {
+            tracing::warn!("{} platforms cannot be resynced", account_platform.platform);
+            return false;
+        }
+    };
+
+    match data {
+        Some(data) => {
+            database::platform_data::write(account_platform, &data, state).await;
+            database::platform::update(&mut account_platform.clone(), state).await;
+            true
+        }
+        None => {
+            tracing::warn!(
+                "Unable to fetch {} user {}",
+                account_platform.platform,
+                account_platform.platform_user
+            );
+            false
+        }
+    }
+}
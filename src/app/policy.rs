@@ -0,0 +1,214 @@
+use crate::database::ban::AccountBan;
+use crate::database::platform::AccountPlatformType;
+use crate::app::bungie::CLAN_IDS_KEY;
+use crate::routes::responses::DiscordRole;
+use crate::sync::discord::{GUILD_ROLES_KEY, OAUTH_GUILDS_KEY};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A rule an account has to satisfy to be allowed in.
+///
+/// Policies are configured as json through the `account.access_policy` setting, for example
+/// `{"type":"any","rules":[{"type":"admin"},{"type":"discord_guild","guild":"123","roles":["Member"]},{"type":"bungie_clan","clan":"456"}]}`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccessRule {
+    /// the account is flagged as an admin
+    Admin,
+
+    /// the discord account is in the guild. When roles are listed, it must have at least one of them (by id or name)
+    DiscordGuild {
+        guild: String,
+        #[serde(default)]
+        roles: Vec<String>,
+    },
+
+    /// the linked bungie account is in the clan
+    BungieClan { clan: String },
+
+    /// every rule must pass
+    All { rules: Vec<AccessRule> },
+
+    /// at least one rule must pass
+    Any { rules: Vec<AccessRule> },
+}
+
+/// with nothing configured nobody gets in
+impl Default for AccessRule {
+    fn default() -> Self {
+        AccessRule::Any { rules: Vec::new() }
+    }
+}
+
+impl AccessRule {
+    /// The policy we used before policies were configurable, membership in any of the allowed discords
+    pub fn from_guilds(guilds: &[String]) -> AccessRule {
+        AccessRule::Any {
+            rules: guilds
+                .iter()
+                .filter(|guild| !guild.is_empty())
+                .map(|guild| AccessRule::DiscordGuild {
+                    guild: guild.clone(),
+                    roles: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Why an account was not allowed in. The string form is passed back to the caller as the `reason` query parameter
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccessDenial {
+    /// we could not figure out who the user is
+    Unauthenticated,
+    NotAdmin,
+    NotInGuild(String),
+    MissingGuildRole(String),
+    NotInClan(String),
+    /// the policy has no rules that could have let the user in
+    NoRules,
+    /// the account is banned until someone lifts it
+    Banned,
+    /// the account is suspended until the timestamp
+    Suspended(i64),
+}
+
+impl AccessDenial {
+    /// the short reason code handed back to callers
+    pub fn reason(&self) -> &'static str {
+        match self {
+            AccessDenial::Unauthenticated => "Unauthenticated",
+            AccessDenial::NotAdmin => "NotAdmin",
+            AccessDenial::NotInGuild(_) => "NotInGuild",
+            AccessDenial::MissingGuildRole(_) => "MissingGuildRole",
+            AccessDenial::NotInClan(_) => "NotInClan",
+            AccessDenial::NoRules => "NoRules",
+            AccessDenial::Banned => "Banned",
+            AccessDenial::Suspended(_) => "Suspended",
+        }
+    }
+}
+
+impl fmt::Display for AccessDenial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessDenial::Unauthenticated => write!(f, "Unable to verify the discord account"),
+            AccessDenial::NotAdmin => write!(f, "Account is not an admin"),
+            AccessDenial::NotInGuild(guild) => write!(f, "Not a member of discord {}", guild),
+            AccessDenial::MissingGuildRole(guild) => write!(f, "Missing a required role in discord {}", guild),
+            AccessDenial::NotInClan(clan) => write!(f, "Not a member of clan {}", clan),
+            AccessDenial::NoRules => write!(f, "No access rules are configured"),
+            AccessDenial::Banned => write!(f, "Account is banned"),
+            AccessDenial::Suspended(until) => write!(f, "Account is suspended until {}", until),
+        }
+    }
+}
+
+impl From<&AccountBan> for AccessDenial {
+    fn from(ban: &AccountBan) -> Self {
+        if ban.is_permanent() {
+            AccessDenial::Banned
+        } else {
+            AccessDenial::Suspended(ban.expires_at)
+        }
+    }
+}
+
+/// Everything about an account that rules can be checked against
+#[derive(Clone, Debug, Default)]
+pub struct AccessSubject {
+    pub is_admin: bool,
+    /// guild id => roles the discord account has in that guild
+    pub guild_roles: HashMap<String, Vec<DiscordRole>>,
+    pub clans: Vec<String>,
+}
+
+impl AccessSubject {
+    /// Builds the subject from the platform data returned by `database::account::all_data`.
+    /// Guilds only known through oauth count as membership without any roles
+    pub fn from_platforms(is_admin: bool, platforms: &HashMap<String, HashMap<String, String>>) -> AccessSubject {
+        let discord = platforms.get(&AccountPlatformType::Discord.to_string());
+        let mut guild_roles: HashMap<String, Vec<DiscordRole>> = discord
+            .and_then(|data| data.get(GUILD_ROLES_KEY))
+            .and_then(|roles| serde_json::from_str(roles).ok())
+            .unwrap_or_default();
+
+        let oauth_guilds = discord.and_then(|data| data.get(OAUTH_GUILDS_KEY));
+        for guild in oauth_guilds.into_iter().flat_map(|guilds| guilds.split(',')) {
+            if !guild.is_empty() {
+                guild_roles.entry(guild.to_string()).or_default();
+            }
+        }
+
+        let clans = platforms
+            .get(&AccountPlatformType::Bungie.to_string())
+            .and_then(|data| data.get(CLAN_IDS_KEY))
+            .map(|clans| {
+                clans
+                    .split(',')
+                    .filter(|clan| !clan.is_empty())
+                    .map(|clan| clan.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        AccessSubject {
+            is_admin,
+            guild_roles,
+            clans,
+        }
+    }
+}
+
+/// Evaluates the rule against the subject.
+/// When an `any` rule fails, the denial of its first rule is returned since that is the one the policy leads with
+pub fn evaluate(rule: &AccessRule, subject: &AccessSubject) -> Result<(), AccessDenial> {
+    match rule {
+        AccessRule::Admin => {
+            if subject.is_admin {
+                Ok(())
+            } else {
+                Err(AccessDenial::NotAdmin)
+            }
+        }
+        AccessRule::DiscordGuild { guild, roles } => {
+            let member_roles = subject
+                .guild_roles
+                .get(guild)
+                .ok_or_else(|| AccessDenial::NotInGuild(guild.clone()))?;
+
+            let has_role = roles.is_empty()
+                || member_roles.iter().any(|member_role| {
+                    roles.iter().any(|role| {
+                        member_role.id.as_deref() == Some(role.as_str()) || member_role.name.eq_ignore_ascii_case(role)
+                    })
+                });
+
+            if has_role {
+                Ok(())
+            } else {
+                Err(AccessDenial::MissingGuildRole(guild.clone()))
+            }
+        }
+        AccessRule::BungieClan { clan } => {
+            if subject.clans.contains(clan) {
+                Ok(())
+            } else {
+                Err(AccessDenial::NotInClan(clan.clone()))
+            }
+        }
+        AccessRule::All { rules } => rules.iter().try_for_each(|rule| evaluate(rule, subject)),
+        AccessRule::Any { rules } => {
+            let mut first_denial = None;
+            for rule in rules.iter() {
+                match evaluate(rule, subject) {
+                    Ok(()) => return Ok(()),
+                    Err(denial) => {
+                        first_denial.get_or_insert(denial);
+                    }
+                }
+            }
+            Err(first_denial.unwrap_or(AccessDenial::NoRules))
+        }
+    }
+}
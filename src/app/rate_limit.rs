@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// buckets are only swept once there are this many, so idle callers do not pile up forever
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Routes that share a limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RateLimitGroup {
+    Search,
+    Challenge,
+    LinkGenerate,
+}
+
+impl std::fmt::Display for RateLimitGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RateLimitGroup::Search => {
+                write!(f, "search")
+            }
+            RateLimitGroup::Challenge => {
+                write!(f, "challenge")
+            }
+            RateLimitGroup::LinkGenerate => {
+                write!(f, "link_generate")
+            }
+        }
+    }
+}
+
+impl RateLimitGroup {
+    pub const ALL: [RateLimitGroup; 3] = [
+        RateLimitGroup::Search,
+        RateLimitGroup::Challenge,
+        RateLimitGroup::LinkGenerate,
+    ];
+
+    /// used when the `rate_limit.<group>` setting is empty or can not be read
+    pub fn default_limit(&self) -> RateLimit {
+        match self {
+            RateLimitGroup::Search => RateLimit::new(60, 60),
+            RateLimitGroup::Challenge => RateLimit::new(10, 60),
+            RateLimitGroup::LinkGenerate => RateLimit::new(30, 60),
+        }
+    }
+}
+
+/// Lets `requests` through every `seconds`, in bursts of up to `requests`. No requests means no limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub seconds: u32,
+}
+
+impl RateLimit {
+    pub fn new(requests: u32, seconds: u32) -> RateLimit {
+        RateLimit { requests, seconds }
+    }
+
+    /// reads the `requests/seconds` form used by the settings, for example `30/60`
+    pub fn parse(value: &str) -> Option<RateLimit> {
+        let (requests, seconds) = value.trim().split_once('/')?;
+        let requests = requests.trim().parse::<u32>().ok()?;
+        let seconds = seconds.trim().parse::<u32>().ok()?;
+        if seconds == 0 {
+            return None;
+        }
+
+        Some(RateLimit { requests, seconds })
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.requests == 0
+    }
+
+    /// tokens added back per second
+    fn refill_rate(&self) -> f64 {
+        self.requests as f64 / self.seconds as f64
+    }
+}
+
+impl std::fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.requests, self.seconds)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// In memory token buckets, one per group and caller. Clones share the same buckets
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter {
+    limits: HashMap<RateLimitGroup, RateLimit>,
+    buckets: Arc<Mutex<HashMap<(RateLimitGroup, String), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: HashMap<RateLimitGroup, RateLimit>) -> RateLimiter {
+        RateLimiter {
+            limits,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn limit(&self, group: RateLimitGroup) -> RateLimit {
+        self.limits.get(&group).copied().unwrap_or_else(|| group.default_limit())
+    }
+
+    /// Takes a token from the bucket of the caller. When the bucket is empty, returns how many seconds until the next token
+    pub fn take(&self, group: RateLimitGroup, caller: &str) -> Result<(), u64> {
+        let limit = self.limit(group);
+        if limit.is_disabled() {
+            return Ok(());
+        }
+
+        let capacity = limit.requests as f64;
+        let refill_rate = limit.refill_rate();
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if buckets.len() >= SWEEP_THRESHOLD {
+            // a bucket that would have refilled completely behaves the same as a missing one
+            buckets.retain(|(group, _), bucket| {
+                let limit = self.limit(*group);
+                bucket.tokens + now.duration_since(bucket.refilled_at).as_secs_f64() * limit.refill_rate()
+                    < limit.requests as f64
+            });
+        }
+
+        let bucket = buckets.entry((group, caller.to_string())).or_insert(Bucket {
+            tokens: capacity,
+            refilled_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_rate).min(capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / refill_rate).ceil() as u64)
+        }
+    }
+}
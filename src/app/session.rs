@@ -1,10 +1,17 @@
+pub mod store;
+
 use crate::app;
+use crate::app::extension::AccountExtension;
+use crate::database;
+use crate::database::account::Account;
 use crate::sync::discord::MemberSyncResult;
 use axum_sessions::async_session::Session;
+use levelcrush::app::ApplicationState;
 use levelcrush::axum_sessions;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+#[derive(Clone, Copy, Debug)]
 pub enum SessionKey {
     Account,
     AccountSecret,
@@ -16,6 +23,14 @@ pub enum SessionKey {
     PlatformTwitchState,
     PlatformBungieCallerUrl,
     PlatformBungieState,
+    PlatformSteamCallerUrl,
+    PlatformSteamState,
+    PlatformBattleNetCallerUrl,
+    PlatformBattleNetState,
+    PlatformPendingLink,
+    UserAgent,
+    IpAddress,
+    LastSeen,
 }
 
 impl From<SessionKey> for &'static str {
@@ -31,6 +46,14 @@ impl From<SessionKey> for &'static str {
             SessionKey::PlatformTwitchState => "platform_twitch_state",
             SessionKey::PlatformBungieCallerUrl => "platform_bungie_caller_url",
             SessionKey::PlatformBungieState => "platform_bungie_state",
+            SessionKey::PlatformSteamCallerUrl => "platform_steam_caller_url",
+            SessionKey::PlatformSteamState => "platform_steam_state",
+            SessionKey::PlatformBattleNetCallerUrl => "platform_battlenet_caller_url",
+            SessionKey::PlatformBattleNetState => "platform_battlenet_state",
+            SessionKey::PlatformPendingLink => "platform_pending_link",
+            SessionKey::UserAgent => "user_agent",
+            SessionKey::IpAddress => "ip_address",
+            SessionKey::LastSeen => "last_seen",
             _ => panic!("No match for this session key"),
         }
     }
@@ -69,15 +92,32 @@ pub fn clear(session: &mut Session) {
     session.remove(SessionKey::PlatformTwitchState.into());
     session.remove(SessionKey::PlatformBungieCallerUrl.into());
     session.remove(SessionKey::PlatformBungieState.into());
+    session.remove(SessionKey::PlatformSteamCallerUrl.into());
+    session.remove(SessionKey::PlatformSteamState.into());
+    session.remove(SessionKey::PlatformBattleNetCallerUrl.into());
+    session.remove(SessionKey::PlatformBattleNetState.into());
+    session.remove(SessionKey::PlatformPendingLink.into());
 }
 
 pub fn login(session: &mut Session, member: MemberSyncResult) {
     // clear the session variables out, this is safe since discord is our primary login
     app::session::clear(session);
 
-    // in the session store important information related to the account, the account token and the token secret
+    // in the session store important information related to the account, the account token and the token secret.
+    // The secret only comes along when it was just issued, see `MemberSyncResult`
     app::session::write(SessionKey::Account, member.account_token, session);
-    app::session::write(SessionKey::AccountSecret, member.account_token_secret, session);
+    if !member.account_token_secret.is_empty() {
+        app::session::write(SessionKey::AccountSecret, member.account_token_secret, session);
+    }
     app::session::write(SessionKey::DisplayName, member.display_name, session);
     app::session::write(SessionKey::Username, member.username, session);
 }
+
+/// Looks up the account logged into the session.
+///
+/// Sessions live in our database and only we write the account into them, so the token alone is trusted here.
+/// Revoking the session is what logs it out, the account secret is only handed out once and is not kept in every session
+pub async fn account(session: &Session, state: &ApplicationState<AccountExtension>) -> Option<Account> {
+    let account_token = read::<String>(SessionKey::Account, session).unwrap_or_default();
+    database::account::by_token(&account_token, state).await
+}
@@ -0,0 +1,156 @@
+use crate::app::extension::AccountExtension;
+use crate::app::session::{self, SessionKey};
+use crate::database;
+use crate::database::session::SessionRecord;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, Extensions, HeaderMap, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum_sessions::async_session::{self, Session, SessionStore};
+use axum_sessions::SessionHandle;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{axum, axum_sessions};
+use std::net::SocketAddr;
+
+/// how often the last seen time of a session is written back, in seconds
+const LAST_SEEN_RESOLUTION: i64 = 60;
+
+/// Keeps sessions in the app database so they survive restarts and can be listed and revoked per account
+#[derive(Clone, Debug)]
+pub struct DatabaseSessionStore {
+    state: ApplicationState<AccountExtension>,
+}
+
+impl DatabaseSessionStore {
+    pub fn new(state: ApplicationState<AccountExtension>) -> DatabaseSessionStore {
+        DatabaseSessionStore { state }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for DatabaseSessionStore {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        let session_id = Session::id_from_cookie_value(&cookie_value)?;
+        let record = database::session::load(&session_id, &self.state).await;
+
+        Ok(record
+            .and_then(|record| serde_json::from_str::<Session>(&record.data).ok())
+            .and_then(Session::validate))
+    }
+
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        let account_token = session::read::<String>(SessionKey::Account, &session).unwrap_or_default();
+        let account = database::account::by_token(&account_token, &self.state)
+            .await
+            .map(|account| account.id)
+            .unwrap_or_default();
+
+        let record = SessionRecord {
+            session_id: session.id().to_string(),
+            account,
+            data: serde_json::to_string(&session)?,
+            user_agent: session::read(SessionKey::UserAgent, &session).unwrap_or_default(),
+            ip: session::read(SessionKey::IpAddress, &session).unwrap_or_default(),
+            expires_at: session.expiry().map(|expiry| expiry.timestamp()).unwrap_or_default(),
+            last_seen_at: session::read(SessionKey::LastSeen, &session).unwrap_or_else(unix_timestamp),
+        };
+
+        if !database::session::store(record, &self.state).await {
+            return Err(async_session::Error::msg("Unable to store session"));
+        }
+
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        database::session::destroy(session.id(), &self.state).await;
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        database::session::clear(&self.state).await;
+        Ok(())
+    }
+}
+
+/// The address of the client as worked out by `resolve_client_ip`. Missing when it could not be determined
+#[derive(Clone, Debug)]
+pub struct ClientIp(pub String);
+
+/// The address of the client.
+///
+/// With no trusted proxies this is the socket address. Behind `trusted_proxies` proxies every one of them appends the address it was
+/// connected from to X-Forwarded-For, so the client is that many hops from the right. Anything further left was sent by the client itself
+pub fn client_ip(headers: &HeaderMap, extensions: &Extensions, trusted_proxies: usize) -> Option<String> {
+    if trusted_proxies == 0 {
+        return extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+    }
+
+    let hops = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|hop| hop.trim())
+        .collect::<Vec<&str>>();
+
+    hops.len()
+        .checked_sub(trusted_proxies)
+        .and_then(|index| hops.get(index))
+        .filter(|hop| !hop.is_empty())
+        .map(|hop| hop.to_string())
+}
+
+/// Works out the client address once per request and makes it available as `ClientIp`. Has to sit outside the other layers
+pub async fn resolve_client_ip<B>(
+    State(state): State<ApplicationState<AccountExtension>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if let Some(ip) = client_ip(req.headers(), req.extensions(), state.extension.trusted_proxies) {
+        req.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(req).await
+}
+
+/// Records the device, ip and last seen time on logged in sessions so they can be listed under `/profile/sessions`.
+/// Only written when something changed or the last seen time is stale, since every write goes to the database
+pub async fn track<B>(req: Request<B>, next: Next<B>) -> Response {
+    if let Some(session_handle) = req.extensions().get::<SessionHandle>().cloned() {
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(512).collect::<String>())
+            .unwrap_or_default();
+        let ip = req
+            .extensions()
+            .get::<ClientIp>()
+            .map(|ClientIp(ip)| ip.clone())
+            .unwrap_or_default();
+        let now = unix_timestamp();
+
+        let needs_update = {
+            let session = session_handle.read().await;
+            let logged_in = session::read::<String>(SessionKey::Account, &session).is_some();
+            let last_seen = session::read::<i64>(SessionKey::LastSeen, &session).unwrap_or_default();
+            let known_user_agent = session::read::<String>(SessionKey::UserAgent, &session).unwrap_or_default();
+            let known_ip = session::read::<String>(SessionKey::IpAddress, &session).unwrap_or_default();
+
+            logged_in && (last_seen + LAST_SEEN_RESOLUTION < now || known_user_agent != user_agent || known_ip != ip)
+        };
+
+        if needs_update {
+            let mut session = session_handle.write().await;
+            session::write(SessionKey::LastSeen, now, &mut session);
+            session::write(SessionKey::UserAgent, user_agent, &mut session);
+            session::write(SessionKey::IpAddress, ip, &mut session);
+        }
+    }
+
+    next.run(req).await
+}
@@ -0,0 +1,139 @@
+use crate::app::extension::AccountExtension;
+use crate::database::platform_data::NewAccountPlatformData;
+use levelcrush::app::ApplicationState;
+use levelcrush::tracing;
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone)]
+pub struct TwitchUserResponse {
+    pub data: Vec<TwitchUserData>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone)]
+pub struct TwitchUserData {
+    pub id: String,
+    pub login: String,
+    pub display_name: String,
+    pub profile_image_url: String,
+    pub offline_image_url: String,
+    pub description: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct TwitchAppTokenRequest {
+    pub client_id: String,
+    pub client_secret: String,
+    pub grant_type: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct TwitchAppTokenResponse {
+    pub access_token: String,
+
+    #[serde(default)]
+    pub expires_in: i64,
+}
+
+/// helix allows at most this many ids per /users request
+pub const TWITCH_USERS_PER_REQUEST: usize = 100;
+
+/// Obtains an app access token through the client credentials flow.
+/// App tokens are not tied to a user and can be used to look up any public user information
+pub async fn app_access_token(state: &ApplicationState<AccountExtension>) -> Option<String> {
+    let request = state
+        .extension
+        .http_client
+        .post("https://id.twitch.tv/oauth2/token")
+        .body(
+            serde_urlencoded::to_string(TwitchAppTokenRequest {
+                client_id: state.extension.twitch_client_id.clone(),
+                client_secret: state.extension.twitch_client_secret.clone(),
+                grant_type: "client_credentials".to_string(),
+            })
+            .unwrap_or_default(),
+        )
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .send()
+        .await;
+
+    if let Ok(response) = request {
+        let result = response.json::<TwitchAppTokenResponse>().await;
+        match result {
+            Ok(result) => Some(result.access_token),
+            Err(error) => {
+                tracing::error!("Could not parse app token response for twitch! {}", error);
+                None
+            }
+        }
+    } else {
+        None
+    }
+}
+
+/// Looks up twitch users by id with the provided access token. Ids past `TWITCH_USERS_PER_REQUEST` are ignored
+pub async fn fetch_users(
+    twitch_ids: &[String],
+    access_token: &str,
+    state: &ApplicationState<AccountExtension>,
+) -> Vec<TwitchUserData> {
+    let query = twitch_ids
+        .iter()
+        .take(TWITCH_USERS_PER_REQUEST)
+        .map(|id| ("id", id.as_str()))
+        .collect::<Vec<(&str, &str)>>();
+
+    let request = state
+        .extension
+        .http_client
+        .get("https://api.twitch.tv/helix/users")
+        .query(&query)
+        .bearer_auth(access_token)
+        .header("Client-Id", state.extension.twitch_client_id.clone())
+        .header("Accept", "application/json")
+        .send()
+        .await;
+
+    if let Ok(request) = request {
+        let result = request.json::<TwitchUserResponse>().await;
+        match result {
+            Ok(result) => result.data,
+            Err(error) => {
+                tracing::error!("{}", error);
+                Vec::new()
+            }
+        }
+    } else {
+        tracing::error!("Could not fetch twitch users");
+        Vec::new()
+    }
+}
+
+/// The platform data stored for a twitch user
+pub fn platform_data(identity: &TwitchUserData) -> Vec<NewAccountPlatformData> {
+    vec![
+        NewAccountPlatformData {
+            key: "twitch_id".to_string(),
+            value: identity.id.to_string(),
+        },
+        NewAccountPlatformData {
+            key: "display_name".to_string(),
+            value: identity.display_name.clone(),
+        },
+        NewAccountPlatformData {
+            key: "offline_image_url".to_string(),
+            value: identity.offline_image_url.clone(),
+        },
+        NewAccountPlatformData {
+            key: "profile_image_url".to_string(),
+            value: identity.profile_image_url.clone(),
+        },
+        NewAccountPlatformData {
+            key: "login".to_string(),
+            value: identity.login.clone(),
+        },
+        NewAccountPlatformData {
+            key: "description".to_string(),
+            value: identity.description.clone(),
+        },
+    ]
+}
@@ -0,0 +1,176 @@
+use crate::app::crypto;
+use crate::app::extension::AccountExtension;
+use crate::database;
+use crate::database::event::AccountEventKind;
+use crate::database::platform::AccountPlatform;
+use crate::database::webhook::{WebhookDelivery, WebhookEvent};
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{reqwest, tracing};
+use std::time::Duration;
+
+/// deliveries picked up per pass of the worker
+const DELIVERY_BATCH: u64 = 50;
+
+/// how long a subscriber gets to answer before the attempt counts as failed
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The webhook events an audit log event is announced as, along with the platform user each one is about.
+/// Platform data only counts when the display name changed. Transfers are announced as an unlink and a link
+fn webhook_events(
+    kind: AccountEventKind,
+    account: RecordId,
+    platform_user: &str,
+    before: &serde_json::Value,
+    after: &serde_json::Value,
+) -> Vec<(WebhookEvent, String)> {
+    let platform_user = platform_user.to_string();
+    match kind {
+        AccountEventKind::AccountCreated => vec![(WebhookEvent::AccountCreated, platform_user)],
+        AccountEventKind::PlatformLinked => vec![(WebhookEvent::PlatformLinked, platform_user)],
+        AccountEventKind::PlatformUnlinked => vec![(WebhookEvent::PlatformUnlinked, platform_user)],
+        AccountEventKind::PlatformDataChanged if after.get("display_name").is_some() => {
+            vec![(WebhookEvent::DisplayNameChanged, platform_user)]
+        }
+        AccountEventKind::PlatformTransferred => {
+            let from = before.get("account").and_then(|account| account.as_i64());
+            let into = after.get("account").and_then(|account| account.as_i64());
+            match (from, into) {
+                // the platform moved between accounts and is recorded on both, see `database::platform::transfer`
+                (Some(from), _) if from == account => vec![(WebhookEvent::PlatformUnlinked, platform_user)],
+                (_, Some(into)) if into == account => vec![(WebhookEvent::PlatformLinked, platform_user)],
+                (Some(_), Some(_)) => Vec::new(),
+                // the account itself now belongs to another platform user, see `database::transfer::approve`
+                _ => {
+                    let user = |value: &serde_json::Value| {
+                        value
+                            .get("platform_user")
+                            .and_then(|user| user.as_str())
+                            .unwrap_or_default()
+                            .to_string()
+                    };
+                    vec![
+                        (WebhookEvent::PlatformUnlinked, user(before)),
+                        (WebhookEvent::PlatformLinked, user(after)),
+                    ]
+                }
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Queues webhook deliveries for the change that was just written to the audit log
+pub async fn notify(
+    kind: AccountEventKind,
+    account: RecordId,
+    platform: Option<&AccountPlatform>,
+    before: &serde_json::Value,
+    after: &serde_json::Value,
+    state: &ApplicationState<AccountExtension>,
+) {
+    let platform_user = platform
+        .map(|platform| platform.platform_user.as_str())
+        .unwrap_or_default();
+    let events = webhook_events(kind, account, platform_user, before, after);
+    if events.is_empty() {
+        return;
+    }
+
+    // the account a platform was merged away from is already gone by the time it is announced
+    let account_token = match database::account::by_id_with_deleted(account, state).await {
+        Some(account) => account.token,
+        None => return,
+    };
+
+    for (event, platform_user) in events.into_iter() {
+        let display_name = if event == WebhookEvent::DisplayNameChanged {
+            serde_json::json!({
+                "before": before.get("display_name").cloned().unwrap_or_default(),
+                "after": after.get("display_name").cloned().unwrap_or_default(),
+            })
+        } else {
+            serde_json::Value::Null
+        };
+
+        let payload = serde_json::json!({
+            "event": event.to_string(),
+            "account": account_token,
+            "platform": platform.map(|platform| platform.platform.clone()).unwrap_or_default(),
+            "platform_user": platform_user,
+            "display_name": display_name,
+            "created_at": unix_timestamp(),
+        });
+
+        database::webhook::enqueue(event, &payload, state).await;
+    }
+}
+
+/// The `Webhook-Signature` header. Subscribers recompute the HMAC-SHA256 of `<Webhook-Timestamp>.<body>` with their secret
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    format!("v1={}", crypto::sign_hex(&format!("{}.{}", timestamp, body), secret))
+}
+
+/// Sends a single attempt of the delivery and records how it went
+pub async fn deliver(delivery: &WebhookDelivery, state: &ApplicationState<AccountExtension>) {
+    let subscription = match database::webhook::get(delivery.subscription, state).await {
+        Some(subscription) => subscription,
+        None => {
+            database::webhook::abandon(delivery, "Subscription was removed", state).await;
+            return;
+        }
+    };
+
+    let secret = match database::webhook::secret(&subscription, state) {
+        Some(secret) => secret,
+        None => {
+            database::webhook::failed(delivery, 0, "Unable to decrypt the subscription secret", state).await;
+            return;
+        }
+    };
+
+    let timestamp = unix_timestamp();
+    let request = state
+        .extension
+        .http_client
+        .post(&subscription.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("Webhook-Id", delivery.id.to_string())
+        .header("Webhook-Event", delivery.event.as_str())
+        .header("Webhook-Timestamp", timestamp.to_string())
+        .header("Webhook-Signature", signature(&secret, timestamp, &delivery.payload))
+        .body(delivery.payload.clone())
+        .timeout(DELIVERY_TIMEOUT)
+        .send()
+        .await;
+
+    match request {
+        Ok(response) if response.status().is_success() => {
+            database::webhook::delivered(delivery, response.status().as_u16() as i32, state).await;
+        }
+        Ok(response) => {
+            let status = response.status();
+            tracing::warn!("Webhook delivery {} to {} answered {}", delivery.id, subscription.name, status);
+            let error = format!("Subscriber answered {}", status);
+            database::webhook::failed(delivery, status.as_u16() as i32, &error, state).await;
+        }
+        Err(err) => {
+            tracing::warn!("Webhook delivery {} to {} failed: {}", delivery.id, subscription.name, err);
+            database::webhook::failed(delivery, 0, &err.to_string(), state).await;
+        }
+    }
+}
+
+/// Sends every delivery that is due and returns how many were attempted
+pub async fn deliver_due(state: &ApplicationState<AccountExtension>) -> usize {
+    let mut attempted = 0;
+    for delivery in database::webhook::due(DELIVERY_BATCH, state).await.iter() {
+        // another worker may have picked it up in the meantime
+        if database::webhook::claim(delivery, state).await {
+            deliver(delivery, state).await;
+            attempted += 1;
+        }
+    }
+    attempted
+}
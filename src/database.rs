@@ -1,5 +1,15 @@
 pub mod account;
+pub mod api_client;
+pub mod ban;
+pub mod event;
+pub mod oauth_client;
 pub mod platform;
 pub mod platform_data;
+pub mod platform_tokens;
+pub mod role;
+pub mod session;
+pub mod signing_key;
+pub mod transfer;
+pub mod webhook;
 
 pub const DATABASE_URL: &str = "sqlite://accounts.sqlite?mode=rwc";
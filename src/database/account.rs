@@ -1,14 +1,19 @@
+use levelcrush::alias::RecordId;
 use levelcrush::app::ApplicationState;
 use levelcrush::project_str;
-use levelcrush::{database, md5, util::unix_timestamp};
+use levelcrush::{database, tracing, util::unix_timestamp};
+use sea_orm::sea_query::{Expr, Query};
 use sea_orm::{
-    self, ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DatabaseBackend, EntityTrait, FromQueryResult,
-    JoinType, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Select, Statement, Value, Values,
+    self, ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DatabaseBackend, DbErr, EntityTrait,
+    FromQueryResult, JoinType, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Select, Statement,
+    TransactionTrait, Value, Values,
 };
 use std::collections::HashMap;
 
+use crate::app::crypto;
 use crate::app::extension::AccountExtension;
-use crate::entities::{account_platform_data, account_platforms, accounts};
+use crate::database::event::{self, AccountEventKind};
+use crate::entities::{account_platform_data, account_platform_tokens, account_platforms, accounts};
 
 #[derive(Clone, Debug, Default, serde::Serialize, FromQueryResult)]
 pub struct AccountLinkedPlatformsResult {
@@ -17,6 +22,12 @@ pub struct AccountLinkedPlatformsResult {
     pub discord: String,
     pub bungie: String,
     pub twitch: String,
+    pub steam: String,
+    pub battlenet: String,
+    /// `banned` or `suspended` while the account is kept out, empty otherwise
+    pub ban_status: String,
+    /// when a suspension ends, 0 for bans and accounts in good standing
+    pub banned_until: i64,
 }
 
 #[derive(Clone, Debug, Default, serde::Serialize, FromQueryResult)]
@@ -27,14 +38,236 @@ pub struct AccountLinkedPlatformDataResult {
     pub value: String,
 }
 
+/// What happened to the platforms of an account that was merged into another
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct AccountMergeResult {
+    /// platforms that now belong to the account that was merged into
+    pub moved: Vec<String>,
+    /// platforms the account that was merged into already had, these were unlinked from the merged account
+    pub dropped: Vec<String>,
+}
+
 pub type Account = accounts::Model;
 
+/// An account along with its plain text secret. Only the hash is stored, so the secret is only ever handed back when it is generated
+#[derive(Clone, Debug, Default)]
+pub struct NewAccountSecret {
+    pub account: Account,
+    pub secret: String,
+}
+
+/// 24 random bytes come out as 32 characters, the width of the token columns
+pub const ACCOUNT_TOKEN_BYTES: usize = 24;
+
+/// 32 random bytes, which come out longer than a legacy secret so the two are never mistaken for each other
+const ACCOUNT_SECRET_BYTES: usize = 32;
+
+/// length of the md5 hex secrets accounts were created with before secrets were hashed
+const LEGACY_SECRET_LENGTH: usize = 32;
+
+/// Looks up the account the token and secret belong to.
+///
+/// Accounts that have been rotated keep accepting their previous token and secret until the legacy credentials are dropped.
+/// Accounts that have not been rotated yet still have their secret in plain text
 pub async fn get(token: &str, token_secret: &str, state: &ApplicationState<AccountExtension>) -> Option<Account> {
-    let model = accounts::Entity::find()
-        .filter(
+    if token.is_empty() || token_secret.is_empty() {
+        return None;
+    }
+
+    let secret_hash = crypto::hash_secret(token_secret);
+    let mut credentials = Condition::any()
+        .add(
+            Condition::all()
+                .add(accounts::Column::Token.eq(token))
+                .add(accounts::Column::TokenSecret.eq(secret_hash.clone())),
+        )
+        .add(
+            Condition::all()
+                .add(accounts::Column::LegacyToken.eq(token))
+                .add(accounts::Column::LegacyTokenSecret.eq(secret_hash)),
+        );
+
+    // a hash is never this short, so a leaked hash can not be passed off as a plain text secret
+    if token_secret.len() == LEGACY_SECRET_LENGTH {
+        credentials = credentials.add(
             Condition::all()
                 .add(accounts::Column::Token.eq(token))
                 .add(accounts::Column::TokenSecret.eq(token_secret)),
+        );
+    }
+
+    let model = accounts::Entity::find()
+        .filter(
+            Condition::all()
+                .add(credentials)
+                .add(accounts::Column::DeletedAt.eq(0)),
+        )
+        .one(&state.database)
+        .await;
+
+    if let Ok(model) = model {
+        model
+    } else {
+        database::log_error(model);
+        None
+    }
+}
+
+/// accounts that still have their original md5 token and plain text secret
+pub fn is_legacy(account: &Account) -> bool {
+    account.token_secret.len() == LEGACY_SECRET_LENGTH
+}
+
+/// Gives the account a new random token and secret.
+///
+/// With `keep_legacy` the current token and the hash of its secret are kept as the legacy credentials,
+/// so sessions and services holding the old token keep working until `drop_legacy` is run.
+/// Without it the old credentials, and any legacy ones from before, stop working straight away
+pub async fn rotate(
+    account: &Account,
+    keep_legacy: bool,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<NewAccountSecret> {
+    let (legacy_token, legacy_secret) = if !keep_legacy {
+        (String::new(), String::new())
+    } else if is_legacy(account) {
+        (account.token.clone(), crypto::hash_secret(&account.token_secret))
+    } else {
+        (account.token.clone(), account.token_secret.clone())
+    };
+
+    let token = crypto::random_token(ACCOUNT_TOKEN_BYTES);
+    let secret = crypto::random_token(ACCOUNT_SECRET_BYTES);
+    let token_secret = crypto::hash_secret(&secret);
+
+    let query = accounts::Entity::update_many()
+        .col_expr(accounts::Column::LegacyToken, Expr::value(legacy_token))
+        .col_expr(accounts::Column::LegacyTokenSecret, Expr::value(legacy_secret))
+        .col_expr(accounts::Column::Token, Expr::value(token))
+        .col_expr(accounts::Column::TokenSecret, Expr::value(token_secret))
+        .col_expr(accounts::Column::UpdatedAt, Expr::value(unix_timestamp()))
+        .filter(accounts::Column::Id.eq(account.id))
+        .exec(&state.database)
+        .await;
+
+    if query.is_err() {
+        database::log_error(query);
+        return None;
+    }
+
+    let rotated = by_id(account.id, state).await?;
+    event::record(
+        AccountEventKind::AccountRotated,
+        account.id,
+        None,
+        &serde_json::json!({ "token": account.token }),
+        &serde_json::json!({ "token": rotated.token }),
+        state,
+    )
+    .await;
+
+    Some(NewAccountSecret {
+        account: rotated,
+        secret,
+    })
+}
+
+/// Legacy accounts, a page at a time
+pub async fn legacy(limit: u64, state: &ApplicationState<AccountExtension>) -> Vec<Account> {
+    let query = accounts::Entity::find()
+        .filter(
+            Condition::all()
+                .add(accounts::Column::LegacyToken.eq(""))
+                .add(Expr::cust_with_values("CHAR_LENGTH(token_secret) = ?", [LEGACY_SECRET_LENGTH as u64])),
+        )
+        .order_by_asc(accounts::Column::Id)
+        .limit(limit)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// Stops accepting the tokens and secrets accounts had before they were rotated
+pub async fn drop_legacy(state: &ApplicationState<AccountExtension>) -> u64 {
+    let query = accounts::Entity::update_many()
+        .col_expr(accounts::Column::LegacyToken, Expr::value(""))
+        .col_expr(accounts::Column::LegacyTokenSecret, Expr::value(""))
+        .filter(accounts::Column::LegacyToken.ne(""))
+        .exec(&state.database)
+        .await;
+
+    match query {
+        Ok(result) => {
+            event::record(
+                AccountEventKind::AccountLegacyDropped,
+                0,
+                None,
+                &(),
+                &serde_json::json!({ "accounts": result.rows_affected }),
+                state,
+            )
+            .await;
+            result.rows_affected
+        }
+        Err(err) => {
+            tracing::error!("Unable to drop legacy account tokens: {}", err);
+            0
+        }
+    }
+}
+
+/// Looks up an account by its record id
+pub async fn by_id(id: RecordId, state: &ApplicationState<AccountExtension>) -> Option<Account> {
+    let model = accounts::Entity::find()
+        .filter(
+            Condition::all()
+                .add(accounts::Column::Id.eq(id))
+                .add(accounts::Column::DeletedAt.eq(0)),
+        )
+        .one(&state.database)
+        .await;
+
+    if let Ok(model) = model {
+        model
+    } else {
+        database::log_error(model);
+        None
+    }
+}
+
+/// Looks up an account by its record id, even when it has been merged away
+pub async fn by_id_with_deleted(id: RecordId, state: &ApplicationState<AccountExtension>) -> Option<Account> {
+    let model = accounts::Entity::find_by_id(id).one(&state.database).await;
+
+    if let Ok(model) = model {
+        model
+    } else {
+        database::log_error(model);
+        None
+    }
+}
+
+/// Looks up an account by its public token alone. Only for tokens we handed out ourselves, like the one in a session. Callers presenting a token and secret go through `get`
+pub async fn by_token(token: &str, state: &ApplicationState<AccountExtension>) -> Option<Account> {
+    if token.is_empty() {
+        return None;
+    }
+
+    let model = accounts::Entity::find()
+        .filter(
+            Condition::all()
+                .add(
+                    Condition::any()
+                        .add(accounts::Column::Token.eq(token))
+                        .add(accounts::Column::LegacyToken.eq(token)),
+                )
+                .add(accounts::Column::DeletedAt.eq(0)),
         )
         .one(&state.database)
         .await;
@@ -47,23 +280,243 @@ pub async fn get(token: &str, token_secret: &str, state: &ApplicationState<Accou
     }
 }
 
-/// Inserts and returns the account that is created based off the two provided seeds
+/// A page of accounts, most recently created first, along with how many accounts matched in total.
+/// `search` matches the account token exactly, or any part of a linked platform user, display name or username
+pub async fn page(
+    search: &str,
+    page: u64,
+    limit: u64,
+    state: &ApplicationState<AccountExtension>,
+) -> (Vec<Account>, u64) {
+    let search = search.trim();
+    let mut condition = Condition::all().add(accounts::Column::DeletedAt.eq(0));
+    if !search.is_empty() {
+        let pattern = format!(
+            "%{}%",
+            search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+        );
+        condition = condition.add(
+            Condition::any()
+                .add(accounts::Column::Token.eq(search))
+                .add(
+                    accounts::Column::Id.in_subquery(
+                        Query::select()
+                            .column(account_platforms::Column::Account)
+                            .from(account_platforms::Entity)
+                            .and_where(account_platforms::Column::PlatformUser.like(pattern.as_str()))
+                            .to_owned(),
+                    ),
+                )
+                .add(
+                    accounts::Column::Id.in_subquery(
+                        Query::select()
+                            .column(account_platform_data::Column::Account)
+                            .from(account_platform_data::Entity)
+                            .and_where(account_platform_data::Column::Key.is_in(["display_name", "username"]))
+                            .and_where(account_platform_data::Column::Value.like(pattern.as_str()))
+                            .to_owned(),
+                    ),
+                ),
+        );
+    }
+
+    let paginator = accounts::Entity::find()
+        .filter(condition)
+        .order_by_desc(accounts::Column::Id)
+        .paginate(&state.database, limit);
+
+    let total = paginator.num_items().await;
+    let total = if let Ok(total) = total {
+        total
+    } else {
+        database::log_error(total);
+        return (Vec::new(), 0);
+    };
+
+    let accounts = paginator.fetch_page(page).await;
+    if let Ok(accounts) = accounts {
+        (accounts, total)
+    } else {
+        database::log_error(accounts);
+        (Vec::new(), total)
+    }
+}
+
+/// Flags or unflags the account as an admin and returns the updated account
+pub async fn set_admin(account: &Account, admin: bool, state: &ApplicationState<AccountExtension>) -> Option<Account> {
+    let query = accounts::Entity::update_many()
+        .col_expr(accounts::Column::Admin, Expr::value(i8::from(admin)))
+        .col_expr(accounts::Column::UpdatedAt, Expr::value(unix_timestamp()))
+        .filter(accounts::Column::Id.eq(account.id))
+        .exec(&state.database)
+        .await;
+
+    if query.is_ok() {
+        event::record(
+            AccountEventKind::AccountAdminChanged,
+            account.id,
+            None,
+            &serde_json::json!({ "admin": account.admin == 1 }),
+            &serde_json::json!({ "admin": admin }),
+            state,
+        )
+        .await;
+        by_id(account.id, state).await
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+/// Moves every platform, along with its data and tokens, from one account into another and soft deletes the emptied account.
 ///
-/// `token_seed` Seed used to compute the public token identifier.
-/// `token_secret_seed` Seed used to compute the private token identifier
-pub async fn create(
-    token_seed: &str,
-    token_secret_seed: &str,
+/// An account can only have one link per platform, so when both accounts have the same platform the one on `into` wins
+/// and the one on `from` is unlinked. Everything happens in a single transaction
+pub async fn merge(
+    from: &Account,
+    into: &Account,
     state: &ApplicationState<AccountExtension>,
-) -> Option<Account> {
-    let token = format!("{:x}", md5::compute(token_seed));
-    let token_secret = format!("{:x}", md5::compute(token_secret_seed));
+) -> Option<AccountMergeResult> {
+    if from.id == into.id {
+        tracing::warn!("Refusing to merge account {} into itself", from.id);
+        return None;
+    }
+
+    let from_platforms = account_platforms::Entity::find()
+        .filter(account_platforms::Column::Account.eq(from.id))
+        .all(&state.database)
+        .await;
+    let into_platforms = account_platforms::Entity::find()
+        .filter(account_platforms::Column::Account.eq(into.id))
+        .all(&state.database)
+        .await;
+
+    let (from_platforms, into_platforms) = match (from_platforms, into_platforms) {
+        (Ok(from_platforms), Ok(into_platforms)) => (from_platforms, into_platforms),
+        (from_platforms, into_platforms) => {
+            database::log_error(from_platforms);
+            database::log_error(into_platforms);
+            return None;
+        }
+    };
+
+    let mut result = AccountMergeResult::default();
+    let (dropped_platforms, moved_platforms): (Vec<_>, Vec<_>) = from_platforms
+        .into_iter()
+        .partition(|platform| into_platforms.iter().any(|existing| existing.platform == platform.platform));
+    result.dropped = dropped_platforms.iter().map(|platform| platform.platform.clone()).collect();
+    result.moved = moved_platforms.iter().map(|platform| platform.platform.clone()).collect();
+    let dropped_ids = dropped_platforms.iter().map(|platform| platform.id).collect::<Vec<RecordId>>();
+
+    let from_id = from.id;
+    let into_id = into.id;
+    let timestamp = unix_timestamp();
+    let transaction = state
+        .database
+        .transaction::<_, (), DbErr>(|txn| {
+            Box::pin(async move {
+                // unlink the platforms that would collide with the ones already on the account we merge into
+                if !dropped_ids.is_empty() {
+                    account_platform_tokens::Entity::delete_many()
+                        .filter(account_platform_tokens::Column::Platform.is_in(dropped_ids.clone()))
+                        .exec(txn)
+                        .await?;
+                    account_platform_data::Entity::delete_many()
+                        .filter(account_platform_data::Column::Platform.is_in(dropped_ids.clone()))
+                        .exec(txn)
+                        .await?;
+                    account_platforms::Entity::delete_many()
+                        .filter(account_platforms::Column::Id.is_in(dropped_ids))
+                        .exec(txn)
+                        .await?;
+                }
+
+                account_platforms::Entity::update_many()
+                    .col_expr(account_platforms::Column::Account, Expr::value(into_id))
+                    .col_expr(account_platforms::Column::UpdatedAt, Expr::value(timestamp))
+                    .filter(account_platforms::Column::Account.eq(from_id))
+                    .exec(txn)
+                    .await?;
+
+                account_platform_data::Entity::update_many()
+                    .col_expr(account_platform_data::Column::Account, Expr::value(into_id))
+                    .filter(account_platform_data::Column::Account.eq(from_id))
+                    .exec(txn)
+                    .await?;
+
+                account_platform_tokens::Entity::update_many()
+                    .col_expr(account_platform_tokens::Column::Account, Expr::value(into_id))
+                    .filter(account_platform_tokens::Column::Account.eq(from_id))
+                    .exec(txn)
+                    .await?;
+
+                accounts::Entity::update_many()
+                    .col_expr(accounts::Column::DeletedAt, Expr::value(timestamp))
+                    .col_expr(accounts::Column::UpdatedAt, Expr::value(timestamp))
+                    .filter(accounts::Column::Id.eq(from_id))
+                    .exec(txn)
+                    .await?;
+
+                Ok(())
+            })
+        })
+        .await;
+
+    match transaction {
+        Ok(()) => {
+            tracing::info!(
+                "Merged account {} into {}. Moved: {:?} Dropped: {:?}",
+                from_id,
+                into_id,
+                result.moved,
+                result.dropped
+            );
+
+            let after = serde_json::json!({
+                "from": from.token,
+                "into": into.token,
+                "moved": result.moved,
+                "dropped": result.dropped,
+            });
+            event::record(AccountEventKind::AccountMerged, from_id, None, &(), &after, state).await;
+            event::record(AccountEventKind::AccountMerged, into_id, None, &(), &after, state).await;
+
+            // every platform is accounted for on its own as well, the same way a single transfer or unlink would be
+            for platform in dropped_platforms.iter() {
+                event::record(AccountEventKind::PlatformUnlinked, from_id, Some(platform), &(), &(), state).await;
+            }
+            let before = serde_json::json!({ "account": from_id });
+            let after = serde_json::json!({ "account": into_id });
+            for platform in moved_platforms.iter() {
+                for side in [from_id, into_id] {
+                    event::record(AccountEventKind::PlatformTransferred, side, Some(platform), &before, &after, state)
+                        .await;
+                }
+            }
+
+            Some(result)
+        }
+        Err(err) => {
+            tracing::error!("Unable to merge account {} into {}: {}", from_id, into_id, err);
+            None
+        }
+    }
+}
+
+/// Inserts a new account with a random token and secret
+pub async fn create(state: &ApplicationState<AccountExtension>) -> Option<NewAccountSecret> {
+    let token = crypto::random_token(ACCOUNT_TOKEN_BYTES);
+    let secret = crypto::random_token(ACCOUNT_SECRET_BYTES);
+    let token_secret = crypto::hash_secret(&secret);
     let timestamp = unix_timestamp();
 
     let active = accounts::ActiveModel {
         id: ActiveValue::NotSet,
-        token: ActiveValue::Set(token.clone()),
-        token_secret: ActiveValue::Set(token_secret.clone()),
+        token: ActiveValue::Set(token),
+        token_secret: ActiveValue::Set(token_secret),
+        legacy_token: ActiveValue::Set(String::new()),
+        legacy_token_secret: ActiveValue::Set(String::new()),
+        subject: ActiveValue::Set(crypto::random_token(ACCOUNT_TOKEN_BYTES)),
         admin: ActiveValue::Set(0),
         timezone: ActiveValue::Set("".to_string()),
         last_login_at: ActiveValue::Set(0),
@@ -80,7 +533,17 @@ pub async fn create(
             .one(&state.database)
             .await;
         if let Ok(model) = model {
-            model
+            let account = model?;
+            event::record(
+                AccountEventKind::AccountCreated,
+                account.id,
+                None,
+                &(),
+                &serde_json::json!({ "token": account.token }),
+                state,
+            )
+            .await;
+            Some(NewAccountSecret { account, secret })
         } else {
             database::log_error(model);
             None
@@ -0,0 +1,242 @@
+use crate::app::crypto;
+use crate::app::extension::AccountExtension;
+use crate::entities::api_clients;
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, tracing};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder};
+use std::str::FromStr;
+
+pub type ApiClient = api_clients::Model;
+
+/// how long a last used timestamp is trusted before it is written again, in seconds
+const LAST_USED_RESOLUTION: i64 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiScope {
+    Search,
+    LinkGenerate,
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiScope::Search => {
+                write!(f, "search")
+            }
+            ApiScope::LinkGenerate => {
+                write!(f, "link-generate")
+            }
+        }
+    }
+}
+
+impl FromStr for ApiScope {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "search" => Ok(ApiScope::Search),
+            "link-generate" => Ok(ApiScope::LinkGenerate),
+            other => Err(format!("Unknown api scope {}", other)),
+        }
+    }
+}
+
+/// A client along with its plain text key. The key is only ever handed back when it is generated
+#[derive(Clone, Debug, Default)]
+pub struct NewApiKey {
+    pub client: ApiClient,
+    pub key: String,
+}
+
+impl ApiClient {
+    pub fn scopes(&self) -> Vec<ApiScope> {
+        self.scopes
+            .split(',')
+            .filter_map(|scope| ApiScope::from_str(scope).ok())
+            .collect()
+    }
+
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes().contains(&scope)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at > 0 && self.expires_at <= unix_timestamp()
+    }
+}
+
+/// Registers a client with the given key. `expires_at` of 0 means the key never expires
+pub async fn create_with_key(
+    name: &str,
+    key: &str,
+    scopes: &[ApiScope],
+    expires_at: i64,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<ApiClient> {
+    let active = api_clients::ActiveModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::Set(name.to_string()),
+        key_hash: ActiveValue::Set(crypto::hash_secret(key)),
+        previous_key_hash: ActiveValue::Set(String::new()),
+        previous_key_expires_at: ActiveValue::Set(0),
+        scopes: ActiveValue::Set(
+            scopes
+                .iter()
+                .map(|scope| scope.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+        ),
+        expires_at: ActiveValue::Set(expires_at),
+        last_used_at: ActiveValue::Set(0),
+        created_at: ActiveValue::Set(unix_timestamp()),
+        updated_at: ActiveValue::Set(0),
+        deleted_at: ActiveValue::Set(0),
+    };
+
+    let query_result = api_clients::Entity::insert(active).exec(&state.database).await;
+    if let Ok(query_result) = query_result {
+        get(query_result.last_insert_id, state).await
+    } else {
+        database::log_error(query_result);
+        None
+    }
+}
+
+/// Registers a client with a freshly generated key
+pub async fn create(
+    name: &str,
+    scopes: &[ApiScope],
+    expires_at: i64,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<NewApiKey> {
+    let key = crypto::random_token(32);
+    let client = create_with_key(name, &key, scopes, expires_at, state).await?;
+    Some(NewApiKey { client, key })
+}
+
+pub async fn get(id: RecordId, state: &ApplicationState<AccountExtension>) -> Option<ApiClient> {
+    let query = api_clients::Entity::find()
+        .filter(
+            Condition::all()
+                .add(api_clients::Column::Id.eq(id))
+                .add(api_clients::Column::DeletedAt.eq(0)),
+        )
+        .one(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+pub async fn all(state: &ApplicationState<AccountExtension>) -> Vec<ApiClient> {
+    let query = api_clients::Entity::find()
+        .filter(api_clients::Column::DeletedAt.eq(0))
+        .order_by_asc(api_clients::Column::Id)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// Finds the client the key belongs to. The previous key of a client keeps working until its grace period runs out.
+/// Expired and revoked clients are never returned. Successful lookups are recorded as the last time the client was used
+pub async fn authenticate(key: &str, state: &ApplicationState<AccountExtension>) -> Option<ApiClient> {
+    if key.is_empty() {
+        return None;
+    }
+
+    let key_hash = crypto::hash_secret(key);
+    let timestamp = unix_timestamp();
+    let query = api_clients::Entity::find()
+        .filter(
+            Condition::all().add(api_clients::Column::DeletedAt.eq(0)).add(
+                Condition::any()
+                    .add(api_clients::Column::KeyHash.eq(key_hash.clone()))
+                    .add(
+                        Condition::all()
+                            .add(api_clients::Column::PreviousKeyHash.eq(key_hash))
+                            .add(api_clients::Column::PreviousKeyExpiresAt.gt(timestamp)),
+                    ),
+            ),
+        )
+        .one(&state.database)
+        .await;
+
+    let client = if let Ok(query) = query {
+        query?
+    } else {
+        database::log_error(query);
+        return None;
+    };
+
+    if client.is_expired() {
+        tracing::info!("Api client {} used an expired key", client.name);
+        return None;
+    }
+
+    if client.last_used_at + LAST_USED_RESOLUTION < timestamp {
+        let query = api_clients::Entity::update_many()
+            .col_expr(api_clients::Column::LastUsedAt, Expr::value(timestamp))
+            .filter(api_clients::Column::Id.eq(client.id))
+            .exec(&state.database)
+            .await;
+        if query.is_err() {
+            database::log_error(query);
+        }
+    }
+
+    Some(client)
+}
+
+/// Issues a new key for the client. The current key keeps working for `grace` seconds so callers can switch over
+pub async fn rotate(client: &ApiClient, grace: i64, state: &ApplicationState<AccountExtension>) -> Option<NewApiKey> {
+    let key = crypto::random_token(32);
+    let timestamp = unix_timestamp();
+    let query = api_clients::Entity::update_many()
+        .col_expr(api_clients::Column::PreviousKeyHash, Expr::value(client.key_hash.clone()))
+        .col_expr(api_clients::Column::PreviousKeyExpiresAt, Expr::value(timestamp + grace))
+        .col_expr(api_clients::Column::KeyHash, Expr::value(crypto::hash_secret(&key)))
+        .col_expr(api_clients::Column::UpdatedAt, Expr::value(timestamp))
+        .filter(api_clients::Column::Id.eq(client.id))
+        .exec(&state.database)
+        .await;
+
+    if query.is_err() {
+        database::log_error(query);
+        return None;
+    }
+
+    let client = get(client.id, state).await?;
+    Some(NewApiKey { client, key })
+}
+
+/// revoked clients stop working immediately
+pub async fn revoke(client: &ApiClient, state: &ApplicationState<AccountExtension>) -> bool {
+    let timestamp = unix_timestamp();
+    let query = api_clients::Entity::update_many()
+        .col_expr(api_clients::Column::DeletedAt, Expr::value(timestamp))
+        .col_expr(api_clients::Column::UpdatedAt, Expr::value(timestamp))
+        .filter(api_clients::Column::Id.eq(client.id))
+        .exec(&state.database)
+        .await;
+
+    if query.is_ok() {
+        true
+    } else {
+        database::log_error(query);
+        false
+    }
+}
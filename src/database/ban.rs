@@ -0,0 +1,128 @@
+use crate::app::extension::AccountExtension;
+use crate::database::account::Account;
+use crate::entities::account_bans;
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, tracing};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder};
+
+/// A ban keeps the account out until it is lifted, a suspension keeps it out until `expires_at`
+pub type AccountBan = account_bans::Model;
+
+impl AccountBan {
+    /// bans never expire on their own, suspensions do
+    pub fn is_permanent(&self) -> bool {
+        self.expires_at == 0
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.lifted_at == 0 && (self.is_permanent() || self.expires_at > unix_timestamp())
+    }
+}
+
+/// Bans the account, or suspends it when `expires_at` is set. `actor` is the account that did it, if any
+pub async fn create(
+    account: &Account,
+    reason: &str,
+    actor: Option<&Account>,
+    expires_at: i64,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<AccountBan> {
+    let active = account_bans::ActiveModel {
+        id: ActiveValue::NotSet,
+        account: ActiveValue::Set(account.id),
+        reason: ActiveValue::Set(reason.to_string()),
+        actor: ActiveValue::Set(actor.map(|actor| actor.id).unwrap_or(0)),
+        expires_at: ActiveValue::Set(expires_at),
+        lifted_by: ActiveValue::Set(0),
+        lifted_at: ActiveValue::Set(0),
+        created_at: ActiveValue::Set(unix_timestamp()),
+        updated_at: ActiveValue::Set(0),
+    };
+
+    let query_result = account_bans::Entity::insert(active).exec(&state.database).await;
+    if let Ok(query_result) = query_result {
+        get(query_result.last_insert_id, state).await
+    } else {
+        database::log_error(query_result);
+        None
+    }
+}
+
+pub async fn get(id: RecordId, state: &ApplicationState<AccountExtension>) -> Option<AccountBan> {
+    let query = account_bans::Entity::find_by_id(id).one(&state.database).await;
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+/// The ban currently keeping the account out. When several apply, a permanent ban wins over the longest suspension
+pub async fn active(account: RecordId, state: &ApplicationState<AccountExtension>) -> Option<AccountBan> {
+    let query = account_bans::Entity::find()
+        .filter(
+            Condition::all()
+                .add(account_bans::Column::Account.eq(account))
+                .add(account_bans::Column::LiftedAt.eq(0))
+                .add(
+                    Condition::any()
+                        .add(account_bans::Column::ExpiresAt.eq(0))
+                        .add(account_bans::Column::ExpiresAt.gt(unix_timestamp())),
+                ),
+        )
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+            .into_iter()
+            .max_by_key(|ban| if ban.is_permanent() { i64::MAX } else { ban.expires_at })
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+/// every ban and suspension the account has had, newest first
+pub async fn by_account(account: &Account, state: &ApplicationState<AccountExtension>) -> Vec<AccountBan> {
+    let query = account_bans::Entity::find()
+        .filter(account_bans::Column::Account.eq(account.id))
+        .order_by_desc(account_bans::Column::CreatedAt)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// Lifts the ban early. Bans that were already lifted are left alone
+pub async fn lift(ban: &AccountBan, actor: Option<&Account>, state: &ApplicationState<AccountExtension>) -> bool {
+    let timestamp = unix_timestamp();
+    let query = account_bans::Entity::update_many()
+        .col_expr(account_bans::Column::LiftedBy, Expr::value(actor.map(|actor| actor.id).unwrap_or(0)))
+        .col_expr(account_bans::Column::LiftedAt, Expr::value(timestamp))
+        .col_expr(account_bans::Column::UpdatedAt, Expr::value(timestamp))
+        .filter(
+            Condition::all()
+                .add(account_bans::Column::Id.eq(ban.id))
+                .add(account_bans::Column::LiftedAt.eq(0)),
+        )
+        .exec(&state.database)
+        .await;
+
+    match query {
+        Ok(result) => result.rows_affected > 0,
+        Err(err) => {
+            tracing::error!("Unable to lift ban {}: {}", ban.id, err);
+            false
+        }
+    }
+}
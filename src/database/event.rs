@@ -0,0 +1,171 @@
+use crate::app::{audit, webhook};
+use crate::app::extension::AccountExtension;
+use crate::database::account;
+use crate::database::platform::AccountPlatform;
+use crate::entities::account_events;
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, tracing};
+use sea_orm::{ActiveValue, ColumnTrait, Condition, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder};
+
+pub type AccountEvent = account_events::Model;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountEventKind {
+    AccountCreated,
+    AccountMerged,
+    AccountRotated,
+    /// legacy credentials were dropped for every account at once, recorded against account 0
+    AccountLegacyDropped,
+    AccountAdminChanged,
+    Login,
+    PlatformLinked,
+    PlatformTransferred,
+    PlatformUnlinked,
+    PlatformTokenRotated,
+    PlatformDataChanged,
+}
+
+impl std::fmt::Display for AccountEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccountEventKind::AccountCreated => {
+                write!(f, "account.created")
+            }
+            AccountEventKind::AccountMerged => {
+                write!(f, "account.merged")
+            }
+            AccountEventKind::AccountRotated => {
+                write!(f, "account.rotated")
+            }
+            AccountEventKind::AccountLegacyDropped => {
+                write!(f, "account.legacy_dropped")
+            }
+            AccountEventKind::AccountAdminChanged => {
+                write!(f, "account.admin_changed")
+            }
+            AccountEventKind::Login => {
+                write!(f, "account.login")
+            }
+            AccountEventKind::PlatformLinked => {
+                write!(f, "platform.linked")
+            }
+            AccountEventKind::PlatformTransferred => {
+                write!(f, "platform.transferred")
+            }
+            AccountEventKind::PlatformUnlinked => {
+                write!(f, "platform.unlinked")
+            }
+            AccountEventKind::PlatformTokenRotated => {
+                write!(f, "platform.token_rotated")
+            }
+            AccountEventKind::PlatformDataChanged => {
+                write!(f, "platform_data.changed")
+            }
+        }
+    }
+}
+
+/// What the audit log can be filtered by. Empty and zero values are ignored
+#[derive(Clone, Debug, Default)]
+pub struct AccountEventFilter {
+    pub account: RecordId,
+    pub platform: String,
+    pub event: String,
+    /// inclusive unix timestamps
+    pub from: i64,
+    pub to: i64,
+}
+
+/// Writes an event to the audit log and queues webhook deliveries for it. The actor and ip come from the request being handled,
+/// see `app::audit`. `before` and `after` are stored as json
+pub async fn record<B: serde::Serialize, A: serde::Serialize>(
+    kind: AccountEventKind,
+    account: RecordId,
+    platform: Option<&AccountPlatform>,
+    before: &B,
+    after: &A,
+    state: &ApplicationState<AccountExtension>,
+) {
+    let before = serde_json::to_value(before).unwrap_or_default();
+    let after = serde_json::to_value(after).unwrap_or_default();
+
+    let context = audit::current();
+    let actor = match account::by_token(&context.actor, state).await {
+        Some(actor) => actor.id,
+        None => 0,
+    };
+
+    let active = account_events::ActiveModel {
+        id: ActiveValue::NotSet,
+        account: ActiveValue::Set(account),
+        event: ActiveValue::Set(kind.to_string()),
+        platform: ActiveValue::Set(platform.map(|platform| platform.platform.clone()).unwrap_or_default()),
+        platform_user: ActiveValue::Set(
+            platform
+                .map(|platform| platform.platform_user.clone())
+                .unwrap_or_default(),
+        ),
+        actor: ActiveValue::Set(actor),
+        ip: ActiveValue::Set(context.ip),
+        before: ActiveValue::Set(before.to_string()),
+        after: ActiveValue::Set(after.to_string()),
+        created_at: ActiveValue::Set(unix_timestamp()),
+    };
+
+    let query = account_events::Entity::insert(active)
+        .exec_without_returning(&state.database)
+        .await;
+    if let Err(err) = query {
+        tracing::error!("Unable to record {} event for account {}: {}", kind, account, err);
+    }
+
+    webhook::notify(kind, account, platform, &before, &after, state).await;
+}
+
+/// A page of events matching the filter, newest first, along with how many matched in total
+pub async fn search(
+    filter: &AccountEventFilter,
+    page: u64,
+    limit: u64,
+    state: &ApplicationState<AccountExtension>,
+) -> (Vec<AccountEvent>, u64) {
+    let mut condition = Condition::all();
+    if filter.account > 0 {
+        condition = condition.add(account_events::Column::Account.eq(filter.account));
+    }
+    if !filter.platform.is_empty() {
+        condition = condition.add(account_events::Column::Platform.eq(filter.platform.as_str()));
+    }
+    if !filter.event.is_empty() {
+        condition = condition.add(account_events::Column::Event.eq(filter.event.as_str()));
+    }
+    if filter.from > 0 {
+        condition = condition.add(account_events::Column::CreatedAt.gte(filter.from));
+    }
+    if filter.to > 0 {
+        condition = condition.add(account_events::Column::CreatedAt.lte(filter.to));
+    }
+
+    let paginator = account_events::Entity::find()
+        .filter(condition)
+        .order_by_desc(account_events::Column::Id)
+        .paginate(&state.database, limit);
+
+    let total = paginator.num_items().await;
+    let total = if let Ok(total) = total {
+        total
+    } else {
+        database::log_error(total);
+        return (Vec::new(), 0);
+    };
+
+    let events = paginator.fetch_page(page).await;
+    if let Ok(events) = events {
+        (events, total)
+    } else {
+        database::log_error(events);
+        (Vec::new(), total)
+    }
+}
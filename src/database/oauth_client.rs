@@ -0,0 +1,109 @@
+use crate::app::crypto;
+use crate::app::extension::AccountExtension;
+use crate::entities::oauth_clients;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, tracing};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter};
+
+pub type OAuthClient = oauth_clients::Model;
+
+/// length of the hex sha256 secrets are stored as. Anything else is a secret from before they were hashed, encrypted with `account.token_key`
+const SECRET_HASH_LENGTH: usize = 64;
+
+/// A registered client along with its plain text secret. The secret is only ever handed back when the client is created
+#[derive(Clone, Debug, Default)]
+pub struct NewOAuthClient {
+    pub client: OAuthClient,
+    pub client_secret: String,
+}
+
+impl OAuthClient {
+    /// redirect uris are stored whitespace separated and must match exactly
+    pub fn allows_redirect(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris.split_whitespace().any(|uri| uri == redirect_uri)
+    }
+}
+
+/// Registers a downstream service that can log users in through us. Only the hash of the secret is stored
+pub async fn create(
+    name: &str,
+    redirect_uris: &[String],
+    state: &ApplicationState<AccountExtension>,
+) -> Option<NewOAuthClient> {
+    let client_id = crypto::random_token(16);
+    let client_secret = crypto::random_token(32);
+
+    let active = oauth_clients::ActiveModel {
+        id: ActiveValue::NotSet,
+        client_id: ActiveValue::Set(client_id.clone()),
+        client_secret: ActiveValue::Set(crypto::hash_secret(&client_secret)),
+        name: ActiveValue::Set(name.to_string()),
+        redirect_uris: ActiveValue::Set(redirect_uris.join(" ")),
+        created_at: ActiveValue::Set(unix_timestamp()),
+        updated_at: ActiveValue::Set(0),
+        deleted_at: ActiveValue::Set(0),
+    };
+
+    let query_result = oauth_clients::Entity::insert(active).exec(&state.database).await;
+    if let Err(err) = query_result {
+        tracing::error!("Unable to register oauth client {}: {}", name, err);
+        return None;
+    }
+
+    get(&client_id, state).await.map(|client| NewOAuthClient { client, client_secret })
+}
+
+pub async fn get(client_id: &str, state: &ApplicationState<AccountExtension>) -> Option<OAuthClient> {
+    let query = oauth_clients::Entity::find()
+        .filter(
+            Condition::all()
+                .add(oauth_clients::Column::ClientId.eq(client_id))
+                .add(oauth_clients::Column::DeletedAt.eq(0)),
+        )
+        .one(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+/// Looks up the client and makes sure the secret it presented is the one we issued.
+/// Clients registered while secrets were still encrypted get their secret hashed the first time they authenticate
+pub async fn authenticate(
+    client_id: &str,
+    client_secret: &str,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<OAuthClient> {
+    if client_secret.is_empty() {
+        return None;
+    }
+
+    let client = get(client_id, state).await?;
+    let secret_hash = crypto::hash_secret(client_secret);
+    if client.client_secret.len() == SECRET_HASH_LENGTH {
+        return crypto::constant_eq(&secret_hash, &client.client_secret).then_some(client);
+    }
+
+    let expected = crypto::decrypt(&client.client_secret, &state.extension.token_key)?;
+    if !crypto::constant_eq(client_secret, &expected) {
+        return None;
+    }
+
+    let query = oauth_clients::Entity::update_many()
+        .col_expr(oauth_clients::Column::ClientSecret, Expr::value(secret_hash))
+        .col_expr(oauth_clients::Column::UpdatedAt, Expr::value(unix_timestamp()))
+        .filter(oauth_clients::Column::Id.eq(client.id))
+        .exec(&state.database)
+        .await;
+    if query.is_err() {
+        database::log_error(query);
+    }
+
+    get(client_id, state).await
+}
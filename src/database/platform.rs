@@ -1,19 +1,27 @@
 use crate::app::extension::AccountExtension;
-use crate::database::account::Account;
-use crate::entities::{account_platform_data, account_platforms, accounts};
+use crate::app::crypto;
+use crate::database::account::{Account, ACCOUNT_TOKEN_BYTES};
+use crate::database::event::{self, AccountEventKind};
+use crate::database::platform_tokens;
+use crate::entities::{account_platform_data, account_platform_tokens, account_platforms, accounts};
 use levelcrush::alias::RecordId;
 use levelcrush::app::ApplicationState;
 use levelcrush::util::unix_timestamp;
-use levelcrush::{database, md5};
+use levelcrush::{database, tracing};
+use sea_orm::sea_query::Expr;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, EntityTrait, FromQueryResult, Iterable,
-    JoinType, Order, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DbErr, EntityTrait, FromQueryResult, Iterable,
+    JoinType, Order, QueryFilter, QueryOrder, QuerySelect, RelationTrait, TransactionTrait,
 };
+use std::collections::HashMap;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AccountPlatformType {
     Discord,
     Twitch,
     Bungie,
+    Steam,
+    BattleNet,
 }
 
 impl std::fmt::Display for AccountPlatformType {
@@ -28,6 +36,27 @@ impl std::fmt::Display for AccountPlatformType {
             AccountPlatformType::Bungie => {
                 write!(f, "bungie")
             }
+            AccountPlatformType::Steam => {
+                write!(f, "steam")
+            }
+            AccountPlatformType::BattleNet => {
+                write!(f, "battlenet")
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for AccountPlatformType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "discord" => Ok(AccountPlatformType::Discord),
+            "twitch" => Ok(AccountPlatformType::Twitch),
+            "bungie" => Ok(AccountPlatformType::Bungie),
+            "steam" => Ok(AccountPlatformType::Steam),
+            "battlenet" => Ok(AccountPlatformType::BattleNet),
+            other => Err(format!("Unknown platform {}", other)),
         }
     }
 }
@@ -41,7 +70,7 @@ pub struct NewAccountPlatform {
 
 #[derive(Debug, Clone, FromQueryResult)]
 struct AccountPlatformNeedUpdate {
-    pub discord_id: String,
+    pub platform_user: String,
 }
 
 pub type AccountPlatform = account_platforms::Model;
@@ -51,13 +80,7 @@ pub async fn create(
     new_platform: NewAccountPlatform,
     state: &ApplicationState<AccountExtension>,
 ) -> Option<AccountPlatform> {
-    let token_seed = format!(
-        "{}||{}||{}",
-        new_platform.platform,
-        new_platform.platform_user.clone(),
-        unix_timestamp()
-    );
-    let token = format!("{:x}", md5::compute(token_seed));
+    let token = crypto::random_token(ACCOUNT_TOKEN_BYTES);
     let platform = new_platform.platform.to_string();
     let platform_user = new_platform.platform_user;
     let timestamp = unix_timestamp();
@@ -86,6 +109,17 @@ pub async fn create(
             .await;
 
         if let Ok(platform_result) = platform_result {
+            if let Some(account_platform) = platform_result.as_ref() {
+                event::record(
+                    AccountEventKind::PlatformLinked,
+                    account_platform.account,
+                    Some(account_platform),
+                    &(),
+                    &(),
+                    state,
+                )
+                .await;
+            }
             platform_result
         } else {
             database::log_error(platform_result);
@@ -129,6 +163,106 @@ pub async fn from_account(
     }
 }
 
+/// every platform currently linked to the account
+pub async fn all_from_account(account: &Account, state: &ApplicationState<AccountExtension>) -> Vec<AccountPlatform> {
+    let query_result = account_platforms::Entity::find()
+        .filter(
+            Condition::all()
+                .add(account_platforms::Column::Account.eq(account.id))
+                .add(account_platforms::Column::DeletedAt.eq(0)),
+        )
+        .order_by_asc(account_platforms::Column::Platform)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query_result) = query_result {
+        query_result
+    } else {
+        database::log_error(query_result);
+        Vec::new()
+    }
+}
+
+/// every platform currently linked to any of the accounts, keyed by account
+pub async fn all_from_accounts(
+    accounts: &[RecordId],
+    state: &ApplicationState<AccountExtension>,
+) -> HashMap<RecordId, Vec<AccountPlatform>> {
+    let mut results = HashMap::new();
+    if accounts.is_empty() {
+        return results;
+    }
+
+    let query_result = account_platforms::Entity::find()
+        .filter(
+            Condition::all()
+                .add(account_platforms::Column::Account.is_in(accounts.to_vec()))
+                .add(account_platforms::Column::DeletedAt.eq(0)),
+        )
+        .order_by_asc(account_platforms::Column::Platform)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query_result) = query_result {
+        for account_platform in query_result.into_iter() {
+            results
+                .entry(account_platform.account)
+                .or_insert_with(Vec::new)
+                .push(account_platform);
+        }
+    } else {
+        database::log_error(query_result);
+    }
+
+    results
+}
+
+/// Platforms that still have the md5 hex token they were created with, a page at a time
+pub async fn legacy(limit: u64, state: &ApplicationState<AccountExtension>) -> Vec<AccountPlatform> {
+    let query_result = account_platforms::Entity::find()
+        .filter(Expr::cust("token REGEXP '^[0-9a-f]{32}$'"))
+        .order_by_asc(account_platforms::Column::Id)
+        .limit(limit)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query_result) = query_result {
+        query_result
+    } else {
+        database::log_error(query_result);
+        Vec::new()
+    }
+}
+
+/// Replaces the token of the platform with a random one
+pub async fn rotate_token(account_platform: &AccountPlatform, state: &ApplicationState<AccountExtension>) -> bool {
+    let query_result = account_platforms::Entity::update_many()
+        .col_expr(
+            account_platforms::Column::Token,
+            Expr::value(crypto::random_token(ACCOUNT_TOKEN_BYTES)),
+        )
+        .col_expr(account_platforms::Column::UpdatedAt, Expr::value(unix_timestamp()))
+        .filter(account_platforms::Column::Id.eq(account_platform.id))
+        .exec(&state.database)
+        .await;
+
+    if query_result.is_ok() {
+        event::record(
+            AccountEventKind::PlatformTokenRotated,
+            account_platform.account,
+            Some(account_platform),
+            &(),
+            &(),
+            state,
+        )
+        .await;
+        true
+    } else {
+        database::log_error(query_result);
+        false
+    }
+}
+
 /// Based off the provided platform information, attempts to match a platform login with an existing account
 pub async fn match_account(
     platform_user: String,
@@ -216,13 +350,84 @@ pub async fn update(
     }
 }
 
+/// Moves the account platform along with its data and tokens over to another account.
+/// Everything is moved inside a single transaction so the platform never ends up split between two accounts
+pub async fn transfer(
+    account_platform: &AccountPlatform,
+    account: RecordId,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<AccountPlatform> {
+    let platform_id = account_platform.id;
+    let timestamp = unix_timestamp();
+
+    let transaction = state
+        .database
+        .transaction::<_, (), DbErr>(|txn| {
+            Box::pin(async move {
+                account_platforms::Entity::update_many()
+                    .col_expr(account_platforms::Column::Account, Expr::value(account))
+                    .col_expr(account_platforms::Column::UpdatedAt, Expr::value(timestamp))
+                    .filter(account_platforms::Column::Id.eq(platform_id))
+                    .exec(txn)
+                    .await?;
+
+                account_platform_data::Entity::update_many()
+                    .col_expr(account_platform_data::Column::Account, Expr::value(account))
+                    .filter(account_platform_data::Column::Platform.eq(platform_id))
+                    .exec(txn)
+                    .await?;
+
+                account_platform_tokens::Entity::update_many()
+                    .col_expr(account_platform_tokens::Column::Account, Expr::value(account))
+                    .filter(account_platform_tokens::Column::Platform.eq(platform_id))
+                    .exec(txn)
+                    .await?;
+
+                Ok(())
+            })
+        })
+        .await;
+
+    if let Err(err) = transaction {
+        tracing::error!("Unable to transfer account platform {}: {}", platform_id, err);
+        return None;
+    }
+
+    // recorded on both sides so either account's history shows where the platform went
+    let before = serde_json::json!({ "account": account_platform.account });
+    let after = serde_json::json!({ "account": account });
+    for side in [account_platform.account, account] {
+        event::record(
+            AccountEventKind::PlatformTransferred,
+            side,
+            Some(account_platform),
+            &before,
+            &after,
+            state,
+        )
+        .await;
+    }
+
+    let query = account_platforms::Entity::find_by_id(platform_id)
+        .one(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
 /// Unlink an account platfrom by directly deleting the related data tied to the account platform and then remove the account platform record itself as well
 /// This is a permanent operation
 pub async fn unlink(
     account_platform: &AccountPlatform,
     state: &ApplicationState<AccountExtension>,
 ) {
-    // delete platform data first
+    // delete any stored tokens and platform data first
+    platform_tokens::unlink(account_platform, state).await;
     let _ = account_platform_data::Entity::delete_many()
         .filter(account_platform_data::Column::Platform.eq(account_platform.id))
         .exec(&state.database)
@@ -232,9 +437,19 @@ pub async fn unlink(
     let _ = account_platforms::Entity::delete_by_id(account_platform.id)
         .exec(&state.database)
         .await;
+
+    event::record(
+        AccountEventKind::PlatformUnlinked,
+        account_platform.account,
+        Some(account_platform),
+        &(),
+        &(),
+        state,
+    )
+    .await;
 }
 
-/// todo: Determine if this function is even still relevant in the new setup
+/// fetches the platform users of the specified platform that have gone the longest without an update
 pub async fn need_update(
     platform: AccountPlatformType,
     limit: i64,
@@ -244,7 +459,7 @@ pub async fn need_update(
 
     let query = account_platforms::Entity::find()
         .select_only()
-        .column_as(account_platforms::Column::PlatformUser, "discord_id")
+        .column(account_platforms::Column::PlatformUser)
         .filter(account_platforms::Column::Platform.eq(&platform))
         .order_by(account_platforms::Column::UpdatedAt, Order::Asc)
         .limit(limit as u64)
@@ -255,7 +470,7 @@ pub async fn need_update(
     if let Ok(query) = query {
         query
             .into_iter()
-            .map(|record| record.discord_id)
+            .map(|record| record.platform_user)
             .collect::<Vec<String>>()
     } else {
         database::log_error(query);
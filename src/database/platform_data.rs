@@ -1,4 +1,5 @@
 use crate::app::extension::AccountExtension;
+use crate::database::event::{self, AccountEventKind};
 use crate::database::platform::AccountPlatform;
 use crate::entities::{account_platform_data, account_platforms, accounts};
 use levelcrush::app::ApplicationState;
@@ -17,7 +18,7 @@ pub struct AccountPlatformDataSlim {
     pub key: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct NewAccountPlatformData {
     pub key: String,
     pub value: String,
@@ -97,6 +98,35 @@ pub async fn write(
         );
     }
 
+    // the current values, so only keys that actually change end up in the audit log
+    let existing = account_platform_data::Entity::find()
+        .filter(
+            Condition::all()
+                .add(account_platform_data::Column::Platform.eq(account_platform.id))
+                .add(account_platform_data::Column::Key.is_in(keys.clone())),
+        )
+        .all(&state.database)
+        .await;
+    let existing = if let Ok(existing) = existing {
+        existing
+            .into_iter()
+            .map(|record| (record.key, record.value))
+            .collect::<HashMap<String, String>>()
+    } else {
+        database::log_error(existing);
+        HashMap::new()
+    };
+
+    let mut before = HashMap::new();
+    let mut after = HashMap::new();
+    for new_data in values.iter() {
+        let old_value = existing.get(&new_data.key);
+        if old_value != Some(&new_data.value) {
+            before.insert(new_data.key.as_str(), old_value.cloned());
+            after.insert(new_data.key.as_str(), new_data.value.as_str());
+        }
+    }
+
     let query_parameters = query_parameters.join(", ");
     let insert_statement =
         project_str!("queries/account_platform_data_insert.sql", query_parameters);
@@ -112,5 +142,19 @@ pub async fn write(
         .await;
 
     // finally execute the query to update/insert this data
-    database::log_error(query);
+    if query.is_ok() {
+        if !after.is_empty() {
+            event::record(
+                AccountEventKind::PlatformDataChanged,
+                account_platform.account,
+                Some(account_platform),
+                &before,
+                &after,
+                state,
+            )
+            .await;
+        }
+    } else {
+        database::log_error(query);
+    }
 }
@@ -0,0 +1,175 @@
+use crate::app::crypto;
+use crate::app::extension::AccountExtension;
+use crate::app::platform::PlatformTokens;
+use crate::database::platform::{AccountPlatform, AccountPlatformType};
+use crate::entities::{account_platform_tokens, account_platforms};
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, project_str, tracing};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, EntityTrait, Iterable, JoinType, Order, QueryFilter, QueryOrder,
+    QuerySelect, RelationTrait, Statement, Value,
+};
+
+pub type AccountPlatformToken = account_platform_tokens::Model;
+
+/// Encrypts and stores the tokens for the account platform, replacing any tokens that were already stored
+pub async fn write(
+    account_platform: &AccountPlatform,
+    tokens: &PlatformTokens,
+    state: &ApplicationState<AccountExtension>,
+) {
+    let token_key = state.extension.token_key.as_str();
+    if token_key.is_empty() {
+        tracing::warn!("No token key has been set (account.token_key). Platform tokens will not be stored");
+        return;
+    }
+
+    let access_token = crypto::encrypt(&tokens.access_token, token_key);
+    // kept empty instead of encrypted so platforms without refresh tokens can be told apart in queries
+    let refresh_token = if tokens.refresh_token.is_empty() {
+        Some(String::new())
+    } else {
+        crypto::encrypt(&tokens.refresh_token, token_key)
+    };
+    let (access_token, refresh_token) = match (access_token, refresh_token) {
+        (Some(access_token), Some(refresh_token)) => (access_token, refresh_token),
+        _ => return,
+    };
+
+    let timestamp = unix_timestamp();
+    let expires_at = if tokens.expires_in > 0 {
+        timestamp + tokens.expires_in
+    } else {
+        0
+    };
+
+    let query = state
+        .database
+        .execute(Statement::from_sql_and_values(
+            sea_orm::DatabaseBackend::MySql,
+            project_str!("queries/account_platform_tokens_insert.sql"),
+            vec![
+                Value::BigInt(Some(account_platform.account)),
+                Value::BigInt(Some(account_platform.id)),
+                Value::String(Some(Box::new(access_token))),
+                Value::String(Some(Box::new(refresh_token))),
+                Value::String(Some(Box::new(tokens.scopes.join(" ")))),
+                Value::BigInt(Some(expires_at)),
+                Value::BigInt(Some(timestamp)),
+                Value::BigInt(Some(0)),
+                Value::BigInt(Some(0)),
+            ],
+        ))
+        .await;
+
+    database::log_error(query);
+}
+
+/// Reads and decrypts the tokens stored for the account platform
+pub async fn read(
+    account_platform: &AccountPlatform,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<PlatformTokens> {
+    let query_result = account_platform_tokens::Entity::find()
+        .filter(
+            Condition::all()
+                .add(account_platform_tokens::Column::Platform.eq(account_platform.id))
+                .add(account_platform_tokens::Column::DeletedAt.eq(0)),
+        )
+        .one(&state.database)
+        .await;
+
+    let model = if let Ok(model) = query_result {
+        model?
+    } else {
+        database::log_error(query_result);
+        return None;
+    };
+
+    let token_key = state.extension.token_key.as_str();
+    let expires_in = if model.expires_at > 0 {
+        model.expires_at - unix_timestamp()
+    } else {
+        0
+    };
+
+    Some(PlatformTokens {
+        access_token: crypto::decrypt(&model.access_token, token_key)?,
+        refresh_token: if model.refresh_token.is_empty() {
+            String::new()
+        } else {
+            crypto::decrypt(&model.refresh_token, token_key)?
+        },
+        expires_in,
+        scopes: model
+            .scopes
+            .split(' ')
+            .filter(|scope| !scope.is_empty())
+            .map(|scope| scope.to_string())
+            .collect(),
+    })
+}
+
+/// Fetches account platforms of the specified type whose tokens expire before the provided timestamp
+/// Tokens without a known expiration or without a refresh token are skipped since there is nothing to refresh
+pub async fn need_refresh(
+    platform: AccountPlatformType,
+    expires_before: i64,
+    limit: i64,
+    state: &ApplicationState<AccountExtension>,
+) -> Vec<AccountPlatform> {
+    let platform = platform.to_string();
+
+    let query = account_platform_tokens::Entity::find()
+        .select_only()
+        .columns(account_platforms::Column::iter())
+        .join(
+            JoinType::InnerJoin,
+            account_platform_tokens::Relation::AccountPlatforms.def(),
+        )
+        .filter(
+            Condition::all()
+                .add(account_platforms::Column::Platform.eq(&platform))
+                .add(account_platform_tokens::Column::DeletedAt.eq(0))
+                .add(account_platform_tokens::Column::RefreshToken.ne(""))
+                .add(account_platform_tokens::Column::ExpiresAt.gt(0))
+                .add(account_platform_tokens::Column::ExpiresAt.lt(expires_before)),
+        )
+        .order_by(account_platform_tokens::Column::ExpiresAt, Order::Asc)
+        .limit(limit as u64)
+        .into_model::<account_platforms::Model>()
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// Forgets when the tokens of the account platform expire, which takes them out of `need_refresh`.
+/// Used once refreshing them failed so they do not sit at the front of every batch
+pub async fn stop_refresh(account_platform: &AccountPlatform, state: &ApplicationState<AccountExtension>) {
+    let query = account_platform_tokens::Entity::update_many()
+        .col_expr(account_platform_tokens::Column::ExpiresAt, Expr::value(0))
+        .col_expr(account_platform_tokens::Column::UpdatedAt, Expr::value(unix_timestamp()))
+        .filter(account_platform_tokens::Column::Platform.eq(account_platform.id))
+        .exec(&state.database)
+        .await;
+
+    database::log_error(query);
+}
+
+/// Removes any tokens stored for the account platform
+pub async fn unlink(account_platform: &AccountPlatform, state: &ApplicationState<AccountExtension>) {
+    let query = account_platform_tokens::Entity::delete_many()
+        .filter(account_platform_tokens::Column::Platform.eq(account_platform.id))
+        .exec(&state.database)
+        .await;
+
+    database::log_error(query);
+}
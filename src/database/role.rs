@@ -0,0 +1,330 @@
+use crate::app::extension::AccountExtension;
+use crate::database::account::Account;
+use crate::entities::{account_roles, role_permissions, roles};
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, tracing};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub type Role = roles::Model;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Permission {
+    /// look up accounts along with their platforms, roles and sessions
+    AccountsView,
+    /// change accounts on behalf of their owners
+    AccountsManage,
+    /// approve or deny discord transfers
+    TransfersReview,
+    /// create roles and hand them out
+    RolesManage,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 4] = [
+        Permission::AccountsView,
+        Permission::AccountsManage,
+        Permission::TransfersReview,
+        Permission::RolesManage,
+    ];
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::AccountsView => {
+                write!(f, "accounts.view")
+            }
+            Permission::AccountsManage => {
+                write!(f, "accounts.manage")
+            }
+            Permission::TransfersReview => {
+                write!(f, "transfers.review")
+            }
+            Permission::RolesManage => {
+                write!(f, "roles.manage")
+            }
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "accounts.view" => Ok(Permission::AccountsView),
+            "accounts.manage" => Ok(Permission::AccountsManage),
+            "transfers.review" => Ok(Permission::TransfersReview),
+            "roles.manage" => Ok(Permission::RolesManage),
+            other => Err(format!("Unknown permission {}", other)),
+        }
+    }
+}
+
+/// The roles every install starts out with
+pub const DEFAULT_ROLES: [(&str, &str, &[Permission]); 3] = [
+    (
+        "support",
+        "Helps members with their accounts",
+        &[Permission::AccountsView],
+    ),
+    (
+        "moderator",
+        "Reviews discord transfers",
+        &[Permission::AccountsView, Permission::TransfersReview],
+    ),
+    ("admin", "Everything", &Permission::ALL),
+];
+
+/// A role along with what it allows
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct RoleView {
+    pub id: RecordId,
+    pub name: String,
+    pub description: String,
+    pub permissions: Vec<String>,
+}
+
+pub async fn get(id: RecordId, state: &ApplicationState<AccountExtension>) -> Option<Role> {
+    let query = roles::Entity::find_by_id(id).one(&state.database).await;
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+pub async fn by_name(name: &str, state: &ApplicationState<AccountExtension>) -> Option<Role> {
+    let query = roles::Entity::find()
+        .filter(roles::Column::Name.eq(name.trim().to_lowercase()))
+        .one(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+/// Creates the role with the permissions. Names are stored lowercase
+pub async fn create(
+    name: &str,
+    description: &str,
+    permissions: &[Permission],
+    state: &ApplicationState<AccountExtension>,
+) -> Option<Role> {
+    let timestamp = unix_timestamp();
+    let active = roles::ActiveModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::Set(name.trim().to_lowercase()),
+        description: ActiveValue::Set(description.to_string()),
+        created_at: ActiveValue::Set(timestamp),
+        updated_at: ActiveValue::Set(0),
+    };
+
+    let query_result = roles::Entity::insert(active).exec(&state.database).await;
+    let role = if let Ok(query_result) = query_result {
+        get(query_result.last_insert_id, state).await?
+    } else {
+        database::log_error(query_result);
+        return None;
+    };
+
+    if !permissions.is_empty() {
+        let models = permissions.iter().map(|permission| role_permissions::ActiveModel {
+            id: ActiveValue::NotSet,
+            role: ActiveValue::Set(role.id),
+            permission: ActiveValue::Set(permission.to_string()),
+            created_at: ActiveValue::Set(timestamp),
+        });
+
+        let query = role_permissions::Entity::insert_many(models)
+            .exec_without_returning(&state.database)
+            .await;
+        if query.is_err() {
+            database::log_error(query);
+        }
+    }
+
+    Some(role)
+}
+
+/// Creates any of the default roles that are missing
+pub async fn seed(state: &ApplicationState<AccountExtension>) {
+    for (name, description, permissions) in DEFAULT_ROLES.iter() {
+        if by_name(name, state).await.is_none() {
+            tracing::info!("Creating the {} role", name);
+            create(name, description, permissions, state).await;
+        }
+    }
+}
+
+/// every role with its permissions, in alphabetical order
+pub async fn all(state: &ApplicationState<AccountExtension>) -> Vec<RoleView> {
+    let query = roles::Entity::find()
+        .order_by_asc(roles::Column::Name)
+        .all(&state.database)
+        .await;
+
+    let roles = if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        return Vec::new();
+    };
+
+    views(roles, state).await
+}
+
+/// the roles handed out to the account
+pub async fn by_account(account: &Account, state: &ApplicationState<AccountExtension>) -> Vec<RoleView> {
+    let query = account_roles::Entity::find()
+        .filter(account_roles::Column::Account.eq(account.id))
+        .all(&state.database)
+        .await;
+
+    let role_ids = if let Ok(query) = query {
+        query.into_iter().map(|account_role| account_role.role).collect::<Vec<RecordId>>()
+    } else {
+        database::log_error(query);
+        return Vec::new();
+    };
+
+    if role_ids.is_empty() {
+        return Vec::new();
+    }
+
+    let query = roles::Entity::find()
+        .filter(roles::Column::Id.is_in(role_ids))
+        .order_by_asc(roles::Column::Name)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        views(query, state).await
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// Everything the account is allowed to do through its roles. Accounts flagged as admin are allowed everything
+pub async fn permissions(account: &Account, state: &ApplicationState<AccountExtension>) -> Vec<Permission> {
+    if account.admin == 1 {
+        return Permission::ALL.to_vec();
+    }
+
+    let granted = by_account(account, state)
+        .await
+        .into_iter()
+        .flat_map(|role| role.permissions)
+        .filter_map(|permission| Permission::from_str(&permission).ok())
+        .collect::<Vec<Permission>>();
+
+    Permission::ALL
+        .into_iter()
+        .filter(|permission| granted.contains(permission))
+        .collect()
+}
+
+pub async fn has_permission(
+    account: &Account,
+    permission: Permission,
+    state: &ApplicationState<AccountExtension>,
+) -> bool {
+    permissions(account, state).await.contains(&permission)
+}
+
+/// Hands the role to the account. Assigning a role the account already has is a no-op
+pub async fn assign(
+    account: &Account,
+    role: &Role,
+    granted_by: Option<&Account>,
+    state: &ApplicationState<AccountExtension>,
+) -> bool {
+    let active = account_roles::ActiveModel {
+        id: ActiveValue::NotSet,
+        account: ActiveValue::Set(account.id),
+        role: ActiveValue::Set(role.id),
+        granted_by: ActiveValue::Set(granted_by.map(|granted_by| granted_by.id).unwrap_or(0)),
+        created_at: ActiveValue::Set(unix_timestamp()),
+    };
+
+    let query = account_roles::Entity::insert(active)
+        .on_conflict(
+            OnConflict::columns([account_roles::Column::Account, account_roles::Column::Role])
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(&state.database)
+        .await;
+
+    if query.is_ok() {
+        true
+    } else {
+        database::log_error(query);
+        false
+    }
+}
+
+/// Takes the role away from the account. Returns false when the account did not have it
+pub async fn unassign(account: &Account, role: &Role, state: &ApplicationState<AccountExtension>) -> bool {
+    let query = account_roles::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(account_roles::Column::Account.eq(account.id))
+                .add(account_roles::Column::Role.eq(role.id)),
+        )
+        .exec(&state.database)
+        .await;
+
+    match query {
+        Ok(result) => result.rows_affected > 0,
+        Err(err) => {
+            tracing::error!("Unable to take role {} from account {}: {}", role.name, account.id, err);
+            false
+        }
+    }
+}
+
+/// attaches the permissions to each role
+async fn views(roles: Vec<Role>, state: &ApplicationState<AccountExtension>) -> Vec<RoleView> {
+    let role_ids = roles.iter().map(|role| role.id).collect::<Vec<RecordId>>();
+    let mut permissions = HashMap::new();
+    if !role_ids.is_empty() {
+        let query = role_permissions::Entity::find()
+            .filter(role_permissions::Column::Role.is_in(role_ids))
+            .order_by_asc(role_permissions::Column::Permission)
+            .all(&state.database)
+            .await;
+
+        if let Ok(query) = query {
+            for role_permission in query.into_iter() {
+                permissions
+                    .entry(role_permission.role)
+                    .or_insert_with(Vec::new)
+                    .push(role_permission.permission);
+            }
+        } else {
+            database::log_error(query);
+        }
+    }
+
+    roles
+        .into_iter()
+        .map(|role| RoleView {
+            permissions: permissions.remove(&role.id).unwrap_or_default(),
+            id: role.id,
+            name: role.name,
+            description: role.description,
+        })
+        .collect()
+}
@@ -0,0 +1,204 @@
+use crate::app::extension::AccountExtension;
+use crate::database::account::Account;
+use crate::entities::account_sessions;
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, tracing};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder};
+
+pub type AccountSession = account_sessions::Model;
+
+/// Everything the session store writes for a session
+#[derive(Clone, Debug, Default)]
+pub struct SessionRecord {
+    pub session_id: String,
+    /// 0 until someone logs in with the session
+    pub account: RecordId,
+    /// the serialized session
+    pub data: String,
+    pub user_agent: String,
+    pub ip: String,
+    /// 0 when the session never expires
+    pub expires_at: i64,
+    pub last_seen_at: i64,
+}
+
+/// loads the session if it exists and has not expired
+pub async fn load(session_id: &str, state: &ApplicationState<AccountExtension>) -> Option<AccountSession> {
+    let query = account_sessions::Entity::find()
+        .filter(
+            Condition::all()
+                .add(account_sessions::Column::SessionId.eq(session_id))
+                .add(
+                    Condition::any()
+                        .add(account_sessions::Column::ExpiresAt.eq(0))
+                        .add(account_sessions::Column::ExpiresAt.gt(unix_timestamp())),
+                ),
+        )
+        .one(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+/// inserts the session or updates it when it already exists
+pub async fn store(record: SessionRecord, state: &ApplicationState<AccountExtension>) -> bool {
+    let timestamp = unix_timestamp();
+    let active = account_sessions::ActiveModel {
+        id: ActiveValue::NotSet,
+        session_id: ActiveValue::Set(record.session_id),
+        account: ActiveValue::Set(record.account),
+        data: ActiveValue::Set(record.data),
+        user_agent: ActiveValue::Set(record.user_agent),
+        ip: ActiveValue::Set(record.ip),
+        expires_at: ActiveValue::Set(record.expires_at),
+        last_seen_at: ActiveValue::Set(record.last_seen_at),
+        created_at: ActiveValue::Set(timestamp),
+        updated_at: ActiveValue::Set(timestamp),
+    };
+
+    let query = account_sessions::Entity::insert(active)
+        .on_conflict(
+            OnConflict::column(account_sessions::Column::SessionId)
+                .update_columns([
+                    account_sessions::Column::Account,
+                    account_sessions::Column::Data,
+                    account_sessions::Column::UserAgent,
+                    account_sessions::Column::Ip,
+                    account_sessions::Column::ExpiresAt,
+                    account_sessions::Column::LastSeenAt,
+                    account_sessions::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(&state.database)
+        .await;
+
+    if query.is_ok() {
+        true
+    } else {
+        database::log_error(query);
+        false
+    }
+}
+
+pub async fn destroy(session_id: &str, state: &ApplicationState<AccountExtension>) -> bool {
+    let query = account_sessions::Entity::delete_many()
+        .filter(account_sessions::Column::SessionId.eq(session_id))
+        .exec(&state.database)
+        .await;
+
+    if query.is_ok() {
+        true
+    } else {
+        database::log_error(query);
+        false
+    }
+}
+
+/// removes every session, logging everyone out
+pub async fn clear(state: &ApplicationState<AccountExtension>) -> bool {
+    let query = account_sessions::Entity::delete_many().exec(&state.database).await;
+
+    if query.is_ok() {
+        true
+    } else {
+        database::log_error(query);
+        false
+    }
+}
+
+/// the live sessions of an account, most recently seen first
+pub async fn by_account(account: &Account, state: &ApplicationState<AccountExtension>) -> Vec<AccountSession> {
+    let query = account_sessions::Entity::find()
+        .filter(
+            Condition::all()
+                .add(account_sessions::Column::Account.eq(account.id))
+                .add(
+                    Condition::any()
+                        .add(account_sessions::Column::ExpiresAt.eq(0))
+                        .add(account_sessions::Column::ExpiresAt.gt(unix_timestamp())),
+                ),
+        )
+        .order_by_desc(account_sessions::Column::LastSeenAt)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// Removes a session of the account. Returns false when the session does not belong to the account
+pub async fn revoke(id: RecordId, account: &Account, state: &ApplicationState<AccountExtension>) -> bool {
+    let query = account_sessions::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(account_sessions::Column::Id.eq(id))
+                .add(account_sessions::Column::Account.eq(account.id)),
+        )
+        .exec(&state.database)
+        .await;
+
+    match query {
+        Ok(result) => result.rows_affected > 0,
+        Err(err) => {
+            tracing::error!("Unable to revoke session {}: {}", id, err);
+            false
+        }
+    }
+}
+
+/// Removes every session of the account except the one with `keep_session_id`, an empty id removes all of them. Returns how many were removed
+pub async fn revoke_others(
+    account: &Account,
+    keep_session_id: &str,
+    state: &ApplicationState<AccountExtension>,
+) -> u64 {
+    let query = account_sessions::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(account_sessions::Column::Account.eq(account.id))
+                .add(account_sessions::Column::SessionId.ne(keep_session_id)),
+        )
+        .exec(&state.database)
+        .await;
+
+    match query {
+        Ok(result) => result.rows_affected,
+        Err(err) => {
+            tracing::error!("Unable to revoke sessions of account {}: {}", account.id, err);
+            0
+        }
+    }
+}
+
+/// removes expired sessions
+pub async fn prune(state: &ApplicationState<AccountExtension>) -> u64 {
+    let query = account_sessions::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(account_sessions::Column::ExpiresAt.gt(0))
+                .add(account_sessions::Column::ExpiresAt.lte(unix_timestamp())),
+        )
+        .exec(&state.database)
+        .await;
+
+    match query {
+        Ok(result) => result.rows_affected,
+        Err(err) => {
+            tracing::error!("Unable to prune sessions: {}", err);
+            0
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crate::app::crypto;
+use crate::app::extension::AccountExtension;
+use crate::entities::signing_keys;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, tracing};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder};
+
+pub type SigningKey = signing_keys::Model;
+
+pub const SIGNING_ALGORITHM: &str = "ES256";
+
+/// how long a retired key stays published after rotation, in seconds.
+/// Has to outlive every token signed with it so consumers can still verify them
+pub const RETIRED_KEY_GRACE: i64 = 86400;
+
+/// Generates and stores a new signing key. The private key is encrypted with `account.token_key`
+pub async fn create(state: &ApplicationState<AccountExtension>) -> Option<SigningKey> {
+    let token_key = state.extension.token_key.as_str();
+    if token_key.is_empty() {
+        tracing::warn!("No token key has been set (account.token_key). Unable to create signing keys");
+        return None;
+    }
+
+    let key_pair = crypto::generate_es256()?;
+    let kid = crypto::random_token(16);
+    let active = signing_keys::ActiveModel {
+        id: ActiveValue::NotSet,
+        kid: ActiveValue::Set(kid),
+        algorithm: ActiveValue::Set(SIGNING_ALGORITHM.to_string()),
+        private_key: ActiveValue::Set(crypto::encrypt(&key_pair.private_pem, token_key)?),
+        public_x: ActiveValue::Set(key_pair.x),
+        public_y: ActiveValue::Set(key_pair.y),
+        retired_at: ActiveValue::Set(0),
+        created_at: ActiveValue::Set(unix_timestamp()),
+        updated_at: ActiveValue::Set(0),
+        deleted_at: ActiveValue::Set(0),
+    };
+
+    let query_result = signing_keys::Entity::insert(active).exec(&state.database).await;
+    if let Ok(query_result) = query_result {
+        let query = signing_keys::Entity::find_by_id(query_result.last_insert_id)
+            .one(&state.database)
+            .await;
+        if let Ok(query) = query {
+            query
+        } else {
+            database::log_error(query);
+            None
+        }
+    } else {
+        database::log_error(query_result);
+        None
+    }
+}
+
+/// Keys consumers should trust, the active key first followed by keys retired within the grace period
+pub async fn published(state: &ApplicationState<AccountExtension>) -> Vec<SigningKey> {
+    let query = signing_keys::Entity::find()
+        .filter(
+            Condition::all().add(signing_keys::Column::DeletedAt.eq(0)).add(
+                Condition::any()
+                    .add(signing_keys::Column::RetiredAt.eq(0))
+                    .add(signing_keys::Column::RetiredAt.gt(unix_timestamp() - RETIRED_KEY_GRACE)),
+            ),
+        )
+        .order_by_asc(signing_keys::Column::RetiredAt)
+        .order_by_desc(signing_keys::Column::Id)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// Creates a new active key and retires every other key. Retired keys keep being published for `RETIRED_KEY_GRACE`
+pub async fn rotate(state: &ApplicationState<AccountExtension>) -> Option<SigningKey> {
+    let key = create(state).await?;
+
+    let timestamp = unix_timestamp();
+    let query = signing_keys::Entity::update_many()
+        .col_expr(signing_keys::Column::RetiredAt, Expr::value(timestamp))
+        .col_expr(signing_keys::Column::UpdatedAt, Expr::value(timestamp))
+        .filter(
+            Condition::all()
+                .add(signing_keys::Column::RetiredAt.eq(0))
+                .add(signing_keys::Column::Id.ne(key.id)),
+        )
+        .exec(&state.database)
+        .await;
+
+    if query.is_err() {
+        database::log_error(query);
+    }
+
+    Some(key)
+}
+
+/// the decrypted pem of the private key
+pub fn private_key(key: &SigningKey, state: &ApplicationState<AccountExtension>) -> Option<String> {
+    crypto::decrypt(&key.private_key, &state.extension.token_key)
+}
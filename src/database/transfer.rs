@@ -0,0 +1,294 @@
+use crate::app::extension::AccountExtension;
+use crate::database::account::{self, Account};
+use crate::database::event::{self, AccountEventKind};
+use crate::database::platform::AccountPlatformType;
+use crate::database::session;
+use crate::entities::{account_platform_data, account_platform_tokens, account_platforms, account_transfers};
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, tracing};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveValue, ColumnTrait, Condition, DbErr, EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use std::collections::HashMap;
+
+pub type AccountTransfer = account_transfers::Model;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferStatus {
+    Pending,
+    Approved,
+    Denied,
+}
+
+impl std::fmt::Display for TransferStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferStatus::Pending => {
+                write!(f, "pending")
+            }
+            TransferStatus::Approved => {
+                write!(f, "approved")
+            }
+            TransferStatus::Denied => {
+                write!(f, "denied")
+            }
+        }
+    }
+}
+
+/// Records a request to move the discord identity of `account` over to the discord user that `requested_by` logged in with
+pub async fn create(
+    account: &Account,
+    requested_by: &Account,
+    old_platform_user: &str,
+    new_platform_user: &str,
+    reason: &str,
+    state: &ApplicationState<AccountExtension>,
+) -> Option<AccountTransfer> {
+    let active = account_transfers::ActiveModel {
+        id: ActiveValue::NotSet,
+        account: ActiveValue::Set(account.id),
+        requested_by: ActiveValue::Set(requested_by.id),
+        platform: ActiveValue::Set(AccountPlatformType::Discord.to_string()),
+        old_platform_user: ActiveValue::Set(old_platform_user.to_string()),
+        new_platform_user: ActiveValue::Set(new_platform_user.to_string()),
+        archived_data: ActiveValue::Set(String::new()),
+        reason: ActiveValue::Set(reason.to_string()),
+        status: ActiveValue::Set(TransferStatus::Pending.to_string()),
+        reviewed_by: ActiveValue::Set(0),
+        reviewed_at: ActiveValue::Set(0),
+        created_at: ActiveValue::Set(unix_timestamp()),
+        updated_at: ActiveValue::Set(0),
+        deleted_at: ActiveValue::Set(0),
+    };
+
+    let query_result = account_transfers::Entity::insert(active).exec(&state.database).await;
+    if let Ok(query_result) = query_result {
+        get(query_result.last_insert_id, state).await
+    } else {
+        database::log_error(query_result);
+        None
+    }
+}
+
+pub async fn get(id: RecordId, state: &ApplicationState<AccountExtension>) -> Option<AccountTransfer> {
+    let query = account_transfers::Entity::find_by_id(id).one(&state.database).await;
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+/// every transfer that is still waiting on an admin, oldest first
+pub async fn pending(state: &ApplicationState<AccountExtension>) -> Vec<AccountTransfer> {
+    let query = account_transfers::Entity::find()
+        .filter(
+            Condition::all()
+                .add(account_transfers::Column::Status.eq(TransferStatus::Pending.to_string()))
+                .add(account_transfers::Column::DeletedAt.eq(0)),
+        )
+        .order_by_asc(account_transfers::Column::CreatedAt)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+pub async fn deny(transfer: &AccountTransfer, reviewer: &Account, state: &ApplicationState<AccountExtension>) -> bool {
+    let timestamp = unix_timestamp();
+    let query = account_transfers::Entity::update_many()
+        .col_expr(account_transfers::Column::Status, Expr::value(TransferStatus::Denied.to_string()))
+        .col_expr(account_transfers::Column::ReviewedBy, Expr::value(reviewer.id))
+        .col_expr(account_transfers::Column::ReviewedAt, Expr::value(timestamp))
+        .col_expr(account_transfers::Column::UpdatedAt, Expr::value(timestamp))
+        .filter(account_transfers::Column::Id.eq(transfer.id))
+        .exec(&state.database)
+        .await;
+
+    if query.is_ok() {
+        true
+    } else {
+        database::log_error(query);
+        false
+    }
+}
+
+/// Points the discord platform of the transferred account at the new discord user.
+///
+/// The platform data and tokens of the old discord user are archived on the transfer and removed, they get rewritten on the next login.
+/// The discord platform the requesting account was created with is removed so the new discord user only belongs to one account,
+/// then whatever else the requesting account linked in the meantime is merged into the transferred account
+pub async fn approve(
+    transfer: &AccountTransfer,
+    reviewer: &Account,
+    state: &ApplicationState<AccountExtension>,
+) -> bool {
+    if transfer.status != TransferStatus::Pending.to_string() {
+        tracing::warn!("Transfer {} has already been reviewed", transfer.id);
+        return false;
+    }
+
+    let platforms = account_platforms::Entity::find()
+        .filter(
+            Condition::all()
+                .add(account_platforms::Column::Platform.eq(&transfer.platform))
+                .add(account_platforms::Column::PlatformUser.is_in([
+                    transfer.old_platform_user.clone(),
+                    transfer.new_platform_user.clone(),
+                ])),
+        )
+        .all(&state.database)
+        .await;
+
+    let platforms = if let Ok(platforms) = platforms {
+        platforms
+    } else {
+        database::log_error(platforms);
+        return false;
+    };
+
+    let old_platform = platforms
+        .iter()
+        .find(|platform| platform.platform_user == transfer.old_platform_user && platform.account == transfer.account);
+    let old_platform = match old_platform {
+        Some(old_platform) => old_platform.clone(),
+        None => {
+            tracing::warn!("Transfer {} no longer matches the discord platform of the account", transfer.id);
+            return false;
+        }
+    };
+
+    // the new discord user should only ever be linked to the account that requested the transfer
+    let new_platform_ids = platforms
+        .iter()
+        .filter(|platform| platform.platform_user == transfer.new_platform_user)
+        .map(|platform| platform.id)
+        .collect::<Vec<RecordId>>();
+    if platforms
+        .iter()
+        .any(|platform| platform.platform_user == transfer.new_platform_user && platform.account != transfer.requested_by)
+    {
+        tracing::warn!("Transfer {} targets a discord user that belongs to another account", transfer.id);
+        return false;
+    }
+
+    // snapshot the old identity before it is replaced
+    let old_data = account_platform_data::Entity::find()
+        .filter(account_platform_data::Column::Platform.eq(old_platform.id))
+        .all(&state.database)
+        .await;
+    let old_data = if let Ok(old_data) = old_data {
+        old_data
+            .into_iter()
+            .map(|data| (data.key, data.value))
+            .collect::<HashMap<String, String>>()
+    } else {
+        database::log_error(old_data);
+        return false;
+    };
+    let archived_data = serde_json::to_string(&old_data).unwrap_or_default();
+
+    let transfer_id = transfer.id;
+    let reviewer_id = reviewer.id;
+    let old_platform_id = old_platform.id;
+    let new_platform_user = transfer.new_platform_user.clone();
+    let timestamp = unix_timestamp();
+    let transaction = state
+        .database
+        .transaction::<_, (), DbErr>(|txn| {
+            Box::pin(async move {
+                if !new_platform_ids.is_empty() {
+                    account_platform_tokens::Entity::delete_many()
+                        .filter(account_platform_tokens::Column::Platform.is_in(new_platform_ids.clone()))
+                        .exec(txn)
+                        .await?;
+                    account_platform_data::Entity::delete_many()
+                        .filter(account_platform_data::Column::Platform.is_in(new_platform_ids.clone()))
+                        .exec(txn)
+                        .await?;
+                    account_platforms::Entity::delete_many()
+                        .filter(account_platforms::Column::Id.is_in(new_platform_ids))
+                        .exec(txn)
+                        .await?;
+                }
+
+                account_platform_tokens::Entity::delete_many()
+                    .filter(account_platform_tokens::Column::Platform.eq(old_platform_id))
+                    .exec(txn)
+                    .await?;
+                account_platform_data::Entity::delete_many()
+                    .filter(account_platform_data::Column::Platform.eq(old_platform_id))
+                    .exec(txn)
+                    .await?;
+
+                account_platforms::Entity::update_many()
+                    .col_expr(account_platforms::Column::PlatformUser, Expr::value(new_platform_user))
+                    .col_expr(account_platforms::Column::UpdatedAt, Expr::value(timestamp))
+                    .filter(account_platforms::Column::Id.eq(old_platform_id))
+                    .exec(txn)
+                    .await?;
+
+                account_transfers::Entity::update_many()
+                    .col_expr(account_transfers::Column::Status, Expr::value(TransferStatus::Approved.to_string()))
+                    .col_expr(account_transfers::Column::ArchivedData, Expr::value(archived_data))
+                    .col_expr(account_transfers::Column::ReviewedBy, Expr::value(reviewer_id))
+                    .col_expr(account_transfers::Column::ReviewedAt, Expr::value(timestamp))
+                    .col_expr(account_transfers::Column::UpdatedAt, Expr::value(timestamp))
+                    .filter(account_transfers::Column::Id.eq(transfer_id))
+                    .exec(txn)
+                    .await?;
+
+                Ok(())
+            })
+        })
+        .await;
+
+    if let Err(err) = transaction {
+        tracing::error!("Unable to approve transfer {}: {}", transfer_id, err);
+        return false;
+    }
+
+    // the new discord user leaves the account that requested the transfer
+    for platform in platforms
+        .iter()
+        .filter(|platform| platform.platform_user == transfer.new_platform_user)
+    {
+        event::record(AccountEventKind::PlatformUnlinked, platform.account, Some(platform), &(), &(), state).await;
+    }
+
+    event::record(
+        AccountEventKind::PlatformTransferred,
+        transfer.account,
+        Some(&old_platform),
+        &serde_json::json!({ "platform_user": transfer.old_platform_user, "data": old_data }),
+        &serde_json::json!({ "platform_user": transfer.new_platform_user, "transfer": transfer_id }),
+        state,
+    )
+    .await;
+
+    // the requesting account has nothing left that identifies it, fold it into the account it is taking over
+    let requested_by = account::by_id(transfer.requested_by, state).await;
+    let transferred = account::by_id(transfer.account, state).await;
+    if let (Some(requested_by), Some(transferred)) = (requested_by, transferred) {
+        account::merge(&requested_by, &transferred, state).await;
+
+        // whoever held the old discord user may still be logged in or hold the account token, so both stop working here
+        session::revoke_others(&requested_by, "", state).await;
+        session::revoke_others(&transferred, "", state).await;
+        if account::rotate(&transferred, false, state).await.is_none() {
+            tracing::error!("Unable to rotate the credentials of account {} after transfer {}", transferred.id, transfer_id);
+        }
+    }
+
+    true
+}
@@ -0,0 +1,419 @@
+use crate::app::crypto;
+use crate::app::extension::AccountExtension;
+use crate::entities::{webhook_deliveries, webhook_subscriptions};
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{database, tracing};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use std::str::FromStr;
+
+pub type WebhookSubscription = webhook_subscriptions::Model;
+pub type WebhookDelivery = webhook_deliveries::Model;
+
+/// a delivery is given up on after this many failed attempts
+pub const MAX_ATTEMPTS: i32 = 10;
+
+/// wait before the first retry, doubled on every attempt after that
+const RETRY_BASE: i64 = 30;
+
+/// longest wait between two attempts, in seconds
+const RETRY_MAX: i64 = 60 * 60 * 6;
+
+/// how long a claimed delivery is hidden from other workers, in seconds. Longer than any request is allowed to take
+const CLAIM_TIMEOUT: i64 = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebhookEvent {
+    AccountCreated,
+    PlatformLinked,
+    PlatformUnlinked,
+    DisplayNameChanged,
+}
+
+impl std::fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookEvent::AccountCreated => {
+                write!(f, "account.created")
+            }
+            WebhookEvent::PlatformLinked => {
+                write!(f, "platform.linked")
+            }
+            WebhookEvent::PlatformUnlinked => {
+                write!(f, "platform.unlinked")
+            }
+            WebhookEvent::DisplayNameChanged => {
+                write!(f, "display_name.changed")
+            }
+        }
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "account.created" => Ok(WebhookEvent::AccountCreated),
+            "platform.linked" => Ok(WebhookEvent::PlatformLinked),
+            "platform.unlinked" => Ok(WebhookEvent::PlatformUnlinked),
+            "display_name.changed" => Ok(WebhookEvent::DisplayNameChanged),
+            other => Err(format!("Unknown webhook event {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// waiting for its first or next attempt
+    Pending,
+    Delivered,
+    /// ran out of attempts
+    Failed,
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryStatus::Pending => {
+                write!(f, "pending")
+            }
+            DeliveryStatus::Delivered => {
+                write!(f, "delivered")
+            }
+            DeliveryStatus::Failed => {
+                write!(f, "failed")
+            }
+        }
+    }
+}
+
+/// A subscription along with its plain text secret. The secret is only handed back when the subscription is created
+#[derive(Clone, Debug, Default)]
+pub struct NewWebhookSubscription {
+    pub subscription: WebhookSubscription,
+    pub secret: String,
+}
+
+impl WebhookSubscription {
+    pub fn events(&self) -> Vec<WebhookEvent> {
+        self.events
+            .split(',')
+            .filter_map(|event| WebhookEvent::from_str(event).ok())
+            .collect()
+    }
+
+    pub fn listens_to(&self, event: WebhookEvent) -> bool {
+        self.events().contains(&event)
+    }
+}
+
+/// Registers a subscriber with a freshly generated secret. The secret is stored encrypted with `account.token_key`
+/// since it is needed in plain text to sign deliveries
+pub async fn create(
+    name: &str,
+    url: &str,
+    events: &[WebhookEvent],
+    state: &ApplicationState<AccountExtension>,
+) -> Option<NewWebhookSubscription> {
+    let token_key = state.extension.token_key.as_str();
+    if token_key.is_empty() {
+        tracing::warn!("No token key has been set (account.token_key). Webhook secrets can not be stored");
+        return None;
+    }
+
+    let secret = crypto::random_token(32);
+    let active = webhook_subscriptions::ActiveModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::Set(name.to_string()),
+        url: ActiveValue::Set(url.to_string()),
+        secret: ActiveValue::Set(crypto::encrypt(&secret, token_key)?),
+        events: ActiveValue::Set(
+            events
+                .iter()
+                .map(|event| event.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+        ),
+        created_at: ActiveValue::Set(unix_timestamp()),
+        updated_at: ActiveValue::Set(0),
+        deleted_at: ActiveValue::Set(0),
+    };
+
+    let query_result = webhook_subscriptions::Entity::insert(active)
+        .exec(&state.database)
+        .await;
+    if let Ok(query_result) = query_result {
+        let subscription = get(query_result.last_insert_id, state).await?;
+        Some(NewWebhookSubscription { subscription, secret })
+    } else {
+        database::log_error(query_result);
+        None
+    }
+}
+
+/// the plain text secret deliveries to the subscriber are signed with
+pub fn secret(subscription: &WebhookSubscription, state: &ApplicationState<AccountExtension>) -> Option<String> {
+    crypto::decrypt(&subscription.secret, &state.extension.token_key)
+}
+
+pub async fn get(id: RecordId, state: &ApplicationState<AccountExtension>) -> Option<WebhookSubscription> {
+    let query = webhook_subscriptions::Entity::find()
+        .filter(
+            Condition::all()
+                .add(webhook_subscriptions::Column::Id.eq(id))
+                .add(webhook_subscriptions::Column::DeletedAt.eq(0)),
+        )
+        .one(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+pub async fn all(state: &ApplicationState<AccountExtension>) -> Vec<WebhookSubscription> {
+    let query = webhook_subscriptions::Entity::find()
+        .filter(webhook_subscriptions::Column::DeletedAt.eq(0))
+        .order_by_asc(webhook_subscriptions::Column::Id)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// Stops sending anything to the subscriber. Deliveries that are still pending are given up on when they come due
+pub async fn remove(subscription: &WebhookSubscription, state: &ApplicationState<AccountExtension>) -> bool {
+    let timestamp = unix_timestamp();
+    let query = webhook_subscriptions::Entity::update_many()
+        .col_expr(webhook_subscriptions::Column::DeletedAt, Expr::value(timestamp))
+        .col_expr(webhook_subscriptions::Column::UpdatedAt, Expr::value(timestamp))
+        .filter(webhook_subscriptions::Column::Id.eq(subscription.id))
+        .exec(&state.database)
+        .await;
+
+    match query {
+        Ok(result) => result.rows_affected > 0,
+        Err(err) => {
+            tracing::error!("Unable to remove webhook subscription {}: {}", subscription.id, err);
+            false
+        }
+    }
+}
+
+/// Queues a delivery of the payload for every subscriber listening to the event
+pub async fn enqueue(event: WebhookEvent, payload: &serde_json::Value, state: &ApplicationState<AccountExtension>) {
+    let subscriptions = all(state)
+        .await
+        .into_iter()
+        .filter(|subscription| subscription.listens_to(event))
+        .collect::<Vec<WebhookSubscription>>();
+    if subscriptions.is_empty() {
+        return;
+    }
+
+    let payload = payload.to_string();
+    let timestamp = unix_timestamp();
+    let deliveries = subscriptions.iter().map(|subscription| webhook_deliveries::ActiveModel {
+        id: ActiveValue::NotSet,
+        subscription: ActiveValue::Set(subscription.id),
+        event: ActiveValue::Set(event.to_string()),
+        payload: ActiveValue::Set(payload.clone()),
+        status: ActiveValue::Set(DeliveryStatus::Pending.to_string()),
+        attempts: ActiveValue::Set(0),
+        next_attempt_at: ActiveValue::Set(timestamp),
+        response_status: ActiveValue::Set(0),
+        error: ActiveValue::Set(String::new()),
+        delivered_at: ActiveValue::Set(0),
+        created_at: ActiveValue::Set(timestamp),
+        updated_at: ActiveValue::Set(0),
+    });
+
+    let query = webhook_deliveries::Entity::insert_many(deliveries)
+        .exec_without_returning(&state.database)
+        .await;
+    if let Err(err) = query {
+        tracing::error!("Unable to queue {} webhook deliveries: {}", event, err);
+    }
+}
+
+pub async fn delivery(id: RecordId, state: &ApplicationState<AccountExtension>) -> Option<WebhookDelivery> {
+    let query = webhook_deliveries::Entity::find_by_id(id).one(&state.database).await;
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        None
+    }
+}
+
+/// pending deliveries whose next attempt is due, oldest first
+pub async fn due(limit: u64, state: &ApplicationState<AccountExtension>) -> Vec<WebhookDelivery> {
+    let query = webhook_deliveries::Entity::find()
+        .filter(
+            Condition::all()
+                .add(webhook_deliveries::Column::Status.eq(DeliveryStatus::Pending.to_string()))
+                .add(webhook_deliveries::Column::NextAttemptAt.lte(unix_timestamp())),
+        )
+        .order_by_asc(webhook_deliveries::Column::NextAttemptAt)
+        .limit(limit)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// The delivery log of a subscriber, newest first
+pub async fn deliveries(
+    subscription: &WebhookSubscription,
+    limit: u64,
+    state: &ApplicationState<AccountExtension>,
+) -> Vec<WebhookDelivery> {
+    let query = webhook_deliveries::Entity::find()
+        .filter(webhook_deliveries::Column::Subscription.eq(subscription.id))
+        .order_by_desc(webhook_deliveries::Column::Id)
+        .limit(limit)
+        .all(&state.database)
+        .await;
+
+    if let Ok(query) = query {
+        query
+    } else {
+        database::log_error(query);
+        Vec::new()
+    }
+}
+
+/// Pushes the next attempt of the delivery back so no other worker picks it up while it is being sent.
+/// Only one caller can claim the same attempt
+pub async fn claim(delivery: &WebhookDelivery, state: &ApplicationState<AccountExtension>) -> bool {
+    let query = webhook_deliveries::Entity::update_many()
+        .col_expr(
+            webhook_deliveries::Column::NextAttemptAt,
+            Expr::value(unix_timestamp() + CLAIM_TIMEOUT),
+        )
+        .filter(
+            Condition::all()
+                .add(webhook_deliveries::Column::Id.eq(delivery.id))
+                .add(webhook_deliveries::Column::Status.eq(DeliveryStatus::Pending.to_string()))
+                .add(webhook_deliveries::Column::NextAttemptAt.eq(delivery.next_attempt_at)),
+        )
+        .exec(&state.database)
+        .await;
+
+    match query {
+        Ok(result) => result.rows_affected > 0,
+        Err(err) => {
+            tracing::error!("Unable to claim webhook delivery {}: {}", delivery.id, err);
+            false
+        }
+    }
+}
+
+pub async fn delivered(delivery: &WebhookDelivery, response_status: i32, state: &ApplicationState<AccountExtension>) {
+    let timestamp = unix_timestamp();
+    let query = webhook_deliveries::Entity::update_many()
+        .col_expr(webhook_deliveries::Column::Status, Expr::value(DeliveryStatus::Delivered.to_string()))
+        .col_expr(webhook_deliveries::Column::Attempts, Expr::value(delivery.attempts + 1))
+        .col_expr(webhook_deliveries::Column::ResponseStatus, Expr::value(response_status))
+        .col_expr(webhook_deliveries::Column::Error, Expr::value(""))
+        .col_expr(webhook_deliveries::Column::DeliveredAt, Expr::value(timestamp))
+        .col_expr(webhook_deliveries::Column::UpdatedAt, Expr::value(timestamp))
+        .filter(webhook_deliveries::Column::Id.eq(delivery.id))
+        .exec(&state.database)
+        .await;
+
+    if query.is_err() {
+        database::log_error(query);
+    }
+}
+
+/// Records a failed attempt and schedules the next one with exponential backoff, or gives up after `MAX_ATTEMPTS`.
+/// `response_status` is 0 when the subscriber could not be reached at all
+pub async fn failed(
+    delivery: &WebhookDelivery,
+    response_status: i32,
+    error: &str,
+    state: &ApplicationState<AccountExtension>,
+) {
+    let attempts = delivery.attempts + 1;
+    let status = if attempts >= MAX_ATTEMPTS {
+        DeliveryStatus::Failed
+    } else {
+        DeliveryStatus::Pending
+    };
+
+    let timestamp = unix_timestamp();
+    let backoff = RETRY_BASE
+        .saturating_mul(2_i64.saturating_pow((attempts - 1).max(0) as u32))
+        .min(RETRY_MAX);
+    let query = webhook_deliveries::Entity::update_many()
+        .col_expr(webhook_deliveries::Column::Status, Expr::value(status.to_string()))
+        .col_expr(webhook_deliveries::Column::Attempts, Expr::value(attempts))
+        .col_expr(webhook_deliveries::Column::NextAttemptAt, Expr::value(timestamp + backoff))
+        .col_expr(webhook_deliveries::Column::ResponseStatus, Expr::value(response_status))
+        .col_expr(webhook_deliveries::Column::Error, Expr::value(error))
+        .col_expr(webhook_deliveries::Column::UpdatedAt, Expr::value(timestamp))
+        .filter(webhook_deliveries::Column::Id.eq(delivery.id))
+        .exec(&state.database)
+        .await;
+
+    if query.is_err() {
+        database::log_error(query);
+    }
+}
+
+/// Gives up on the delivery without another attempt, for example when its subscriber was removed
+pub async fn abandon(delivery: &WebhookDelivery, reason: &str, state: &ApplicationState<AccountExtension>) {
+    let query = webhook_deliveries::Entity::update_many()
+        .col_expr(webhook_deliveries::Column::Status, Expr::value(DeliveryStatus::Failed.to_string()))
+        .col_expr(webhook_deliveries::Column::Error, Expr::value(reason))
+        .col_expr(webhook_deliveries::Column::UpdatedAt, Expr::value(unix_timestamp()))
+        .filter(webhook_deliveries::Column::Id.eq(delivery.id))
+        .exec(&state.database)
+        .await;
+
+    if query.is_err() {
+        database::log_error(query);
+    }
+}
+
+/// Queues a delivery that was given up on again, with a fresh set of attempts
+pub async fn retry(delivery: &WebhookDelivery, state: &ApplicationState<AccountExtension>) -> bool {
+    let timestamp = unix_timestamp();
+    let query = webhook_deliveries::Entity::update_many()
+        .col_expr(webhook_deliveries::Column::Status, Expr::value(DeliveryStatus::Pending.to_string()))
+        .col_expr(webhook_deliveries::Column::Attempts, Expr::value(0))
+        .col_expr(webhook_deliveries::Column::NextAttemptAt, Expr::value(timestamp))
+        .col_expr(webhook_deliveries::Column::UpdatedAt, Expr::value(timestamp))
+        .filter(
+            Condition::all()
+                .add(webhook_deliveries::Column::Id.eq(delivery.id))
+                .add(webhook_deliveries::Column::Status.eq(DeliveryStatus::Failed.to_string())),
+        )
+        .exec(&state.database)
+        .await;
+
+    match query {
+        Ok(result) => result.rows_affected > 0,
+        Err(err) => {
+            tracing::error!("Unable to retry webhook delivery {}: {}", delivery.id, err);
+            false
+        }
+    }
+}
@@ -16,6 +16,9 @@ pub struct Model {
     pub id: i64,
     pub token: String,
     pub token_secret: String,
+    pub legacy_token: String,
+    pub legacy_token_secret: String,
+    pub subject: String,
     pub admin: i8,
     pub timezone: String,
     pub last_login_at: i64,
@@ -29,6 +32,9 @@ pub enum Column {
     Id,
     Token,
     TokenSecret,
+    LegacyToken,
+    LegacyTokenSecret,
+    Subject,
     Admin,
     Timezone,
     LastLoginAt,
@@ -61,7 +67,10 @@ impl ColumnTrait for Column {
         match self {
             Self::Id => ColumnType::BigInteger.def(),
             Self::Token => ColumnType::Char(Some(32u32)).def().unique(),
-            Self::TokenSecret => ColumnType::Char(Some(32u32)).def(),
+            Self::TokenSecret => ColumnType::Char(Some(64u32)).def(),
+            Self::LegacyToken => ColumnType::Char(Some(32u32)).def(),
+            Self::LegacyTokenSecret => ColumnType::Char(Some(64u32)).def(),
+            Self::Subject => ColumnType::Char(Some(32u32)).def().unique(),
             Self::Admin => ColumnType::TinyInteger.def(),
             Self::Timezone => ColumnType::String(Some(32u32)).def(),
             Self::LastLoginAt => ColumnType::BigInteger.def(),
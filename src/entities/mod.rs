@@ -2,6 +2,19 @@
 
 pub mod prelude;
 
+pub mod account_bans;
+pub mod account_events;
 pub mod account_platform_data;
+pub mod account_platform_tokens;
 pub mod account_platforms;
+pub mod account_roles;
+pub mod account_sessions;
+pub mod account_transfers;
 pub mod accounts;
+pub mod api_clients;
+pub mod oauth_clients;
+pub mod role_permissions;
+pub mod roles;
+pub mod signing_keys;
+pub mod webhook_deliveries;
+pub mod webhook_subscriptions;
@@ -1,5 +1,18 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
+pub use super::account_bans::Entity as AccountBans;
+pub use super::account_events::Entity as AccountEvents;
 pub use super::account_platform_data::Entity as AccountPlatformData;
+pub use super::account_platform_tokens::Entity as AccountPlatformTokens;
 pub use super::account_platforms::Entity as AccountPlatforms;
+pub use super::account_roles::Entity as AccountRoles;
+pub use super::account_sessions::Entity as AccountSessions;
+pub use super::account_transfers::Entity as AccountTransfers;
 pub use super::accounts::Entity as Accounts;
+pub use super::api_clients::Entity as ApiClients;
+pub use super::oauth_clients::Entity as OauthClients;
+pub use super::role_permissions::Entity as RolePermissions;
+pub use super::roles::Entity as Roles;
+pub use super::signing_keys::Entity as SigningKeys;
+pub use super::webhook_deliveries::Entity as WebhookDeliveries;
+pub use super::webhook_subscriptions::Entity as WebhookSubscriptions;
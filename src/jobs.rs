@@ -1,3 +1,13 @@
+pub mod account_tokens;
+pub mod api_client;
+pub mod bungie;
 pub mod discord;
+pub mod merge;
 pub mod migrate;
+pub mod oauth_client;
+pub mod role;
 pub mod server;
+pub mod signing_key;
+pub mod tokens;
+pub mod twitch;
+pub mod webhook;
\ No newline at end of file
@@ -0,0 +1,66 @@
+use crate::{app::extension::AccountExtension, database};
+use levelcrush::{anyhow, tracing};
+
+/// Moves accounts and platforms off their md5 tokens.
+///
+/// Rotated accounts keep accepting their old token and secret, so nobody is logged out and services holding
+/// the old account token can still look it up. Once old sessions have run out, `drop-legacy` stops accepting them.
+///
+/// Usage: `account-tokens rotate [batch size]` or `account-tokens drop-legacy`
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, _, global_process) = AccountExtension::app_stack(1, 1, "account-tokens").await?;
+
+    let command = args.first().map(|v| v.as_str()).unwrap_or_default();
+    let batch = args.get(1).and_then(|v| v.parse::<u64>().ok()).unwrap_or(500);
+    match command {
+        "rotate" => {
+            let mut rotated = 0;
+            loop {
+                let accounts = database::account::legacy(batch, &state).await;
+                if accounts.is_empty() {
+                    break;
+                }
+
+                for account in accounts.iter() {
+                    if database::account::rotate(account, true, &state).await.is_some() {
+                        rotated += 1;
+                    } else {
+                        tracing::error!("Unable to rotate account {}, stopping", account.id);
+                        return Ok(());
+                    }
+                }
+            }
+            let msg = format!("Rotated {} account tokens", rotated);
+            global_process.log_info(&msg).await;
+
+            let mut rotated = 0;
+            loop {
+                let platforms = database::platform::legacy(batch, &state).await;
+                if platforms.is_empty() {
+                    break;
+                }
+
+                for account_platform in platforms.iter() {
+                    if database::platform::rotate_token(account_platform, &state).await {
+                        rotated += 1;
+                    } else {
+                        tracing::error!("Unable to rotate platform {}, stopping", account_platform.id);
+                        return Ok(());
+                    }
+                }
+            }
+            let msg = format!("Rotated {} platform tokens", rotated);
+            global_process.log_info(&msg).await;
+        }
+        "drop-legacy" => {
+            let dropped = database::account::drop_legacy(&state).await;
+            let msg = format!("Dropped the legacy tokens of {} accounts", dropped);
+            global_process.log_info(&msg).await;
+        }
+        _ => {
+            tracing::error!("Expected one of: rotate, drop-legacy");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use crate::database::api_client::ApiScope;
+use crate::{app::extension::AccountExtension, database};
+use levelcrush::util::unix_timestamp;
+use levelcrush::{anyhow, tracing};
+use std::str::FromStr;
+
+/// how long a rotated key keeps working when no grace period is given, in seconds
+const DEFAULT_ROTATION_GRACE: i64 = 86400;
+
+/// Manages the api clients that are allowed to call the search and link generation endpoints
+///
+/// Usage:
+/// * `api-client create <name> <scope,scope> [days until expiry]`
+/// * `api-client rotate <id> [grace period in seconds]`
+/// * `api-client revoke <id>`
+/// * `api-client list`
+/// * `api-client import-legacy` registers the old shared `account.key` so existing callers keep working
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, app_settings, global_process) = AccountExtension::app_stack(1, 1, "account-api-client").await?;
+
+    let command = args.first().map(|v| v.as_str()).unwrap_or_default();
+    match command {
+        "create" => {
+            let name = args.get(1).cloned().unwrap_or_default();
+            let scopes = args
+                .get(2)
+                .map(|scopes| scopes.split(',').filter_map(|scope| ApiScope::from_str(scope).ok()).collect())
+                .unwrap_or_else(Vec::new);
+            let expires_at = args
+                .get(3)
+                .and_then(|days| days.parse::<i64>().ok())
+                .map(|days| unix_timestamp() + days * 86400)
+                .unwrap_or(0);
+
+            if name.is_empty() || scopes.is_empty() {
+                tracing::error!("Expected a name and at least one scope (search, link-generate)");
+                return Ok(());
+            }
+
+            match database::api_client::create(&name, &scopes, expires_at, &state).await {
+                Some(result) => {
+                    let msg = format!("Created api client {} ({})", result.client.name, result.client.id);
+                    global_process.log_info(&msg).await;
+
+                    // the key cannot be shown again, so it only goes to stdout and never into the process log
+                    println!("key: {}", result.key);
+                }
+                None => tracing::error!("Unable to create api client"),
+            }
+        }
+        "rotate" => {
+            let client = match args.get(1).and_then(|id| id.parse::<i64>().ok()) {
+                Some(id) => database::api_client::get(id, &state).await,
+                None => None,
+            };
+            let grace = args
+                .get(2)
+                .and_then(|grace| grace.parse::<i64>().ok())
+                .unwrap_or(DEFAULT_ROTATION_GRACE);
+
+            match client {
+                Some(client) => match database::api_client::rotate(&client, grace, &state).await {
+                    Some(result) => {
+                        let msg = format!("Rotated key of api client {} ({})", result.client.name, result.client.id);
+                        global_process.log_info(&msg).await;
+                        println!("key: {}", result.key);
+                    }
+                    None => tracing::error!("Unable to rotate the api key"),
+                },
+                None => tracing::error!("Unable to find the api client"),
+            }
+        }
+        "revoke" => {
+            let client = match args.get(1).and_then(|id| id.parse::<i64>().ok()) {
+                Some(id) => database::api_client::get(id, &state).await,
+                None => None,
+            };
+
+            match client {
+                Some(client) => {
+                    if database::api_client::revoke(&client, &state).await {
+                        let msg = format!("Revoked api client {} ({})", client.name, client.id);
+                        global_process.log_info(&msg).await;
+                    }
+                }
+                None => tracing::error!("Unable to find the api client"),
+            }
+        }
+        "list" => {
+            for client in database::api_client::all(&state).await.iter() {
+                println!(
+                    "{}\t{}\tscopes: {}\texpires: {}\tlast used: {}",
+                    client.id, client.name, client.scopes, client.expires_at, client.last_used_at
+                );
+            }
+        }
+        "import-legacy" => {
+            let legacy_key = app_settings.get_global("account.key").unwrap_or_default();
+            if legacy_key.is_empty() {
+                tracing::error!("There is no account.key to import");
+                return Ok(());
+            }
+
+            let scopes = [ApiScope::Search, ApiScope::LinkGenerate];
+            match database::api_client::create_with_key("legacy", &legacy_key, &scopes, 0, &state).await {
+                Some(client) => {
+                    let msg = format!("Imported account.key as api client {}", client.id);
+                    global_process.log_info(&msg).await;
+                }
+                None => tracing::error!("Unable to import account.key"),
+            }
+        }
+        _ => {
+            tracing::error!("Expected one of: create, rotate, revoke, list, import-legacy");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use crate::{
+    app::{bungie, extension::AccountExtension},
+    database::{self, platform::AccountPlatformType},
+};
+use levelcrush::{anyhow, tokio, tracing};
+use std::time::Duration;
+
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, _, global_process) = AccountExtension::app_stack(1, 1, "bungie-info").await?;
+
+    let limit = match args.first() {
+        Some(v) => v.parse::<i64>().unwrap_or(1000),
+        _ => 1000,
+    };
+
+    let need_update = database::platform::need_update(AccountPlatformType::Bungie, limit, &state).await;
+    for membership_id in need_update.into_iter() {
+        let msg = format!("Updating bungie member: {membership_id}");
+        let handle = global_process.log_info(&msg);
+
+        let account_platform =
+            database::platform::read(AccountPlatformType::Bungie, membership_id.clone(), &state).await;
+        let identity = bungie::fetch_identity(&membership_id, None, &state).await;
+
+        // rewrite the platform data exactly as if the user had just linked
+        if let Some(mut account_platform) = account_platform {
+            if let Some(identity) = identity {
+                let data = bungie::platform_data(&identity);
+                database::platform_data::write(&account_platform, &data, &state).await;
+            } else {
+                tracing::warn!("Unable to fetch bungie member: {}", membership_id);
+            }
+
+            // always touch the platform so a member that cannot be fetched does not hold up the rest of the queue
+            database::platform::update(&mut account_platform, &state).await;
+        }
+
+        handle.await;
+
+        // two requests are made per member, stay well under the bungie api throttle of 25 requests per second
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    Ok(())
+}
@@ -2,8 +2,7 @@ use crate::{
     app::{self, extension::AccountExtension},
     database::{self, platform::AccountPlatformType},
 };
-use levelcrush::{anyhow, tokio, tracing};
-use std::time::Duration;
+use levelcrush::{anyhow, tracing};
 
 pub async fn run(args: &[String]) -> anyhow::Result<()> {
     let (app, mut state, _, mut global_process) =
@@ -27,16 +26,9 @@ pub async fn run(args: &[String]) -> anyhow::Result<()> {
         let msg = format!("Updating member: {discord_id}");
         let handle = global_process.log_info(&msg);
 
+        // requests go through the discord client, which waits on discord's rate limits for us
         app::discord::member(&discord_id, &state).await;
         handle.await;
-
-        // intentionally add a delay between each request of 100ms
-        // this is a lazy and innaccurate way of making sure we dont exceed our
-        // global rate limit of 50 request per second
-        // https://discord.com/developers/docs/topics/rate-limits#global-rate-limit
-        // eventually we should examine the request for rate limit info
-        // for now this works just fine
-        tokio::time::sleep(Duration::from_millis(100)).await;
     }
 
     Ok(())
@@ -0,0 +1,40 @@
+use crate::{app::extension::AccountExtension, database};
+use levelcrush::{anyhow, tracing};
+
+/// Merges one account into another by their account tokens
+///
+/// Usage: `merge <from account token> <into account token>`
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, _, global_process) = AccountExtension::app_stack(1, 1, "account-merge").await?;
+
+    let (from_token, into_token) = match (args.first(), args.get(1)) {
+        (Some(from_token), Some(into_token)) => (from_token, into_token),
+        _ => {
+            tracing::error!("Expected the token of the account to merge from and the token of the account to merge into");
+            return Ok(());
+        }
+    };
+
+    let from = database::account::by_token(from_token, &state).await;
+    let into = database::account::by_token(into_token, &state).await;
+    let (from, into) = match (from, into) {
+        (Some(from), Some(into)) => (from, into),
+        _ => {
+            tracing::error!("Unable to find both accounts");
+            return Ok(());
+        }
+    };
+
+    let msg = format!("Merging account {} into {}", from.id, into.id);
+    global_process.log_info(&msg).await;
+
+    match database::account::merge(&from, &into, &state).await {
+        Some(result) => {
+            let msg = format!("Merged. Moved: {:?} Dropped: {:?}", result.moved, result.dropped);
+            global_process.log_info(&msg).await;
+        }
+        None => tracing::error!("Merge failed, nothing was changed"),
+    }
+
+    Ok(())
+}
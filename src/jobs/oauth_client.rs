@@ -0,0 +1,31 @@
+use crate::{app::extension::AccountExtension, database};
+use levelcrush::{anyhow, tracing};
+
+/// Registers a downstream service as an oauth / openid connect client and prints its credentials
+///
+/// Usage: `oauth-client <name> <redirect uri> [redirect uri...]`
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, _, global_process) = AccountExtension::app_stack(1, 1, "account-oauth-client").await?;
+
+    let (name, redirect_uris) = match args.split_first() {
+        Some((name, redirect_uris)) if !redirect_uris.is_empty() => (name, redirect_uris),
+        _ => {
+            tracing::error!("Expected the name of the client followed by at least one redirect uri");
+            return Ok(());
+        }
+    };
+
+    match database::oauth_client::create(name, redirect_uris, &state).await {
+        Some(result) => {
+            let msg = format!("Registered oauth client {}", result.client.name);
+            global_process.log_info(&msg).await;
+
+            // the secret cannot be shown again, so it only goes to stdout and never into the process log
+            println!("client_id: {}", result.client.client_id);
+            println!("client_secret: {}", result.client_secret);
+        }
+        None => tracing::error!("Unable to register oauth client"),
+    }
+
+    Ok(())
+}
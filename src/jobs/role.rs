@@ -0,0 +1,83 @@
+use crate::database::role::Permission;
+use crate::{app::extension::AccountExtension, database};
+use levelcrush::{anyhow, tracing};
+use std::str::FromStr;
+
+/// Manages roles and who has them
+///
+/// Usage:
+/// * `role create <name> <permission,permission> [description]`
+/// * `role assign <account token> <role>`
+/// * `role unassign <account token> <role>`
+/// * `role list`
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, _app_settings, global_process) = AccountExtension::app_stack(1, 1, "account-role").await?;
+
+    database::role::seed(&state).await;
+
+    let command = args.first().map(|v| v.as_str()).unwrap_or_default();
+    match command {
+        "create" => {
+            let name = args.get(1).cloned().unwrap_or_default();
+            let permissions = args
+                .get(2)
+                .map(|permissions| {
+                    permissions
+                        .split(',')
+                        .filter_map(|permission| Permission::from_str(permission).ok())
+                        .collect()
+                })
+                .unwrap_or_else(Vec::new);
+            let description = args.get(3).cloned().unwrap_or_default();
+
+            if name.is_empty() || permissions.is_empty() {
+                tracing::error!("Expected a name and at least one permission");
+                return Ok(());
+            }
+
+            match database::role::create(&name, &description, &permissions, &state).await {
+                Some(role) => {
+                    let msg = format!("Created role {} ({})", role.name, role.id);
+                    global_process.log_info(&msg).await;
+                }
+                None => tracing::error!("Unable to create role"),
+            }
+        }
+        "assign" | "unassign" => {
+            let account = match args.get(1) {
+                Some(token) => database::account::by_token(token, &state).await,
+                None => None,
+            };
+            let role = match args.get(2) {
+                Some(role) => database::role::by_name(role, &state).await,
+                None => None,
+            };
+
+            match (account, role) {
+                (Some(account), Some(role)) => {
+                    if command == "assign" {
+                        if database::role::assign(&account, &role, None, &state).await {
+                            let msg = format!("Gave account {} the {} role", account.id, role.name);
+                            global_process.log_info(&msg).await;
+                        }
+                    } else if database::role::unassign(&account, &role, &state).await {
+                        let msg = format!("Took the {} role from account {}", role.name, account.id);
+                        global_process.log_info(&msg).await;
+                    }
+                }
+                (None, _) => tracing::error!("Unable to find the account"),
+                (_, None) => tracing::error!("Unable to find the role"),
+            }
+        }
+        "list" => {
+            for role in database::role::all(&state).await.iter() {
+                println!("{}\t{}\t{}", role.id, role.name, role.permissions.join(","));
+            }
+        }
+        _ => {
+            tracing::error!("Expected one of: create, assign, unassign, list");
+        }
+    }
+
+    Ok(())
+}
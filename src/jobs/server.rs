@@ -1,8 +1,18 @@
 use crate::app::extension::AccountExtension;
-use crate::routes;
-use levelcrush::anyhow;
-use levelcrush::server::Server;
+use crate::app::{audit, session};
+use crate::app::session::store::DatabaseSessionStore;
+use crate::{app, database, routes};
+use axum::middleware;
+use axum_sessions::{SameSite, SessionLayer};
 use levelcrush::tokio;
+use levelcrush::tokio::time::Duration;
+use levelcrush::{anyhow, axum, axum_sessions};
+use std::net::SocketAddr;
+use axum::http::HeaderValue;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+/// how long a session lives, in seconds
+const SESSION_LIFETIME: u64 = 60 * 60 * 24 * 30;
 
 pub async fn run(db_core_connections: u32, db_app_connections: u32) -> anyhow::Result<()> {
     let (mut app, app_state, app_settings, global_process) =
@@ -16,6 +26,15 @@ pub async fn run(db_core_connections: u32, db_app_connections: u32) -> anyhow::R
         panic!("Please set a server secret");
     }
 
+    // access and id tokens cannot be issued without a signing key, so the first run creates one
+    if database::signing_key::published(&app_state).await.is_empty() {
+        global_process.log_info("No signing keys found, creating one").await;
+        database::signing_key::rotate(&app_state).await;
+    }
+
+    // make sure the default roles exist so they can be handed out
+    database::role::seed(&app_state).await;
+
     global_process
         .log_info("Setting up cache prune task for account service")
         .await;
@@ -28,20 +47,66 @@ pub async fn run(db_core_connections: u32, db_app_connections: u32) -> anyhow::R
             app_state_bg.extension.profiles.prune().await;
             app_state_bg.extension.mass_searches.prune().await;
             app_state_bg.extension.searches.prune().await;
+            app_state_bg.extension.discord_roles.prune().await;
+            app_state_bg.extension.merge_codes.prune().await;
+            app_state_bg.extension.oauth_codes.prune().await;
+            app_state_bg.extension.oauth_tokens.prune().await;
+            app_state_bg.extension.signing_keys.prune().await;
+            database::session::prune(&app_state_bg).await;
             tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
         }
     });
 
+    // webhook deliveries are queued in the database, so anything left over from a previous run is picked up here as well
+    let app_state_webhooks = app_state.clone();
+    let webhook_task = tokio::spawn(async move {
+        loop {
+            let attempted = app::webhook::deliver_due(&app_state_webhooks).await;
+            if attempted == 0 {
+                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            }
+        }
+    });
+
     let msg = format!("Running server on port {server_port}");
     global_process.log_info(&msg).await;
 
-    (_, _) = tokio::join!(
-        Server::new(server_port)
-            .enable_cors()
-            .enable_session(&server_secret)
-            .run(routes::router(), app_state.clone()),
-        cache_task
-    );
+    // sessions are kept in the app database instead of the in memory store the server would set up.
+    // Cookies have to survive the redirect back from the platforms and be sent along by our other sites, so they are SameSite=None
+    let session_layer = SessionLayer::new(DatabaseSessionStore::new(app_state.clone()), server_secret.as_bytes())
+        .with_same_site_policy(SameSite::None)
+        .with_secure(true)
+        .with_session_ttl(Some(Duration::from_secs(SESSION_LIFETIME)));
+
+    // the session cookie goes along with every cross site request, so only our own sites may send credentialed requests and read the answers
+    let redirect_policy = app_state.extension.redirect_policy.clone();
+    let cors_layer = CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            origin
+                .to_str()
+                .map(|origin| redirect_policy.allows_origin(origin))
+                .unwrap_or(false)
+        }))
+        .allow_credentials(true)
+        .allow_methods(AllowMethods::mirror_request())
+        .allow_headers(AllowHeaders::mirror_request());
+
+    let router = routes::router()
+        .layer(middleware::from_fn(session::store::track))
+        .layer(middleware::from_fn(audit::scope))
+        .layer(session_layer)
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            session::store::resolve_client_ip,
+        ))
+        .layer(cors_layer)
+        .with_state(app_state.clone());
+
+    // served directly so the socket address of the caller is available to `resolve_client_ip`
+    let address = SocketAddr::from(([0, 0, 0, 0], server_port));
+    let server = axum::Server::bind(&address).serve(router.into_make_service_with_connect_info::<SocketAddr>());
+
+    (_, _, _) = tokio::join!(server, cache_task, webhook_task);
 
     Ok(())
 }
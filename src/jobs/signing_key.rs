@@ -0,0 +1,20 @@
+use crate::{app::extension::AccountExtension, database};
+use levelcrush::{anyhow, tracing};
+
+/// Rotates the key access and id tokens are signed with.
+/// The previous key stays published in the jwks for a day so tokens it already signed keep verifying
+///
+/// Usage: `signing-key-rotate`
+pub async fn run(_args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, _, global_process) = AccountExtension::app_stack(1, 1, "account-signing-key").await?;
+
+    match database::signing_key::rotate(&state).await {
+        Some(key) => {
+            let msg = format!("Rotated signing key. New key id: {}", key.kid);
+            global_process.log_info(&msg).await;
+        }
+        None => tracing::error!("Unable to rotate the signing key, the current key is still in use"),
+    }
+
+    Ok(())
+}
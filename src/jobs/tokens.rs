@@ -0,0 +1,55 @@
+use crate::{
+    app::{extension::AccountExtension, platform::PlatformProvider},
+    database,
+    routes::platform::{bungie::BungieProvider, discord::DiscordProvider, twitch::TwitchProvider},
+};
+use levelcrush::{anyhow, app::ApplicationState, tracing, util::unix_timestamp};
+
+/// refresh any tokens that are going to expire within this many seconds
+const REFRESH_WINDOW: i64 = 60 * 60;
+
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, _, global_process) = AccountExtension::app_stack(1, 1, "token-refresh").await?;
+
+    let limit = match args.first() {
+        Some(v) => v.parse::<i64>().unwrap_or(1000),
+        _ => 1000,
+    };
+
+    if state.extension.token_key.is_empty() {
+        global_process
+            .log_info("No token key has been set (account.token_key). Nothing to refresh")
+            .await;
+        return Ok(());
+    }
+
+    refresh::<DiscordProvider>(limit, &state).await;
+    refresh::<TwitchProvider>(limit, &state).await;
+    refresh::<BungieProvider>(limit, &state).await;
+
+    Ok(())
+}
+
+/// keeps the stored tokens of a single platform alive by trading in their refresh tokens before they expire
+async fn refresh<P: PlatformProvider>(limit: i64, state: &ApplicationState<AccountExtension>) {
+    let expires_before = unix_timestamp() + REFRESH_WINDOW;
+    let need_refresh = database::platform_tokens::need_refresh(P::platform(), expires_before, limit, state).await;
+    for account_platform in need_refresh.into_iter() {
+        let tokens = database::platform_tokens::read(&account_platform, state).await;
+        let refresh_token = tokens.map(|tokens| tokens.refresh_token).unwrap_or_default();
+        if refresh_token.is_empty() {
+            // stored before empty refresh tokens were kept out of the query, or no longer decryptable
+            database::platform_tokens::stop_refresh(&account_platform, state).await;
+            continue;
+        }
+
+        tracing::info!("Refreshing {} tokens for platform {}", P::platform(), account_platform.id);
+        if let Some(tokens) = P::refresh_token(&refresh_token, state).await {
+            database::platform_tokens::write(&account_platform, &tokens, state).await;
+        } else {
+            // the refresh token is most likely revoked, the user has to link again to hand us new tokens
+            tracing::warn!("Unable to refresh {} tokens for platform {}", P::platform(), account_platform.id);
+            database::platform_tokens::stop_refresh(&account_platform, state).await;
+        }
+    }
+}
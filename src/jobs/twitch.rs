@@ -0,0 +1,55 @@
+use crate::{
+    app::{
+        extension::AccountExtension,
+        twitch::{self, TWITCH_USERS_PER_REQUEST},
+    },
+    database::{self, platform::AccountPlatformType},
+};
+use levelcrush::{anyhow, tracing};
+use std::collections::HashMap;
+
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, _, global_process) = AccountExtension::app_stack(1, 1, "twitch-info").await?;
+
+    let limit = match args.first() {
+        Some(v) => v.parse::<i64>().unwrap_or(1000),
+        _ => 1000,
+    };
+
+    let app_token = match twitch::app_access_token(&state).await {
+        Some(app_token) => app_token,
+        None => {
+            global_process.log_info("Unable to obtain a twitch app access token").await;
+            return Ok(());
+        }
+    };
+
+    let need_update = database::platform::need_update(AccountPlatformType::Twitch, limit, &state).await;
+    for twitch_ids in need_update.chunks(TWITCH_USERS_PER_REQUEST) {
+        let msg = format!("Updating {} twitch members", twitch_ids.len());
+        global_process.log_info(&msg).await;
+
+        let twitch_users = twitch::fetch_users(twitch_ids, &app_token, &state)
+            .await
+            .into_iter()
+            .map(|user| (user.id.clone(), user))
+            .collect::<HashMap<String, _>>();
+
+        for twitch_id in twitch_ids.iter() {
+            let account_platform = database::platform::read(AccountPlatformType::Twitch, twitch_id.clone(), &state).await;
+            if let Some(mut account_platform) = account_platform {
+                if let Some(twitch_user) = twitch_users.get(twitch_id) {
+                    let data = twitch::platform_data(twitch_user);
+                    database::platform_data::write(&account_platform, &data, &state).await;
+                } else {
+                    tracing::warn!("Twitch did not return a user for: {}", twitch_id);
+                }
+
+                // always touch the platform so a member that cannot be fetched does not hold up the rest of the queue
+                database::platform::update(&mut account_platform, &state).await;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use crate::database::webhook::WebhookEvent;
+use crate::{app, app::extension::AccountExtension, database};
+use levelcrush::{anyhow, tracing};
+use std::str::FromStr;
+
+/// deliveries shown by `webhook log` when no count is given
+const DEFAULT_LOG_SIZE: u64 = 25;
+
+/// Manages the subscribers that receive signed webhook deliveries
+///
+/// Usage:
+/// * `webhook subscribe <name> <url> <event,event>` events are account.created, platform.linked, platform.unlinked and display_name.changed
+/// * `webhook remove <id>`
+/// * `webhook list`
+/// * `webhook log <id> [count]` the most recent deliveries to the subscriber
+/// * `webhook retry <delivery id>` queues a delivery that was given up on again
+/// * `webhook deliver` sends everything that is due, the server does this on its own while it runs
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (_app, state, _app_settings, global_process) = AccountExtension::app_stack(1, 1, "account-webhook").await?;
+
+    let command = args.first().map(|v| v.as_str()).unwrap_or_default();
+    match command {
+        "subscribe" => {
+            let name = args.get(1).cloned().unwrap_or_default();
+            let url = args.get(2).cloned().unwrap_or_default();
+            let events = args
+                .get(3)
+                .map(|events| events.split(',').filter_map(|event| WebhookEvent::from_str(event).ok()).collect())
+                .unwrap_or_else(Vec::new);
+
+            let is_http = url.starts_with("https://") || url.starts_with("http://");
+            if name.is_empty() || !is_http || events.is_empty() {
+                tracing::error!("Expected a name, an http(s) url and at least one event");
+                return Ok(());
+            }
+
+            match database::webhook::create(&name, &url, &events, &state).await {
+                Some(result) => {
+                    let msg = format!(
+                        "Created webhook subscription {} ({})",
+                        result.subscription.name, result.subscription.id
+                    );
+                    global_process.log_info(&msg).await;
+
+                    // the secret cannot be shown again, so it only goes to stdout and never into the process log
+                    println!("secret: {}", result.secret);
+                }
+                None => tracing::error!("Unable to create webhook subscription"),
+            }
+        }
+        "remove" => {
+            let subscription = match args.get(1).and_then(|id| id.parse::<i64>().ok()) {
+                Some(id) => database::webhook::get(id, &state).await,
+                None => None,
+            };
+
+            match subscription {
+                Some(subscription) => {
+                    if database::webhook::remove(&subscription, &state).await {
+                        let msg = format!("Removed webhook subscription {} ({})", subscription.name, subscription.id);
+                        global_process.log_info(&msg).await;
+                    }
+                }
+                None => tracing::error!("Unable to find the webhook subscription"),
+            }
+        }
+        "list" => {
+            for subscription in database::webhook::all(&state).await.iter() {
+                println!(
+                    "{}\t{}\t{}\tevents: {}",
+                    subscription.id, subscription.name, subscription.url, subscription.events
+                );
+            }
+        }
+        "log" => {
+            let subscription = match args.get(1).and_then(|id| id.parse::<i64>().ok()) {
+                Some(id) => database::webhook::get(id, &state).await,
+                None => None,
+            };
+            let count = args
+                .get(2)
+                .and_then(|count| count.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_LOG_SIZE);
+
+            match subscription {
+                Some(subscription) => {
+                    for delivery in database::webhook::deliveries(&subscription, count, &state).await.iter() {
+                        println!(
+                            "{}\t{}\t{}\tattempts: {}\thttp: {}\tcreated: {}\t{}",
+                            delivery.id,
+                            delivery.event,
+                            delivery.status,
+                            delivery.attempts,
+                            delivery.response_status,
+                            delivery.created_at,
+                            delivery.error
+                        );
+                    }
+                }
+                None => tracing::error!("Unable to find the webhook subscription"),
+            }
+        }
+        "retry" => {
+            let delivery = match args.get(1).and_then(|id| id.parse::<i64>().ok()) {
+                Some(id) => database::webhook::delivery(id, &state).await,
+                None => None,
+            };
+
+            match delivery {
+                Some(delivery) => {
+                    if database::webhook::retry(&delivery, &state).await {
+                        let msg = format!("Queued webhook delivery {} again", delivery.id);
+                        global_process.log_info(&msg).await;
+                    } else {
+                        tracing::error!("Only deliveries that were given up on can be retried");
+                    }
+                }
+                None => tracing::error!("Unable to find the webhook delivery"),
+            }
+        }
+        "deliver" => {
+            let mut total = 0;
+            loop {
+                let attempted = app::webhook::deliver_due(&state).await;
+                if attempted == 0 {
+                    break;
+                }
+                total += attempted;
+            }
+
+            let msg = format!("Attempted {} webhook deliveries", total);
+            global_process.log_info(&msg).await;
+        }
+        _ => {
+            tracing::error!("Expected one of: subscribe, remove, list, log, retry, deliver");
+        }
+    }
+
+    Ok(())
+}
@@ -1,9 +1,14 @@
+pub mod admin;
 pub mod guards;
 pub mod link;
+pub mod oidc;
 pub mod platform;
 pub mod profile;
 pub mod responses;
+pub mod role;
 pub mod search;
+pub mod transfer;
+use crate::app;
 use crate::app::extension::AccountExtension;
 use crate::routes::platform::OAuthLoginQueries;
 use axum::extract::Query;
@@ -24,6 +29,12 @@ pub fn router() -> Router<ApplicationState<AccountExtension>> {
         .nest("/profile", profile::router())
         .nest("/search", search::router())
         .nest("/link", link::router())
+        .nest("/transfer", transfer::router())
+        .nest("/roles", role::router())
+        .nest("/admin", admin::router())
+        .nest("/oauth", oidc::router())
+        .route("/.well-known/openid-configuration", get(oidc::configuration))
+        .route("/.well-known/jwks.json", get(oidc::jwks))
 }
 
 pub async fn login(
@@ -31,8 +42,7 @@ pub async fn login(
     Query(login_fields): Query<OAuthLoginQueries>,
 ) -> Redirect {
     // make sure we know where to return our user to after they are done logging in
-    let final_fallback_url = state.extension.fallback_url;
-    let final_redirect = login_fields.redirect.unwrap_or(final_fallback_url);
+    let final_redirect = app::redirect::resolve(login_fields.redirect, &state);
 
     let path = format!(
         "/platform/discord/login?redirect={}",
@@ -48,8 +58,7 @@ pub async fn logout(
     Query(login_fields): Query<OAuthLoginQueries>,
     mut session: WritableSession,
 ) -> Redirect {
-    let final_fallback_url = state.extension.fallback_url;
-    let final_redirect = login_fields.redirect.unwrap_or(final_fallback_url);
+    let final_redirect = app::redirect::resolve(login_fields.redirect, &state);
 
     // destroy session
     session.destroy();
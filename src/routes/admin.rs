@@ -0,0 +1,509 @@
+use crate::app::extension::AccountExtension;
+use crate::database::account::Account;
+use crate::database::ban::AccountBan;
+use crate::database::event::{AccountEvent, AccountEventFilter};
+use crate::database::platform::{AccountPlatform, AccountPlatformType};
+use crate::database::role::RoleView;
+use crate::routes::guards::{ManageAccounts, RequireAdmin, RequirePermission, ViewAccounts};
+use crate::{app, database};
+use axum::extract::{Path, Query, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::server::APIResponse;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{axum, tracing};
+use std::collections::HashMap;
+
+/// accounts per page when the caller does not ask for a page size
+const DEFAULT_PAGE_SIZE: u64 = 25;
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
+pub struct AccountListQuery {
+    #[serde(default)]
+    pub search: String,
+    /// pages start at 1
+    pub page: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
+pub struct EventListQuery {
+    /// account token
+    #[serde(default)]
+    pub account: String,
+    #[serde(default)]
+    pub platform: String,
+    #[serde(default)]
+    pub event: String,
+    /// unix timestamps, both inclusive
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// pages start at 1
+    pub page: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
+pub struct AdminTogglePayload {
+    pub admin: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
+pub struct BanPayload {
+    pub reason: String,
+    /// seconds until the suspension ends. Leaving it out bans the account until someone lifts it
+    pub duration: Option<i64>,
+}
+
+#[derive(serde::Serialize, Clone, Default, Debug)]
+pub struct BanView {
+    pub id: RecordId,
+    pub reason: String,
+    /// `banned` or `suspended`
+    pub kind: String,
+    pub active: bool,
+    pub actor: RecordId,
+    /// 0 for bans
+    pub expires_at: i64,
+    pub lifted_by: RecordId,
+    pub lifted_at: i64,
+    pub created_at: i64,
+}
+
+impl From<AccountBan> for BanView {
+    fn from(ban: AccountBan) -> Self {
+        BanView {
+            kind: if ban.is_permanent() { "banned" } else { "suspended" }.to_string(),
+            active: ban.is_active(),
+            id: ban.id,
+            reason: ban.reason,
+            actor: ban.actor,
+            expires_at: ban.expires_at,
+            lifted_by: ban.lifted_by,
+            lifted_at: ban.lifted_at,
+            created_at: ban.created_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Default, Debug)]
+pub struct EventView {
+    pub id: RecordId,
+    pub account: RecordId,
+    pub event: String,
+    pub platform: String,
+    pub platform_user: String,
+    /// the account that caused the change, 0 when it came from a job or an anonymous request
+    pub actor: RecordId,
+    pub ip: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+    pub created_at: i64,
+}
+
+impl From<AccountEvent> for EventView {
+    fn from(event: AccountEvent) -> Self {
+        EventView {
+            before: serde_json::from_str(&event.before).unwrap_or_default(),
+            after: serde_json::from_str(&event.after).unwrap_or_default(),
+            id: event.id,
+            account: event.account,
+            event: event.event,
+            platform: event.platform,
+            platform_user: event.platform_user,
+            actor: event.actor,
+            ip: event.ip,
+            created_at: event.created_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Default, Debug)]
+pub struct AdminEventPage {
+    pub events: Vec<EventView>,
+    pub page: u64,
+    pub limit: u64,
+    pub total: u64,
+}
+
+#[derive(serde::Serialize, Clone, Default, Debug)]
+pub struct AdminPlatformView {
+    pub platform: String,
+    pub platform_user: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<AccountPlatform> for AdminPlatformView {
+    fn from(account_platform: AccountPlatform) -> Self {
+        AdminPlatformView {
+            platform: account_platform.platform,
+            platform_user: account_platform.platform_user,
+            created_at: account_platform.created_at,
+            updated_at: account_platform.updated_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Default, Debug)]
+pub struct AdminAccountView {
+    pub token: String,
+    pub is_admin: bool,
+    pub platforms: Vec<AdminPlatformView>,
+    pub last_login_at: i64,
+    pub created_at: i64,
+}
+
+impl AdminAccountView {
+    fn new(account: Account, platforms: Vec<AccountPlatform>) -> AdminAccountView {
+        AdminAccountView {
+            token: account.token,
+            is_admin: account.admin == 1,
+            platforms: platforms.into_iter().map(AdminPlatformView::from).collect(),
+            last_login_at: account.last_login_at,
+            created_at: account.created_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Default, Debug)]
+pub struct AdminAccountPage {
+    pub accounts: Vec<AdminAccountView>,
+    pub page: u64,
+    pub limit: u64,
+    pub total: u64,
+}
+
+#[derive(serde::Serialize, Clone, Default, Debug)]
+pub struct AdminAccountDetail {
+    pub account: AdminAccountView,
+    /// platform => key => value, everything stored in `account_platform_data`
+    pub data: HashMap<String, HashMap<String, String>>,
+    pub roles: Vec<RoleView>,
+    /// every ban and suspension, newest first
+    pub bans: Vec<BanView>,
+}
+
+/// Account management for staff. Reading accounts needs `accounts.view`, changing them needs `accounts.manage`
+/// and only admins can hand out admin
+pub fn router() -> Router<ApplicationState<AccountExtension>> {
+    Router::new()
+        .route("/accounts", get(accounts))
+        .route("/accounts/:token", get(account))
+        .route("/accounts/:token/admin", post(toggle_admin))
+        .route("/accounts/:token/platforms/:platform/unlink", post(unlink))
+        .route("/accounts/:token/platforms/:platform/resync", post(resync))
+        .route("/accounts/:token/ban", post(ban))
+        .route("/bans/:id/lift", post(lift_ban))
+        .route("/events", get(events))
+}
+
+/// everything about the account an admin would want to see
+async fn detail(account: Account, state: &ApplicationState<AccountExtension>) -> AdminAccountDetail {
+    let platforms = database::platform::all_from_account(&account, state).await;
+    let data = database::account::all_data(&account, state).await;
+    let roles = database::role::by_account(&account, state).await;
+    let bans = database::ban::by_account(&account, state)
+        .await
+        .into_iter()
+        .map(BanView::from)
+        .collect();
+
+    AdminAccountDetail {
+        account: AdminAccountView::new(account, platforms),
+        data,
+        roles,
+        bans,
+    }
+}
+
+/// the account the path points at along with its linked platform of the requested type
+async fn account_platform(
+    token: &str,
+    platform: &str,
+    state: &ApplicationState<AccountExtension>,
+) -> Result<(Account, AccountPlatform), (&'static str, &'static str)> {
+    let account = database::account::by_token(token, state)
+        .await
+        .ok_or(("account", "Account not found"))?;
+    let platform = platform
+        .parse::<AccountPlatformType>()
+        .map_err(|_| ("platform", "Unknown platform"))?;
+    let account_platform = database::platform::from_account(&account, platform, state)
+        .await
+        .ok_or(("platform", "Platform is not linked to this account"))?;
+
+    Ok((account, account_platform))
+}
+
+async fn accounts(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Query(query): Query<AccountListQuery>,
+    _viewer: RequirePermission<ViewAccounts>,
+) -> Json<APIResponse<AdminAccountPage>> {
+    let mut response = APIResponse::new();
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let (accounts, total) = database::account::page(&query.search, page - 1, limit, &state).await;
+
+    let account_ids = accounts.iter().map(|account| account.id).collect::<Vec<_>>();
+    let mut platforms = database::platform::all_from_accounts(&account_ids, &state).await;
+    let accounts = accounts
+        .into_iter()
+        .map(|account| {
+            let account_platforms = platforms.remove(&account.id).unwrap_or_default();
+            AdminAccountView::new(account, account_platforms)
+        })
+        .collect();
+
+    response.data(Some(AdminAccountPage {
+        accounts,
+        page,
+        limit,
+        total,
+    }));
+
+    response.complete();
+    Json(response)
+}
+
+async fn account(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path(token): Path<String>,
+    _viewer: RequirePermission<ViewAccounts>,
+) -> Json<APIResponse<AdminAccountDetail>> {
+    let mut response = APIResponse::new();
+
+    match database::account::by_token(&token, &state).await {
+        Some(account) => {
+            response.data(Some(detail(account, &state).await));
+        }
+        None => {
+            response.error("account", "Account not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+async fn toggle_admin(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path(token): Path<String>,
+    RequireAdmin(admin): RequireAdmin,
+    Json(payload): Json<AdminTogglePayload>,
+) -> Json<APIResponse<AdminAccountDetail>> {
+    let mut response = APIResponse::new();
+
+    match database::account::by_token(&token, &state).await {
+        Some(account) if account.id == admin.id && !payload.admin => {
+            response.error("account", "Admins cannot remove their own admin");
+        }
+        Some(account) => {
+            tracing::info!("Account {} admin set to {} by {}", account.id, payload.admin, admin.id);
+            match database::account::set_admin(&account, payload.admin, &state).await {
+                Some(account) => {
+                    response.data(Some(detail(account, &state).await));
+                }
+                None => {
+                    response.error("account", "Unable to update account");
+                }
+            }
+        }
+        None => {
+            response.error("account", "Account not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+async fn unlink(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path((token, platform)): Path<(String, String)>,
+    RequirePermission { account: manager, .. }: RequirePermission<ManageAccounts>,
+) -> Json<APIResponse<AdminAccountDetail>> {
+    let mut response = APIResponse::new();
+
+    match account_platform(&token, &platform, &state).await {
+        // discord is what the account logs in with, moving it is what transfers are for
+        Ok((_, account_platform)) if account_platform.platform == AccountPlatformType::Discord.to_string() => {
+            response.error("platform", "Discord cannot be unlinked, use a transfer instead");
+        }
+        Ok((account, account_platform)) => {
+            tracing::info!(
+                "Force unlinking {} {} from account {} by {}",
+                account_platform.platform,
+                account_platform.platform_user,
+                account.id,
+                manager.id
+            );
+            database::platform::unlink(&account_platform, &state).await;
+            response.data(Some(detail(account, &state).await));
+        }
+        Err((field, message)) => {
+            response.error(field, message);
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+async fn resync(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path((token, platform)): Path<(String, String)>,
+    RequirePermission { account: manager, .. }: RequirePermission<ManageAccounts>,
+) -> Json<APIResponse<AdminAccountDetail>> {
+    let mut response = APIResponse::new();
+
+    match account_platform(&token, &platform, &state).await {
+        Ok((account, account_platform)) => {
+            tracing::info!(
+                "Resyncing {} {} of account {} for {}",
+                account_platform.platform,
+                account_platform.platform_user,
+                account.id,
+                manager.id
+            );
+            if app::platform::resync(&account_platform, &state).await {
+                response.data(Some(detail(account, &state).await));
+            } else {
+                response.error("platform", "Unable to resync platform");
+            }
+        }
+        Err((field, message)) => {
+            response.error(field, message);
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+/// Bans or suspends the account and logs out every session it has
+async fn ban(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path(token): Path<String>,
+    RequirePermission { account: manager, .. }: RequirePermission<ManageAccounts>,
+    Json(payload): Json<BanPayload>,
+) -> Json<APIResponse<AdminAccountDetail>> {
+    let mut response = APIResponse::new();
+
+    let expires_at = match payload.duration {
+        Some(duration) if duration > 0 => unix_timestamp() + duration,
+        _ => 0,
+    };
+
+    match database::account::by_token(&token, &state).await {
+        Some(account) if account.id == manager.id => {
+            response.error("account", "You cannot ban yourself");
+        }
+        Some(account) => {
+            tracing::info!(
+                "Account {} {} by {}: {}",
+                account.id,
+                if expires_at == 0 { "banned" } else { "suspended" },
+                manager.id,
+                payload.reason
+            );
+            if database::ban::create(&account, &payload.reason, Some(&manager), expires_at, &state)
+                .await
+                .is_some()
+            {
+                database::session::revoke_others(&account, "", &state).await;
+                response.data(Some(detail(account, &state).await));
+            } else {
+                response.error("ban", "Unable to ban account");
+            }
+        }
+        None => {
+            response.error("account", "Account not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+async fn lift_ban(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path(id): Path<RecordId>,
+    RequirePermission { account: manager, .. }: RequirePermission<ManageAccounts>,
+) -> Json<APIResponse<AdminAccountDetail>> {
+    let mut response = APIResponse::new();
+
+    let ban = database::ban::get(id, &state).await;
+    let account = match &ban {
+        Some(ban) => database::account::by_id(ban.account, &state).await,
+        None => None,
+    };
+
+    match (ban, account) {
+        (Some(ban), Some(account)) => {
+            tracing::info!("Ban {} on account {} lifted by {}", ban.id, account.id, manager.id);
+            if database::ban::lift(&ban, Some(&manager), &state).await {
+                response.data(Some(detail(account, &state).await));
+            } else {
+                response.error("ban", "Ban has already been lifted");
+            }
+        }
+        _ => {
+            response.error("ban", "Ban not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+/// The audit log, newest first
+async fn events(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Query(query): Query<EventListQuery>,
+    _viewer: RequirePermission<ViewAccounts>,
+) -> Json<APIResponse<AdminEventPage>> {
+    let mut response = APIResponse::new();
+
+    let account = if query.account.is_empty() {
+        Some(0)
+    } else {
+        database::account::by_token(&query.account, &state)
+            .await
+            .map(|account| account.id)
+    };
+
+    match account {
+        Some(account) => {
+            let filter = AccountEventFilter {
+                account,
+                platform: query.platform,
+                event: query.event,
+                from: query.from.unwrap_or(0),
+                to: query.to.unwrap_or(0),
+            };
+
+            let page = query.page.unwrap_or(1).max(1);
+            let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+            let (events, total) = database::event::search(&filter, page - 1, limit, &state).await;
+
+            response.data(Some(AdminEventPage {
+                events: events.into_iter().map(EventView::from).collect(),
+                page,
+                limit,
+                total,
+            }));
+        }
+        None => {
+            response.error("account", "Account not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
@@ -1,11 +1,24 @@
-use crate::app;
+use crate::app::extension::AccountExtension;
+use crate::app::rate_limit::RateLimitGroup;
+use crate::app::session::store::ClientIp;
 use crate::app::session::SessionKey;
-use axum::http::Request;
+use crate::database::account::Account;
+use crate::database::api_client::{ApiClient, ApiScope};
+use crate::database::role::Permission;
+use crate::{app, database};
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderValue, Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Redirect, Response};
+use axum::Json;
 use axum_sessions::SessionHandle;
+use levelcrush::app::ApplicationState;
 use levelcrush::axum_sessions;
+use levelcrush::server::APIResponse;
 use levelcrush::{axum, tracing};
+use std::marker::PhantomData;
 
 // checks to make sure their is a account session variable inside the user session
 pub async fn session_logged_in<B>(req: Request<B>, next: Next<B>) -> Response {
@@ -33,3 +46,265 @@ pub async fn session_logged_in<B>(req: Request<B>, next: Next<B>) -> Response {
         next.run(req).await
     }
 }
+
+/// Same check as `session_logged_in`, but answers with a json 401 instead of sending the browser to `/login`.
+/// Meant for routes that are called with fetch instead of navigated to
+pub async fn session_logged_in_json<B>(req: Request<B>, next: Next<B>) -> Response {
+    let account = match req.extensions().get::<SessionHandle>() {
+        Some(session_handle) => {
+            let session = session_handle.read().await;
+            app::session::read::<String>(SessionKey::Account, &session).unwrap_or_default()
+        }
+        None => String::new(),
+    };
+
+    if account.trim().is_empty() {
+        rejection(StatusCode::UNAUTHORIZED, "user", "Not logged in")
+    } else {
+        next.run(req).await
+    }
+}
+
+/// loads the account logged into the session of the request
+async fn session_account(parts: &Parts, state: &ApplicationState<AccountExtension>) -> Option<Account> {
+    let session = parts.extensions.get::<SessionHandle>()?.read().await.clone();
+    app::session::account(&session, state).await
+}
+
+/// The account logged into the session. Requests without one get a json 401
+pub struct SessionAccount(pub Account);
+
+#[async_trait]
+impl FromRequestParts<ApplicationState<AccountExtension>> for SessionAccount {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Result<Self, Self::Rejection> {
+        match session_account(parts, state).await {
+            Some(account) => Ok(SessionAccount(account)),
+            None => Err(rejection(StatusCode::UNAUTHORIZED, "user", "Not logged in")),
+        }
+    }
+}
+
+/// The account logged into the session, as long as `accounts.admin` is set.
+/// Requests without an account get a json 401, accounts that are not admins get a 403
+pub struct RequireAdmin(pub Account);
+
+#[async_trait]
+impl FromRequestParts<ApplicationState<AccountExtension>> for RequireAdmin {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Result<Self, Self::Rejection> {
+        match session_account(parts, state).await {
+            Some(account) if account.admin == 1 => Ok(RequireAdmin(account)),
+            Some(account) => {
+                tracing::warn!("Account {} tried to use an admin route", account.id);
+                Err(rejection(StatusCode::FORBIDDEN, "user", "Not allowed"))
+            }
+            None => Err(rejection(StatusCode::UNAUTHORIZED, "user", "Not logged in")),
+        }
+    }
+}
+
+/// The permission a `RequirePermission` extractor demands from the session account
+pub trait RequiredPermission: Send + Sync {
+    const PERMISSION: Permission;
+}
+
+pub struct ViewAccounts;
+impl RequiredPermission for ViewAccounts {
+    const PERMISSION: Permission = Permission::AccountsView;
+}
+
+pub struct ManageAccounts;
+impl RequiredPermission for ManageAccounts {
+    const PERMISSION: Permission = Permission::AccountsManage;
+}
+
+pub struct ReviewTransfers;
+impl RequiredPermission for ReviewTransfers {
+    const PERMISSION: Permission = Permission::TransfersReview;
+}
+
+pub struct ManageRoles;
+impl RequiredPermission for ManageRoles {
+    const PERMISSION: Permission = Permission::RolesManage;
+}
+
+/// The account logged into the session, as long as one of its roles grants the permission `T`.
+/// Requests without an account get a json 401, accounts missing the permission get a 403
+pub struct RequirePermission<T: RequiredPermission> {
+    pub account: Account,
+    permission: PhantomData<T>,
+}
+
+#[async_trait]
+impl<T: RequiredPermission> FromRequestParts<ApplicationState<AccountExtension>> for RequirePermission<T> {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Result<Self, Self::Rejection> {
+        let account = match session_account(parts, state).await {
+            Some(account) => account,
+            None => return Err(rejection(StatusCode::UNAUTHORIZED, "user", "Not logged in")),
+        };
+
+        if !database::role::has_permission(&account, T::PERMISSION, state).await {
+            tracing::warn!("Account {} is missing the {} permission", account.id, T::PERMISSION);
+            return Err(rejection(StatusCode::FORBIDDEN, "user", "Not allowed"));
+        }
+
+        Ok(RequirePermission {
+            account,
+            permission: PhantomData,
+        })
+    }
+}
+
+/// The scope an `ApiKey` extractor demands from the calling client
+pub trait RequiredScope: Send + Sync {
+    const SCOPE: ApiScope;
+}
+
+pub struct SearchScope;
+impl RequiredScope for SearchScope {
+    const SCOPE: ApiScope = ApiScope::Search;
+}
+
+pub struct LinkGenerateScope;
+impl RequiredScope for LinkGenerateScope {
+    const SCOPE: ApiScope = ApiScope::LinkGenerate;
+}
+
+/// Authenticates the api client through the `Account-Key` header and makes sure it was granted the scope `T`.
+/// Requests without a valid key get a 401, clients missing the scope get a 403
+pub struct ApiKey<T: RequiredScope> {
+    pub client: ApiClient,
+    scope: PhantomData<T>,
+}
+
+#[async_trait]
+impl<T: RequiredScope> FromRequestParts<ApplicationState<AccountExtension>> for ApiKey<T> {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Result<Self, Self::Rejection> {
+        let key = parts
+            .headers
+            .get("Account-Key")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        let client = match database::api_client::authenticate(key, state).await {
+            Some(client) => client,
+            None => return Err(rejection(StatusCode::UNAUTHORIZED, "api_key", "Missing or invalid api key")),
+        };
+
+        if !client.has_scope(T::SCOPE) {
+            tracing::warn!("Api client {} is missing the {} scope", client.name, T::SCOPE);
+            let msg = format!("This api key does not have the {} scope", T::SCOPE);
+            return Err(rejection(StatusCode::FORBIDDEN, "api_key", &msg));
+        }
+
+        // rate limits further down the extractor chain count against the client instead of its ip
+        parts.extensions.insert(client.clone());
+
+        Ok(ApiKey {
+            client,
+            scope: PhantomData,
+        })
+    }
+}
+
+/// The route group a `RateLimited` extractor counts requests against
+pub trait RequiredRateLimit: Send + Sync {
+    const GROUP: RateLimitGroup;
+}
+
+pub struct SearchLimit;
+impl RequiredRateLimit for SearchLimit {
+    const GROUP: RateLimitGroup = RateLimitGroup::Search;
+}
+
+pub struct ChallengeLimit;
+impl RequiredRateLimit for ChallengeLimit {
+    const GROUP: RateLimitGroup = RateLimitGroup::Challenge;
+}
+
+pub struct LinkGenerateLimit;
+impl RequiredRateLimit for LinkGenerateLimit {
+    const GROUP: RateLimitGroup = RateLimitGroup::LinkGenerate;
+}
+
+/// Takes a token from the bucket of the caller for the group `T`, answering with a 429 and `Retry-After` once it runs dry.
+///
+/// Callers are told apart by api client when an `ApiKey` extractor ran before this one, then by the account logged into the session,
+/// and by ip otherwise. Requests without any of those are turned away. Anonymous sessions are not used since they cost nothing to throw away
+pub struct RateLimited<T: RequiredRateLimit> {
+    group: PhantomData<T>,
+}
+
+#[async_trait]
+impl<T: RequiredRateLimit> FromRequestParts<ApplicationState<AccountExtension>> for RateLimited<T> {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Result<Self, Self::Rejection> {
+        let account = match parts.extensions.get::<SessionHandle>() {
+            Some(session_handle) => {
+                let session = session_handle.read().await;
+                app::session::read::<String>(SessionKey::Account, &session).unwrap_or_default()
+            }
+            None => String::new(),
+        };
+
+        let caller = if let Some(client) = parts.extensions.get::<ApiClient>() {
+            format!("client:{}", client.id)
+        } else if !account.is_empty() {
+            format!("account:{}", account)
+        } else if let Some(ClientIp(ip)) = parts.extensions.get::<ClientIp>() {
+            format!("ip:{}", ip)
+        } else {
+            // everyone without an address would end up sharing one bucket
+            tracing::warn!("Unable to determine the client address for {}", T::GROUP);
+            return Err(rejection(
+                StatusCode::BAD_REQUEST,
+                "rate_limit",
+                "Unable to determine the client address",
+            ));
+        };
+
+        match state.extension.rate_limiter.take(T::GROUP, &caller) {
+            Ok(()) => Ok(RateLimited { group: PhantomData }),
+            Err(retry_after) => {
+                tracing::warn!("Rate limited {} on {}", caller, T::GROUP);
+                let msg = format!("Too many requests, try again in {} seconds", retry_after);
+                let mut response = rejection(StatusCode::TOO_MANY_REQUESTS, "rate_limit", &msg);
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                Err(response)
+            }
+        }
+    }
+}
+
+/// an `APIResponse` error sent back with the status instead of a 200
+fn rejection(status: StatusCode, field: &str, message: &str) -> Response {
+    let mut response = APIResponse::<()>::new();
+    response.error(field, message);
+    response.complete();
+    (status, Json(response)).into_response()
+}
@@ -1,5 +1,9 @@
-use crate::app::{self, extension::AccountExtension};
+use crate::app::policy::AccessDenial;
+use crate::app::{self, crypto, extension::AccountExtension};
+use crate::database::{self, account::ACCOUNT_TOKEN_BYTES};
+use crate::sync::discord::MemberSyncResult;
 
+use super::guards::{ApiKey, LinkGenerateLimit, LinkGenerateScope, RateLimited};
 use super::responses::LinkGeneratedResponse;
 use axum::Router;
 use levelcrush::{
@@ -7,17 +11,16 @@ use levelcrush::{
     axum::{
         self,
         extract::{Path, Query, State},
-        http::HeaderMap,
         response::Redirect,
         routing::{get, post},
         Json,
     },
     axum_sessions::extractors::WritableSession,
     cache::{CacheDuration, CacheValue},
-    md5,
     server::APIResponse,
+    tracing,
     urlencoding,
-    util::{slugify, unix_timestamp},
+    util::slugify,
 };
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
@@ -39,35 +42,25 @@ pub fn router() -> Router<ApplicationState<AccountExtension>> {
 }
 
 async fn link_generate(
-    headers: HeaderMap,
     State(mut state): State<ApplicationState<AccountExtension>>,
+    _client: ApiKey<LinkGenerateScope>,
+    _limit: RateLimited<LinkGenerateLimit>,
     Json(payload): Json<LinkGeneratePayload>,
 ) -> Json<APIResponse<LinkGeneratedResponse>> {
-    let key_header = match headers.get("Account-Key") {
-        Some(header_value) => header_value
-            .to_str()
-            .expect("Unable to convert header value to str"),
-        _ => "",
-    };
-
-    let server_key = state.extension.account_key.clone();
-    if server_key != key_header {
-        return Json(APIResponse::new());
-    }
-
     let mut response = APIResponse::new();
 
     let member = app::discord::member(&payload.id, &state).await;
-    if let Some(member) = member {
-        let input: String = format!(
-            "{}@{}::{}@{}",
-            member.account_token_secret,
-            member.username,
-            unix_timestamp(),
-            member.account_token,
-        );
-        let md5_digest = md5::compute(input);
-        let hash = format!("{:x}", md5_digest);
+    let denial = match &member {
+        Some(member) => ban_denial(member, &state).await,
+        None => None,
+    };
+
+    if let Some(denial) = denial {
+        tracing::info!("Link code for {} denied: {}", payload.id, denial);
+        response.error(denial.reason(), &denial.to_string());
+    } else if let Some(member) = member {
+        // the code is all it takes to link a platform to the account, so it has to be unguessable
+        let hash = crypto::random_token(ACCOUNT_TOKEN_BYTES);
 
         // store our hash
         // whena  user makes a request to /link/bungie or /link/twitch with  ?code=hash , if the has is found in link_gen cache, then we will trust them
@@ -109,14 +102,23 @@ async fn link_platform(
         }
     };
 
+    // the code may have been handed out before the account was banned
+    let denial = match &member {
+        Some(member) => ban_denial(member, &state).await,
+        None => None,
+    };
+    if let Some(denial) = denial {
+        tracing::info!("Link with code {} denied: {}", link_code, denial);
+        state.extension.link_gens.delete(&link_code).await;
+        let redirect_url = format!("{}/link/bad", state.extension.server_host);
+        return Redirect::temporary(&redirect_url);
+    }
+
     if let Some(member) = member {
         app::session::login(&mut session, member);
         let platform = slugify(&target_platform.to_lowercase());
-        let done_url = format!(
-            "{}/link/done?code={}",
-            state.extension.server_host,
-            urlencoding::encode(&link_code),
-        );
+        // relative, so it stays on our own host no matter which origins are allowed
+        let done_url = format!("/link/done?code={}", urlencoding::encode(&link_code));
         let redirect_url = format!(
             "{}/platform/{}/login?redirect={}",
             state.extension.server_host,
@@ -130,6 +132,14 @@ async fn link_platform(
     }
 }
 
+/// Why the account of the member may not link anything, if it is banned
+async fn ban_denial(member: &MemberSyncResult, state: &ApplicationState<AccountExtension>) -> Option<AccessDenial> {
+    let account = database::account::by_token(&member.account_token, state).await?;
+    database::ban::active(account.id, state)
+        .await
+        .map(|ban| AccessDenial::from(&ban))
+}
+
 async fn link_done(
     Query(query): Query<LinkQuery>,
     State(mut state): State<ApplicationState<AccountExtension>>,
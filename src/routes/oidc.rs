@@ -0,0 +1,412 @@
+use crate::app::extension::AccountExtension;
+use crate::app::jwt::{self, JwkSet};
+use crate::app::oidc::{
+    self, AccessGrant, AuthorizationGrant, OAUTH_ACCESS_TOKEN_LIFETIME, SCOPE_OPENID, SUPPORTED_SCOPES,
+};
+use crate::database::signing_key::SIGNING_ALGORITHM;
+use crate::routes::profile;
+use crate::{app, database};
+use axum::extract::{Query, RawQuery, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::{Form, Json, Router};
+use axum_sessions::extractors::ReadableSession;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use levelcrush::app::ApplicationState;
+use levelcrush::cache::{CacheDuration, CacheValue};
+use levelcrush::util::unix_timestamp;
+use levelcrush::{axum, axum_sessions, tracing, urlencoding};
+
+#[derive(serde::Deserialize, Default, Debug)]
+pub struct AuthorizeQuery {
+    #[serde(default)]
+    pub response_type: String,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub scope: String,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub code_challenge: String,
+    #[serde(default)]
+    pub code_challenge_method: String,
+}
+
+#[derive(serde::Deserialize, Default, Debug)]
+pub struct TokenRequest {
+    #[serde(default)]
+    pub grant_type: String,
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: String,
+    #[serde(default)]
+    pub code_verifier: String,
+}
+
+#[derive(serde::Serialize, Default, Debug)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub id_token: String,
+    pub scope: String,
+}
+
+#[derive(serde::Serialize, Default, Debug)]
+pub struct OpenIdConfiguration {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub scopes_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+}
+
+#[derive(serde::Serialize, Default, Debug)]
+struct OAuthErrorBody {
+    error: String,
+    error_description: String,
+}
+
+/// An error response as described in https://datatracker.ietf.org/doc/html/rfc6749#section-5.2
+#[derive(Debug)]
+pub struct OAuthError {
+    pub status: StatusCode,
+    pub error: &'static str,
+    pub description: String,
+}
+
+impl OAuthError {
+    fn new(status: StatusCode, error: &'static str, description: &str) -> OAuthError {
+        OAuthError {
+            status,
+            error,
+            description: description.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let body = OAuthErrorBody {
+            error: self.error.to_string(),
+            error_description: self.description,
+        };
+
+        if self.status == StatusCode::UNAUTHORIZED {
+            let challenge = format!("Bearer error=\"{}\"", self.error);
+            (
+                self.status,
+                [(header::WWW_AUTHENTICATE, challenge.as_str()), (header::CACHE_CONTROL, "no-store")],
+                Json(body),
+            )
+                .into_response()
+        } else {
+            (self.status, [(header::CACHE_CONTROL, "no-store")], Json(body)).into_response()
+        }
+    }
+}
+
+/// Lets downstream services log their users in through us with the authorization code flow.
+/// Clients are registered through the `oauth-client` job
+pub fn router() -> Router<ApplicationState<AccountExtension>> {
+    Router::new()
+        .route("/authorize", get(authorize))
+        .route("/token", post(token))
+        .route("/userinfo", get(userinfo))
+}
+
+/// sends the user back to the client with an error, only safe once the redirect uri has been checked against the client
+fn error_redirect(redirect_uri: &str, error: &str, description: &str, oauth_state: &Option<String>) -> Response {
+    let mut params = vec![("error", error), ("error_description", description)];
+    if let Some(oauth_state) = oauth_state {
+        params.push(("state", oauth_state.as_str()));
+    }
+    Redirect::temporary(&append_query(redirect_uri, &params)).into_response()
+}
+
+fn append_query(url: &str, params: &[(&str, &str)]) -> String {
+    let query = params
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+        .collect::<Vec<String>>()
+        .join("&");
+
+    let separator = if url.contains('?') { "&" } else { "?" };
+    format!("{}{}{}", url, separator, query)
+}
+
+/// Issues an authorization code for the session account and sends it back to the client.
+/// Users without a session are sent through the normal discord login first and land back here afterwards
+pub async fn authorize(
+    State(mut state): State<ApplicationState<AccountExtension>>,
+    RawQuery(raw_query): RawQuery,
+    Query(query): Query<AuthorizeQuery>,
+    session: ReadableSession,
+) -> Response {
+    // until the redirect uri is known to belong to the client we must not send the user anywhere
+    let client = database::oauth_client::get(&query.client_id, &state).await;
+    let client = match client {
+        Some(client) if client.allows_redirect(&query.redirect_uri) => client,
+        _ => {
+            tracing::warn!("Rejected authorize request for client {}", query.client_id);
+            return (StatusCode::BAD_REQUEST, "Unknown client or redirect uri").into_response();
+        }
+    };
+
+    if query.response_type != "code" {
+        return error_redirect(
+            &query.redirect_uri,
+            "unsupported_response_type",
+            "Only the code response type is supported",
+            &query.state,
+        );
+    }
+
+    let scopes = oidc::parse_scopes(&query.scope);
+    if !scopes.iter().any(|scope| scope == SCOPE_OPENID) {
+        return error_redirect(
+            &query.redirect_uri,
+            "invalid_scope",
+            "The openid scope is required",
+            &query.state,
+        );
+    }
+
+    if !query.code_challenge.is_empty() && query.code_challenge_method != "S256" {
+        return error_redirect(
+            &query.redirect_uri,
+            "invalid_request",
+            "Only S256 code challenges are supported",
+            &query.state,
+        );
+    }
+
+    let account = match app::session::account(&session, &state).await {
+        Some(account) => account,
+        None => {
+            // relative, so it stays on our own host no matter which origins are allowed
+            let authorize_url = format!("/oauth/authorize?{}", raw_query.unwrap_or_default());
+            let login_url = format!("/login?redirect={}", urlencoding::encode(&authorize_url));
+            return Redirect::temporary(&login_url).into_response();
+        }
+    };
+
+    if let Err(denial) = profile::view(&account, &state).await {
+        tracing::info!("Denied {} access to client {}: {}", account.token, client.client_id, denial);
+        return error_redirect(&query.redirect_uri, "access_denied", &denial.to_string(), &query.state);
+    }
+
+    let code = app::crypto::random_token(32);
+    let grant = AuthorizationGrant {
+        client_id: client.client_id.clone(),
+        redirect_uri: query.redirect_uri.clone(),
+        account_token: account.token.clone(),
+        scopes,
+        nonce: query.nonce.clone(),
+        code_challenge: query.code_challenge.clone(),
+        auth_time: unix_timestamp(),
+    };
+    state
+        .extension
+        .oauth_codes
+        .write(
+            code.clone(),
+            CacheValue::with_duration(grant, CacheDuration::Minute, CacheDuration::Minute),
+        )
+        .await;
+
+    let mut params = vec![("code", code.as_str())];
+    if let Some(oauth_state) = &query.state {
+        params.push(("state", oauth_state.as_str()));
+    }
+    Redirect::temporary(&append_query(&query.redirect_uri, &params)).into_response()
+}
+
+/// client credentials from the basic auth header, falling back to the ones posted in the body
+fn client_credentials(headers: &HeaderMap, request: &TokenRequest) -> (String, String) {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| STANDARD.decode(value.trim()).ok())
+        .and_then(|value| String::from_utf8(value).ok());
+
+    if let Some((client_id, client_secret)) = basic.as_deref().and_then(|basic| basic.split_once(':')) {
+        let client_id = urlencoding::decode(client_id).map(|v| v.into_owned()).unwrap_or_default();
+        let client_secret = urlencoding::decode(client_secret)
+            .map(|v| v.into_owned())
+            .unwrap_or_default();
+        (client_id, client_secret)
+    } else {
+        (request.client_id.clone(), request.client_secret.clone())
+    }
+}
+
+/// Trades an authorization code in for an access token and a signed id token
+pub async fn token(
+    State(mut state): State<ApplicationState<AccountExtension>>,
+    headers: HeaderMap,
+    Form(request): Form<TokenRequest>,
+) -> Result<Response, OAuthError> {
+    if request.grant_type != "authorization_code" {
+        return Err(OAuthError::new(
+            StatusCode::BAD_REQUEST,
+            "unsupported_grant_type",
+            "Only the authorization_code grant is supported",
+        ));
+    }
+
+    let (client_id, client_secret) = client_credentials(&headers, &request);
+    let client = database::oauth_client::authenticate(&client_id, &client_secret, &state)
+        .await
+        .ok_or_else(|| OAuthError::new(StatusCode::UNAUTHORIZED, "invalid_client", "Client authentication failed"))?;
+
+    let invalid_grant =
+        || OAuthError::new(StatusCode::BAD_REQUEST, "invalid_grant", "The code is invalid or has expired");
+
+    // codes are single use, even when the exchange fails
+    let grant = state.extension.oauth_codes.access(&request.code).await;
+    if grant.is_some() {
+        state.extension.oauth_codes.delete(&request.code).await;
+    }
+    let grant = grant.ok_or_else(invalid_grant)?;
+
+    if grant.client_id != client.client_id || grant.redirect_uri != request.redirect_uri {
+        return Err(invalid_grant());
+    }
+
+    if !grant.verify_pkce(&request.code_verifier) {
+        return Err(OAuthError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_grant",
+            "The code verifier does not match the code challenge",
+        ));
+    }
+
+    let account = database::account::by_token(&grant.account_token, &state)
+        .await
+        .ok_or_else(invalid_grant)?;
+
+    // access could have been lost between authorizing and redeeming the code
+    let profile = profile::view(&account, &state)
+        .await
+        .map_err(|denial| OAuthError::new(StatusCode::BAD_REQUEST, "invalid_grant", &denial.to_string()))?;
+
+    let user = oidc::user_info(&account, &profile, &grant.scopes);
+    let id_token = oidc::id_token(&grant, user, &state).await.ok_or_else(|| {
+        OAuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Unable to issue tokens")
+    })?;
+
+    let access_token = app::crypto::random_token(32);
+    let access_grant = AccessGrant {
+        client_id: client.client_id.clone(),
+        account_token: account.token.clone(),
+        scopes: grant.scopes.clone(),
+    };
+    state
+        .extension
+        .oauth_tokens
+        .write(
+            access_token.clone(),
+            CacheValue::with_duration(access_grant, CacheDuration::TenMinutes, CacheDuration::TenMinutes),
+        )
+        .await;
+
+    let response = TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: OAUTH_ACCESS_TOKEN_LIFETIME,
+        id_token,
+        scope: grant.scopes.join(" "),
+    };
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
+
+/// Claims about the account the access token was issued for
+pub async fn userinfo(
+    State(state): State<ApplicationState<AccountExtension>>,
+    headers: HeaderMap,
+) -> Result<Json<oidc::UserInfo>, OAuthError> {
+    let invalid_token =
+        || OAuthError::new(StatusCode::UNAUTHORIZED, "invalid_token", "The access token is invalid or has expired");
+
+    let access_token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    let grant = state
+        .extension
+        .oauth_tokens
+        .access(access_token)
+        .await
+        .ok_or_else(invalid_token)?;
+
+    let account = database::account::by_token(&grant.account_token, &state)
+        .await
+        .ok_or_else(invalid_token)?;
+
+    let profile = profile::view(&account, &state)
+        .await
+        .map_err(|denial| OAuthError::new(StatusCode::FORBIDDEN, "access_denied", &denial.to_string()))?;
+
+    Ok(Json(oidc::user_info(&account, &profile, &grant.scopes)))
+}
+
+/// https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderMetadata
+pub async fn configuration(State(state): State<ApplicationState<AccountExtension>>) -> Json<OpenIdConfiguration> {
+    let issuer = state.extension.server_host.clone();
+    let to_strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<String>>();
+
+    Json(OpenIdConfiguration {
+        authorization_endpoint: format!("{}/oauth/authorize", issuer),
+        token_endpoint: format!("{}/oauth/token", issuer),
+        userinfo_endpoint: format!("{}/oauth/userinfo", issuer),
+        jwks_uri: format!("{}/.well-known/jwks.json", issuer),
+        issuer,
+        response_types_supported: to_strings(&["code"]),
+        grant_types_supported: to_strings(&["authorization_code"]),
+        subject_types_supported: to_strings(&["public"]),
+        id_token_signing_alg_values_supported: to_strings(&[SIGNING_ALGORITHM]),
+        scopes_supported: to_strings(&SUPPORTED_SCOPES),
+        token_endpoint_auth_methods_supported: to_strings(&["client_secret_basic", "client_secret_post"]),
+        code_challenge_methods_supported: to_strings(&["S256"]),
+        claims_supported: to_strings(&[
+            "sub",
+            "iss",
+            "aud",
+            "exp",
+            "iat",
+            "auth_time",
+            "nonce",
+            "name",
+            "is_admin",
+            "platforms",
+            "discord_roles",
+        ]),
+    })
+}
+
+/// The public keys access and id tokens are signed with. Consumers should refetch this when they see a `kid` they do not know
+pub async fn jwks(State(state): State<ApplicationState<AccountExtension>>) -> Json<JwkSet> {
+    Json(jwt::jwks(&state).await)
+}
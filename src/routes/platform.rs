@@ -3,8 +3,11 @@ use levelcrush::{app::ApplicationState, axum};
 
 use crate::app::extension::AccountExtension;
 
+pub mod battlenet;
 pub mod bungie;
 pub mod discord;
+pub mod provider;
+pub mod steam;
 pub mod twitch;
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -27,6 +30,18 @@ pub struct OAuthLoginValidationRequest {
     pub code: String,
     pub redirect_uri: String,
     pub scope: String,
+
+    /// PKCE verifier, only sent when the authorization included a code challenge
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub code_verifier: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct OAuthRefreshRequest {
+    pub client_id: String,
+    pub client_secret: String,
+    pub grant_type: String,
+    pub refresh_token: String,
 }
 
 pub fn router() -> Router<ApplicationState<AccountExtension>> {
@@ -34,4 +49,6 @@ pub fn router() -> Router<ApplicationState<AccountExtension>> {
         .nest("/discord", discord::router())
         .nest("/twitch", twitch::router())
         .nest("/bungie", bungie::router())
+        .nest("/steam", steam::router())
+        .nest("/battlenet", battlenet::router())
 }
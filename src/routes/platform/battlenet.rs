@@ -0,0 +1,194 @@
+use crate::app::extension::AccountExtension;
+use crate::app::platform::{PlatformProvider, PlatformTokens};
+use crate::app::session::SessionKey;
+use crate::database::platform::AccountPlatformType;
+use crate::database::platform_data::NewAccountPlatformData;
+use crate::routes::platform::provider;
+use axum::Router;
+use levelcrush::app::ApplicationState;
+use levelcrush::tracing;
+use levelcrush::{axum, urlencoding};
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct BattleNetValidationResponse {
+    pub access_token: String,
+
+    #[serde(default)]
+    pub expires_in: i64,
+
+    #[serde(default)]
+    pub scope: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone)]
+pub struct BattleNetUserResponse {
+    pub id: i64,
+    pub battletag: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct OAuthLoginValidationRequest {
+    pub grant_type: String,
+    pub code: String,
+    pub redirect_uri: String,
+    pub scope: String,
+}
+
+/// Battle.net oauth lives on a different host depending on the region the client was registered in.
+/// China has its own domain, every other region shares the same pattern
+/// https://develop.battle.net/documentation/guides/regionality-and-apis
+fn get_oauth_host(region: &str) -> String {
+    match region.to_lowercase().as_str() {
+        "cn" => "https://oauth.battlenet.com.cn".to_string(),
+        "us" | "eu" | "kr" | "tw" => format!("https://{}.battle.net/oauth", region.to_lowercase()),
+        _ => "https://oauth.battle.net".to_string(),
+    }
+}
+
+pub struct BattleNetProvider;
+
+pub fn router() -> Router<ApplicationState<AccountExtension>> {
+    provider::router::<BattleNetProvider>()
+}
+
+#[async_trait::async_trait]
+impl PlatformProvider for BattleNetProvider {
+    type Validation = BattleNetValidationResponse;
+    type Identity = BattleNetUserResponse;
+
+    fn platform() -> AccountPlatformType {
+        AccountPlatformType::BattleNet
+    }
+
+    fn session_state_key() -> SessionKey {
+        SessionKey::PlatformBattleNetState
+    }
+
+    fn session_caller_key() -> SessionKey {
+        SessionKey::PlatformBattleNetCallerUrl
+    }
+
+    /// battle.net does not support PKCE, the state is our only protection here
+    fn authorize_url(oauth_state: &str, _code_challenge: &str, state: &ApplicationState<AccountExtension>) -> String {
+        let oauth_host = get_oauth_host(&state.extension.battlenet_region);
+        let client_id = state.extension.battlenet_client_id.clone();
+        let authorize_redirect = state.extension.battlenet_validate_url.clone();
+        let scopes = vec!["openid"].join("+");
+
+        format!(
+            "{}/authorize?response_type={}&client_id={}&scope={}&state={}&redirect_uri={}",
+            oauth_host,
+            "code",
+            urlencoding::encode(client_id.as_str()),
+            scopes,
+            urlencoding::encode(oauth_state),
+            urlencoding::encode(authorize_redirect.as_str()),
+        )
+    }
+
+    async fn exchange_token(
+        code: &str,
+        _code_verifier: &str,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Option<BattleNetValidationResponse> {
+        let oauth_host = get_oauth_host(&state.extension.battlenet_region);
+        let client_id = state.extension.battlenet_client_id.clone();
+        let client_secret = state.extension.battlenet_client_secret.clone();
+        let form_body = serde_urlencoded::to_string(OAuthLoginValidationRequest {
+            grant_type: "authorization_code".to_string(),
+            code: code.to_string(),
+            redirect_uri: state.extension.battlenet_validate_url.clone(),
+            scope: "openid".to_string(),
+        })
+        .unwrap_or_default();
+
+        let request = state
+            .extension
+            .http_client
+            .post(format!("{}/token", oauth_host))
+            .body(form_body)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept", "application/json")
+            .basic_auth(client_id, Some(client_secret))
+            .send()
+            .await;
+
+        match request {
+            Ok(oauth_result) => {
+                let oauth_result = oauth_result.json::<BattleNetValidationResponse>().await;
+                match oauth_result {
+                    Ok(oauth_result) => Some(oauth_result),
+                    Err(err) => {
+                        tracing::error!("Could not parse validation response for battle.net!");
+                        tracing::error!("{}", err);
+                        None
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::error!("Request Error: {}", err);
+                None
+            }
+        }
+    }
+
+    async fn identity(
+        validation: &BattleNetValidationResponse,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Option<BattleNetUserResponse> {
+        let oauth_host = get_oauth_host(&state.extension.battlenet_region);
+        let request = state
+            .extension
+            .http_client
+            .get(format!("{}/userinfo", oauth_host))
+            .bearer_auth(validation.access_token.as_str())
+            .header("Accept", "application/json")
+            .send()
+            .await;
+
+        if let Ok(request) = request {
+            let result = request.json::<BattleNetUserResponse>().await;
+            match result {
+                Ok(result) => Some(result),
+                Err(error) => {
+                    tracing::error!("Could not parse battle.net user response {}", error);
+                    None
+                }
+            }
+        } else {
+            tracing::error!("Could not fetch battle.net user");
+            None
+        }
+    }
+
+    fn platform_user(identity: &BattleNetUserResponse) -> String {
+        identity.id.to_string()
+    }
+
+    fn platform_data(identity: &BattleNetUserResponse) -> Vec<NewAccountPlatformData> {
+        vec![
+            NewAccountPlatformData {
+                key: "battlenet_id".to_string(),
+                value: identity.id.to_string(),
+            },
+            NewAccountPlatformData {
+                key: "battletag".to_string(),
+                value: identity.battletag.clone(),
+            },
+            NewAccountPlatformData {
+                key: "display_name".to_string(),
+                value: identity.battletag.clone(),
+            },
+        ]
+    }
+
+    /// battle.net does not issue refresh tokens, so the user has to link again once the access token expires
+    fn tokens(validation: &BattleNetValidationResponse) -> Option<PlatformTokens> {
+        Some(PlatformTokens {
+            access_token: validation.access_token.clone(),
+            refresh_token: String::new(),
+            expires_in: validation.expires_in,
+            scopes: validation.scope.split(' ').map(|scope| scope.to_string()).collect(),
+        })
+    }
+}
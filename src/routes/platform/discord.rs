@@ -1,59 +1,104 @@
 use crate::app;
 use crate::app::extension::AccountExtension;
+use crate::app::platform::{PlatformProvider, PlatformTokens};
+use crate::app::policy::{self, AccessDenial, AccessSubject};
 use crate::app::session::SessionKey;
-use crate::routes::platform::{OAuthLoginQueries, OAuthLoginValidationQueries};
-use crate::routes::responses::DiscordGuild;
+use crate::database::event::AccountEventKind;
+use crate::database::platform::AccountPlatformType;
+use crate::database::platform_data::NewAccountPlatformData;
+use crate::routes::platform::{provider, OAuthLoginValidationQueries};
+use crate::routes::responses::{DiscordUserResponse, DiscordValidationResponse};
+use crate::{database, sync};
 use axum::extract::{Query, State};
 use axum::response::Redirect;
 use axum::routing::get;
 use axum::Router;
 use axum_sessions::extractors::WritableSession;
 use levelcrush::app::ApplicationState;
+use levelcrush::axum_sessions;
 use levelcrush::tracing;
-use levelcrush::util::unix_timestamp;
 use levelcrush::{axum, urlencoding};
-use levelcrush::{axum_sessions, md5};
 
+pub struct DiscordProvider;
+
+/// Discord is our primary login, so unlike the other platforms its routes are not guarded
+/// and validating logs the user in instead of linking to an existing account
 pub fn router() -> Router<ApplicationState<AccountExtension>> {
     Router::new()
-        .route("/login", get(login))
+        .route("/login", get(provider::login::<DiscordProvider>))
         .route("/validate", get(validate))
 }
 
-pub async fn login(
-    State(state): State<ApplicationState<AccountExtension>>,
-    Query(login_fields): Query<OAuthLoginQueries>,
-    mut session: WritableSession,
-) -> Redirect {
-    // make sure we know where to return our user to after they are done logging in
-    let server_url = state.extension.server_host.clone();
-    let fallback_url = state.extension.fallback_url.clone();
-    let final_fallback_url = fallback_url;
-    let final_redirect = login_fields.redirect.unwrap_or(final_fallback_url);
-
-    let client_id = state.extension.discord_client_id;
-    let authorize_redirect = state.extension.discord_validate_url;
-    let scopes = vec!["identify", "guilds"].join("+");
-
-    let hash_input = md5::compute(format!("{}||{}", client_id, unix_timestamp()));
-    let discord_state = format!("{:x}", hash_input);
-    let authorize_url = format!("https://discord.com/api/oauth2/authorize?response_type={}&client_id={}&scope={}&state={}&redirect_uri={}&prompt={}",
-                                "code",
-                                urlencoding::encode(client_id.as_str()),
-                                scopes,
-                                urlencoding::encode(discord_state.as_str()),
-                                urlencoding::encode(authorize_redirect.as_str()),
-                                "none"//"consent"
-    );
-
-    // store discord state check and final redirect in session
-    app::session::write(SessionKey::PlatformDiscordState, discord_state, &mut session);
-
-    // store original url that this route was called from
-    app::session::write(SessionKey::PlatformDiscordCallerUrl, final_redirect, &mut session);
-
-    // Now redirect
-    Redirect::temporary(authorize_url.as_str())
+#[async_trait::async_trait]
+impl PlatformProvider for DiscordProvider {
+    type Validation = DiscordValidationResponse;
+    type Identity = DiscordUserResponse;
+
+    fn platform() -> AccountPlatformType {
+        AccountPlatformType::Discord
+    }
+
+    fn session_state_key() -> SessionKey {
+        SessionKey::PlatformDiscordState
+    }
+
+    fn session_caller_key() -> SessionKey {
+        SessionKey::PlatformDiscordCallerUrl
+    }
+
+    fn authorize_url(oauth_state: &str, code_challenge: &str, state: &ApplicationState<AccountExtension>) -> String {
+        let client_id = state.extension.discord_client_id.clone();
+        let authorize_redirect = state.extension.discord_validate_url.clone();
+        let scopes = vec!["identify", "guilds"].join("+");
+
+        format!("https://discord.com/api/oauth2/authorize?response_type={}&client_id={}&scope={}&state={}&redirect_uri={}&prompt={}&code_challenge={}&code_challenge_method={}",
+                "code",
+                urlencoding::encode(client_id.as_str()),
+                scopes,
+                urlencoding::encode(oauth_state),
+                urlencoding::encode(authorize_redirect.as_str()),
+                "none",//"consent"
+                urlencoding::encode(code_challenge),
+                "S256"
+        )
+    }
+
+    async fn exchange_token(
+        code: &str,
+        code_verifier: &str,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Option<DiscordValidationResponse> {
+        app::discord::validate_oauth(code, code_verifier, state).await
+    }
+
+    async fn identity(
+        validation: &DiscordValidationResponse,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Option<DiscordUserResponse> {
+        app::discord::member_oauth_api(&validation.access_token, state).await
+    }
+
+    fn platform_user(identity: &DiscordUserResponse) -> String {
+        identity.id.clone().unwrap_or_default()
+    }
+
+    fn platform_data(identity: &DiscordUserResponse) -> Vec<NewAccountPlatformData> {
+        sync::discord::platform_data(identity)
+    }
+
+    fn tokens(validation: &DiscordValidationResponse) -> Option<PlatformTokens> {
+        Some(PlatformTokens {
+            access_token: validation.access_token.clone(),
+            refresh_token: validation.refresh_token.clone(),
+            expires_in: validation.expires_in,
+            scopes: validation.scope.split(' ').map(|scope| scope.to_string()).collect(),
+        })
+    }
+
+    async fn refresh_token(refresh_token: &str, state: &ApplicationState<AccountExtension>) -> Option<PlatformTokens> {
+        let validation = app::discord::refresh_oauth(refresh_token, state).await?;
+        DiscordProvider::tokens(&validation)
+    }
 }
 
 pub async fn validate(
@@ -61,7 +106,6 @@ pub async fn validate(
     State(mut state): State<ApplicationState<AccountExtension>>,
     mut session: WritableSession,
 ) -> Redirect {
-    let query_fields = validation_query;
     // make sure we know where to return our user to after they are done logging in
     let fallback_url = state.extension.fallback_url.clone();
     let final_fallback_url = fallback_url;
@@ -69,70 +113,74 @@ pub async fn validate(
     let mut final_redirect =
         app::session::read(SessionKey::PlatformDiscordCallerUrl, &session).unwrap_or(final_fallback_url);
 
-    let mut do_process = true;
-    let validation_state = query_fields.state.unwrap_or_default();
-    let session_state = app::session::read::<String>(SessionKey::PlatformDiscordState, &session).unwrap_or_default();
-
-    let oauth_code = query_fields.code.unwrap_or_default();
-    let oauth_error = query_fields.error.unwrap_or_default();
-
-    // make sure we don't have an error and we have a code that we can check
-    if !oauth_error.is_empty() {
-        do_process = false;
-        tracing::warn!("There was an error found in the oauth request {}", oauth_error);
-    }
-
-    if oauth_code.is_empty() {
-        tracing::warn!("There was no code present in the oauth request");
-        do_process = false;
-    }
-
-    if validation_state != session_state {
-        tracing::warn!(
-            "Validation State and Session state did not match: Discord ({}) || Session({})",
-            validation_state,
-            session_state
-        );
-        do_process = false;
-    }
-
     // if we are not yet allowed to process then go ahead and simply return immediately to our final redirect url that we know about
-    if !do_process {
-        return Redirect::temporary(final_redirect.as_str());
-    }
+    let callback = match provider::verify_callback::<DiscordProvider>(validation_query, &mut session) {
+        Some(callback) => callback,
+        None => return Redirect::temporary(final_redirect.as_str()),
+    };
 
     // now validate the code returned to us if we are allowed to process
-    let validation_response = app::discord::validate_oauth(&oauth_code, &state).await;
-    let mut access_token = String::new();
-    let member_sync = if let Some(validation) = validation_response {
-        access_token = validation.access_token.to_string();
-        app::discord::member_oauth(&access_token, &state).await
+    let validation_response = DiscordProvider::exchange_token(&callback.code, &callback.code_verifier, &state).await;
+    let member_sync = if let Some(validation) = &validation_response {
+        app::discord::member_oauth(&validation.access_token, &state).await
     } else {
         None
     };
 
-    // now get the list of guilds they are in and if they have at least one of the guild ids in the allowed server list then we are in good shape
-    let is_allowed = if let Some(member) = &member_sync {
-        let discord_guilds = app::discord::member_oauth_guilds_api(&access_token, &state).await;
-        discord_guilds
-            .into_iter()
-            .any(|g| state.extension.allowed_discords.contains(&g.id))
+    // the roles and oauth guilds we just synced along with everything else linked to the account decide if they are allowed in
+    let access = if let Some(member) = &member_sync {
+        let account = database::account::by_token(&member.account_token, &state).await;
+        let ban = match &account {
+            Some(account) => database::ban::active(account.id, &state).await,
+            None => None,
+        };
+        if let Some(ban) = ban {
+            Err(AccessDenial::from(&ban))
+        } else if let Some(account) = account {
+            let platforms = database::account::all_data(&account, &state).await;
+            let subject = AccessSubject::from_platforms(account.admin == 1, &platforms);
+            policy::evaluate(&state.extension.access_policy, &subject)
+        } else {
+            Err(AccessDenial::Unauthenticated)
+        }
     } else {
-        false
+        Err(AccessDenial::Unauthenticated)
     };
 
-    if is_allowed {
+    if let Err(denial) = &access {
+        tracing::info!("Login denied: {}", denial);
+        let param_type = if final_redirect.contains('?') { "&" } else { "?" };
+        final_redirect = format!("{final_redirect}{param_type}error=NotAllowed&reason={}", denial.reason());
+    } else {
         if let Some(member) = member_sync {
             app::session::login(&mut session, member);
         }
 
+        // keep the tokens around so we can call discord on behalf of the user later
+        let tokens = validation_response.as_ref().and_then(DiscordProvider::tokens);
+        let account_platform = match app::session::account(&session, &state).await {
+            Some(account) => database::platform::from_account(&account, AccountPlatformType::Discord, &state).await,
+            None => None,
+        };
+        if let Some(account_platform) = &account_platform {
+            database::event::record(
+                AccountEventKind::Login,
+                account_platform.account,
+                Some(account_platform),
+                &(),
+                &(),
+                &state,
+            )
+            .await;
+        }
+        if let (Some(account_platform), Some(tokens)) = (account_platform, tokens) {
+            database::platform_tokens::write(&account_platform, &tokens, &state).await;
+        }
+
         let discord_username = app::session::read::<String>(SessionKey::Username, &session).unwrap_or_default();
         let search_cache_key = format!("search_discord||{}", discord_username);
         tracing::info!("Busting search key: {}", search_cache_key);
         state.extension.searches.delete(&search_cache_key).await;
-    } else {
-        let param_type = if final_redirect.contains("?") { "&" } else { "?" };
-        final_redirect = format!("{final_redirect}{param_type}error=NotAllowed")
     }
 
     // no matter what we redirect back to our caller
@@ -0,0 +1,315 @@
+use crate::app::extension::AccountExtension;
+use crate::app::platform::{PendingPlatformLink, PlatformOAuthState, PlatformProvider, OAUTH_STATE_LIFETIME};
+use crate::app::session::SessionKey;
+use crate::database::platform::{AccountPlatform, AccountPlatformType, NewAccountPlatform};
+use crate::database::platform_data::NewAccountPlatformData;
+use crate::routes::guards;
+use crate::routes::platform::{OAuthLoginQueries, OAuthLoginValidationQueries};
+use crate::routes::profile::CACHE_KEY_PROFILE;
+use crate::{app, database};
+use axum::extract::{Query, State};
+use axum::response::Redirect;
+use axum::routing::get;
+use axum::Router;
+use axum_sessions::async_session::Session;
+use axum_sessions::extractors::{ReadableSession, WritableSession};
+use levelcrush::app::ApplicationState;
+use levelcrush::util::unix_timestamp;
+use levelcrush::{axum, axum_sessions, tracing};
+
+/// Mounts the login/validate/unlink routes for any platform provider.
+/// All routes require a logged in session since these platforms are linked to an existing account
+pub fn router<P: PlatformProvider>() -> Router<ApplicationState<AccountExtension>> {
+    Router::new()
+        .route("/login", get(login::<P>))
+        .route("/validate", get(validate::<P>))
+        .route("/unlink", get(unlink::<P>))
+        .route("/confirm", get(confirm::<P>))
+        .route("/cancel", get(cancel::<P>))
+        .route_layer(axum::middleware::from_fn(guards::session_logged_in))
+}
+
+pub async fn login<P: PlatformProvider>(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Query(login_fields): Query<OAuthLoginQueries>,
+    mut session: WritableSession,
+) -> Redirect {
+    // make sure we know where to return our user to after they are done logging in
+    let final_redirect = app::redirect::resolve(login_fields.redirect, &state);
+
+    let oauth_state = PlatformOAuthState::new();
+    let authorize_url = P::authorize_url(&oauth_state.state, &oauth_state.code_challenge(), &state);
+
+    // store state check, pkce verifier and final redirect in session
+    app::session::write(P::session_state_key(), oauth_state, &mut session);
+
+    // store original url that this route was called from
+    app::session::write(P::session_caller_key(), final_redirect, &mut session);
+
+    // Now redirect
+    Redirect::temporary(authorize_url.as_str())
+}
+
+pub async fn validate<P: PlatformProvider>(
+    Query(validation_query): Query<OAuthLoginValidationQueries>,
+    State(mut state): State<ApplicationState<AccountExtension>>,
+    mut session: WritableSession,
+) -> Redirect {
+    let final_fallback_url = state.extension.fallback_url.clone();
+    let mut final_redirect =
+        app::session::read::<String>(P::session_caller_key(), &session).unwrap_or(final_fallback_url);
+
+    // if we are not allowed to process then go ahead and simply return immediately to our final redirect url that we know about
+    let callback = match verify_callback::<P>(validation_query, &mut session) {
+        Some(callback) => callback,
+        None => return Redirect::temporary(final_redirect.as_str()),
+    };
+
+    // now validate the code returned to us
+    tracing::info!("Validating {} oauth", P::platform());
+    let validation = P::exchange_token(&callback.code, &callback.code_verifier, &state).await;
+    let identity = if let Some(validation) = &validation {
+        tracing::info!("Validated. Getting more information about the {} user", P::platform());
+        P::identity(validation, &state).await
+    } else {
+        None
+    };
+
+    // only link if we have some user data present in our response
+    // no point in querying the database if we have no way to link it
+    if let Some(identity) = identity {
+        let platform_user = P::platform_user(&identity);
+        let data = P::platform_data(&identity);
+        match link(P::platform(), platform_user, &data, false, &mut session, &state).await {
+            LinkOutcome::Linked(account_platform) => {
+                // keep the tokens around so we can call the platform on behalf of the user later
+                if let Some(tokens) = validation.as_ref().and_then(P::tokens) {
+                    database::platform_tokens::write(&account_platform, &tokens, &state).await;
+                }
+            }
+            LinkOutcome::Conflict => {
+                final_redirect = conflict_redirect(&final_redirect, P::platform());
+            }
+            LinkOutcome::Failed => {}
+        }
+    }
+
+    bust_cache(&session, &mut state).await;
+
+    // no matter what we redirect back to our caller
+    Redirect::temporary(final_redirect.as_str())
+}
+
+pub async fn unlink<P: PlatformProvider>(
+    Query(fields): Query<OAuthLoginQueries>,
+    State(mut state): State<ApplicationState<AccountExtension>>,
+    session: ReadableSession,
+) -> Redirect {
+    // make sure we know where to return our user to after they are done
+    let final_redirect = app::redirect::resolve(fields.redirect, &state);
+
+    // find the account platform tied to the account in our session
+    let account_platform = match app::session::account(&session, &state).await {
+        Some(account) => database::platform::from_account(&account, P::platform(), &state).await,
+        None => None,
+    };
+
+    // if we found it , we can go ahead and perform all of our unlink operations on it
+    if let Some(account_platform) = account_platform {
+        tracing::info!("Unlinking {}!", P::platform());
+        database::platform::unlink(&account_platform, &state).await;
+    }
+
+    bust_cache(&session, &mut state).await;
+
+    // Now redirect
+    Redirect::temporary(final_redirect.as_str())
+}
+
+/// An oauth callback that matched the state in the session and can be traded in with the platform
+pub struct VerifiedCallback {
+    pub code: String,
+    pub code_verifier: String,
+}
+
+/// Moves a platform that was put on hold by a link conflict over to the session account.
+/// The tokens from the original link are not kept while the link is on hold, so those only come back on the next link
+pub async fn confirm<P: PlatformProvider>(
+    Query(fields): Query<OAuthLoginQueries>,
+    State(mut state): State<ApplicationState<AccountExtension>>,
+    mut session: WritableSession,
+) -> Redirect {
+    let final_redirect = app::redirect::resolve(fields.redirect, &state);
+
+    let pending = take_pending_link::<P>(&mut session);
+    if let Some(pending) = pending {
+        tracing::info!(
+            "Confirmed moving {} account {} away from account {}",
+            P::platform(),
+            pending.platform_user,
+            pending.account
+        );
+        link(P::platform(), pending.platform_user, &pending.data, true, &mut session, &state).await;
+    }
+
+    bust_cache(&session, &mut state).await;
+    Redirect::temporary(final_redirect.as_str())
+}
+
+/// Drops a link that was put on hold by a link conflict, leaving the platform with the account it is already linked to
+pub async fn cancel<P: PlatformProvider>(
+    Query(fields): Query<OAuthLoginQueries>,
+    State(state): State<ApplicationState<AccountExtension>>,
+    mut session: WritableSession,
+) -> Redirect {
+    let final_redirect = app::redirect::resolve(fields.redirect, &state);
+
+    take_pending_link::<P>(&mut session);
+    Redirect::temporary(final_redirect.as_str())
+}
+
+/// Removes the pending link from the session, only returning it if it belongs to this platform and has not expired
+fn take_pending_link<P: PlatformProvider>(session: &mut Session) -> Option<PendingPlatformLink> {
+    let pending = app::session::read::<PendingPlatformLink>(SessionKey::PlatformPendingLink, session);
+    session.remove(SessionKey::PlatformPendingLink.into());
+    pending.filter(|pending| pending.platform == P::platform().to_string() && !pending.is_expired())
+}
+
+/// Tells the caller the link is waiting on the user to confirm moving the platform away from another account
+pub fn conflict_redirect(final_redirect: &str, platform: AccountPlatformType) -> String {
+    let param_type = if final_redirect.contains('?') { "&" } else { "?" };
+    format!("{final_redirect}{param_type}error=PlatformConflict&platform={platform}")
+}
+
+/// Checks the oauth callback against the state stored in the session
+/// The state is removed from the session, so a callback can only be verified once
+/// Returns the oauth code and pkce verifier if it is safe to continue processing
+pub fn verify_callback<P: PlatformProvider>(
+    validation_query: OAuthLoginValidationQueries,
+    session: &mut Session,
+) -> Option<VerifiedCallback> {
+    let mut do_process = true;
+    let validation_state = validation_query.state.unwrap_or_default();
+    let oauth_state = PlatformOAuthState::take(P::session_state_key(), session).unwrap_or_default();
+    let session_state = oauth_state.state;
+
+    let oauth_code = validation_query.code.unwrap_or_default();
+    let oauth_error = validation_query.error.unwrap_or_default();
+
+    // make sure we don't have an error and we have a code that we can check
+    if !oauth_error.is_empty() {
+        do_process = false;
+        tracing::warn!("There was an error found in the oauth request {}", oauth_error);
+    }
+
+    if oauth_code.is_empty() {
+        tracing::warn!("There was no code present in the oauth request");
+        do_process = false;
+    }
+
+    if session_state.is_empty() || validation_state != session_state {
+        tracing::warn!(
+            "Validation State and Session state did not match: {} ({}) || Session({})",
+            P::platform(),
+            validation_state,
+            session_state
+        );
+        do_process = false;
+    }
+
+    if do_process {
+        Some(VerifiedCallback {
+            code: oauth_code,
+            code_verifier: oauth_state.code_verifier,
+        })
+    } else {
+        None
+    }
+}
+
+/// What happened when linking a platform user to the session account
+pub enum LinkOutcome {
+    Linked(AccountPlatform),
+    /// the platform user belongs to another account. The link is waiting in the session for the user to confirm
+    Conflict,
+    Failed,
+}
+
+/// Links the platform user to the account in the session and writes out the platform data.
+/// If the platform user is already linked to another account, it is only moved over when `transfer` is set.
+/// Otherwise the link is held in the session until the user confirms it through the `/confirm` route
+pub async fn link(
+    platform: AccountPlatformType,
+    platform_user: String,
+    data: &[NewAccountPlatformData],
+    transfer: bool,
+    session: &mut Session,
+    state: &ApplicationState<AccountExtension>,
+) -> LinkOutcome {
+    let account = match app::session::account(session, state).await {
+        Some(account) => account,
+        None => return LinkOutcome::Failed,
+    };
+
+    tracing::info!("Matching {} account", platform);
+    let account_platform = database::platform::read(platform, platform_user.clone(), state).await;
+    let account_platform = if let Some(mut account_platform_record) = account_platform {
+        if account_platform_record.account == account.id {
+            database::platform::update(&mut account_platform_record, state).await
+        } else if transfer {
+            tracing::info!(
+                "{} account moving from account {} to account {}",
+                platform,
+                account_platform_record.account,
+                account.id
+            );
+            database::platform::transfer(&account_platform_record, account.id, state).await
+        } else {
+            tracing::info!(
+                "{} account is already linked to account {}. Waiting on confirmation",
+                platform,
+                account_platform_record.account
+            );
+            let pending = PendingPlatformLink {
+                platform: platform.to_string(),
+                platform_user,
+                account: account_platform_record.account,
+                data: data.to_vec(),
+                expires_at: unix_timestamp() + OAUTH_STATE_LIFETIME,
+            };
+            app::session::write(SessionKey::PlatformPendingLink, pending, session);
+            return LinkOutcome::Conflict;
+        }
+    } else {
+        tracing::info!("New {} account needs to be linked", platform);
+        database::platform::create(
+            NewAccountPlatform {
+                account: account.id,
+                platform,
+                platform_user,
+            },
+            state,
+        )
+        .await
+    };
+
+    // update profile metadata
+    if let Some(account_platform) = account_platform {
+        database::platform_data::write(&account_platform, data, state).await;
+        LinkOutcome::Linked(account_platform)
+    } else {
+        LinkOutcome::Failed
+    }
+}
+
+/// Busts the profile cache tied to the session and the discord search cache of the session user
+pub async fn bust_cache(session: &Session, state: &mut ApplicationState<AccountExtension>) {
+    let cache_key = format!("{}{}", CACHE_KEY_PROFILE, session.id());
+    tracing::info!("Busting cache key: {}", cache_key);
+    state.extension.profiles.delete(&cache_key).await;
+
+    let discord_username = app::session::read::<String>(SessionKey::Username, session).unwrap_or_default();
+    let search_cache_key = format!("search_discord||{}", discord_username);
+    tracing::info!("Busting search key: {}", search_cache_key);
+    state.extension.searches.delete(&search_cache_key).await;
+}
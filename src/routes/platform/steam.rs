@@ -0,0 +1,287 @@
+use crate::app;
+use crate::app::extension::AccountExtension;
+use crate::app::platform::{PlatformOAuthState, PlatformProvider};
+use crate::app::session::SessionKey;
+use crate::database::platform::AccountPlatformType;
+use crate::database::platform_data::NewAccountPlatformData;
+use crate::routes::guards;
+use crate::routes::platform::provider;
+use axum::extract::{RawQuery, State};
+use axum::response::Redirect;
+use axum::routing::get;
+use axum::Router;
+use axum_sessions::extractors::WritableSession;
+use levelcrush::app::ApplicationState;
+use levelcrush::tracing;
+use levelcrush::{axum, axum_sessions, urlencoding};
+use std::collections::HashMap;
+
+const STEAM_OPENID_ENDPOINT: &str = "https://steamcommunity.com/openid/login";
+const STEAM_OPENID_IDENTITY_PREFIX: &str = "https://steamcommunity.com/openid/id/";
+const OPENID_NS: &str = "http://specs.openid.net/auth/2.0";
+const OPENID_IDENTIFIER_SELECT: &str = "http://specs.openid.net/auth/2.0/identifier_select";
+
+/// steam has no oauth token. Once the assertion has been verified all we have is the steam id
+#[derive(Default, Debug, Clone)]
+pub struct SteamValidation {
+    pub steam_id: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone)]
+pub struct SteamPlayerSummary {
+    pub steamid: String,
+    pub personaname: String,
+
+    #[serde(default)]
+    pub profileurl: String,
+
+    #[serde(default)]
+    pub avatarfull: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone)]
+pub struct SteamPlayerSummaries {
+    pub players: Vec<SteamPlayerSummary>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone)]
+pub struct SteamResponse<T> {
+    pub response: T,
+}
+
+pub struct SteamProvider;
+
+/// Steam uses OpenID 2.0 instead of oauth, so the validate route has to read the raw openid assertion
+/// Everything other than validate is the same as any other platform
+pub fn router() -> Router<ApplicationState<AccountExtension>> {
+    Router::new()
+        .route("/login", get(provider::login::<SteamProvider>))
+        .route("/validate", get(validate))
+        .route("/unlink", get(provider::unlink::<SteamProvider>))
+        .route("/confirm", get(provider::confirm::<SteamProvider>))
+        .route("/cancel", get(provider::cancel::<SteamProvider>))
+        .route_layer(axum::middleware::from_fn(guards::session_logged_in))
+}
+
+#[async_trait::async_trait]
+impl PlatformProvider for SteamProvider {
+    type Validation = SteamValidation;
+    type Identity = SteamPlayerSummary;
+
+    fn platform() -> AccountPlatformType {
+        AccountPlatformType::Steam
+    }
+
+    fn session_state_key() -> SessionKey {
+        SessionKey::PlatformSteamState
+    }
+
+    fn session_caller_key() -> SessionKey {
+        SessionKey::PlatformSteamCallerUrl
+    }
+
+    /// openid has no state parameter, so we attach it to the url steam returns the user to
+    fn authorize_url(oauth_state: &str, _code_challenge: &str, state: &ApplicationState<AccountExtension>) -> String {
+        let return_to = format!(
+            "{}?state={}",
+            state.extension.steam_validate_url,
+            urlencoding::encode(oauth_state)
+        );
+
+        format!(
+            "{}?openid.ns={}&openid.mode={}&openid.return_to={}&openid.realm={}&openid.identity={}&openid.claimed_id={}",
+            STEAM_OPENID_ENDPOINT,
+            urlencoding::encode(OPENID_NS),
+            "checkid_setup",
+            urlencoding::encode(return_to.as_str()),
+            urlencoding::encode(state.extension.server_host.as_str()),
+            urlencoding::encode(OPENID_IDENTIFIER_SELECT),
+            urlencoding::encode(OPENID_IDENTIFIER_SELECT),
+        )
+    }
+
+    /// the code for steam is the raw query string of the openid assertion.
+    /// The assertion is sent back to steam to confirm it was signed by them before we trust the claimed id
+    async fn exchange_token(
+        code: &str,
+        _code_verifier: &str,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Option<SteamValidation> {
+        let mut assertion = serde_urlencoded::from_str::<HashMap<String, String>>(code).unwrap_or_default();
+
+        let op_endpoint = assertion.get("openid.op_endpoint").cloned().unwrap_or_default();
+        if op_endpoint != STEAM_OPENID_ENDPOINT {
+            tracing::warn!("Steam assertion came from an unexpected endpoint: {}", op_endpoint);
+            return None;
+        }
+
+        let return_to = assertion.get("openid.return_to").cloned().unwrap_or_default();
+        if !return_to.starts_with(&state.extension.steam_validate_url) {
+            tracing::warn!("Steam assertion was not meant for us: {}", return_to);
+            return None;
+        }
+
+        let claimed_id = assertion.get("openid.claimed_id").cloned().unwrap_or_default();
+        let steam_id = claimed_id
+            .strip_prefix(STEAM_OPENID_IDENTITY_PREFIX)
+            .unwrap_or_default()
+            .to_string();
+        if steam_id.is_empty() || !steam_id.chars().all(|c| c.is_ascii_digit()) {
+            tracing::warn!("Steam assertion had an invalid claimed id: {}", claimed_id);
+            return None;
+        }
+
+        // only the openid fields are sent back to steam, our own state parameter is not part of the signature
+        assertion.retain(|key, _| key.starts_with("openid."));
+        assertion.insert("openid.mode".to_string(), "check_authentication".to_string());
+
+        let request = state
+            .extension
+            .http_client
+            .post(STEAM_OPENID_ENDPOINT)
+            .body(serde_urlencoded::to_string(&assertion).unwrap_or_default())
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .send()
+            .await;
+
+        let body = match request {
+            Ok(response) => response.text().await.unwrap_or_default(),
+            Err(err) => {
+                tracing::error!("Request Error: {}", err);
+                return None;
+            }
+        };
+
+        // response is in key-value form encoding, one pair per line
+        let is_valid = body.lines().any(|line| line.trim() == "is_valid:true");
+        if is_valid {
+            Some(SteamValidation { steam_id })
+        } else {
+            tracing::warn!("Steam could not verify the openid assertion for {}", steam_id);
+            None
+        }
+    }
+
+    async fn identity(
+        validation: &SteamValidation,
+        state: &ApplicationState<AccountExtension>,
+    ) -> Option<SteamPlayerSummary> {
+        let endpoint = format!(
+            "https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v2/?key={}&steamids={}",
+            urlencoding::encode(state.extension.steam_api_key.as_str()),
+            urlencoding::encode(validation.steam_id.as_str())
+        );
+
+        let request = state
+            .extension
+            .http_client
+            .get(endpoint)
+            .header("Accept", "application/json")
+            .send()
+            .await;
+
+        if let Ok(request) = request {
+            let result = request.json::<SteamResponse<SteamPlayerSummaries>>().await;
+            match result {
+                Ok(result) => result
+                    .response
+                    .players
+                    .into_iter()
+                    .find(|player| player.steamid == validation.steam_id),
+                Err(error) => {
+                    tracing::error!("Could not parse steam player summary response {}", error);
+                    None
+                }
+            }
+        } else {
+            tracing::error!("Could not fetch steam player summary");
+            None
+        }
+    }
+
+    fn platform_user(identity: &SteamPlayerSummary) -> String {
+        identity.steamid.clone()
+    }
+
+    fn platform_data(identity: &SteamPlayerSummary) -> Vec<NewAccountPlatformData> {
+        vec![
+            NewAccountPlatformData {
+                key: "steam_id".to_string(),
+                value: identity.steamid.clone(),
+            },
+            NewAccountPlatformData {
+                key: "display_name".to_string(),
+                value: identity.personaname.clone(),
+            },
+            NewAccountPlatformData {
+                key: "profile_url".to_string(),
+                value: identity.profileurl.clone(),
+            },
+            NewAccountPlatformData {
+                key: "avatar".to_string(),
+                value: identity.avatarfull.clone(),
+            },
+        ]
+    }
+}
+
+pub async fn validate(
+    RawQuery(raw_query): RawQuery,
+    State(mut state): State<ApplicationState<AccountExtension>>,
+    mut session: WritableSession,
+) -> Redirect {
+    let final_fallback_url = state.extension.fallback_url.clone();
+    let mut final_redirect =
+        app::session::read::<String>(SessionKey::PlatformSteamCallerUrl, &session).unwrap_or(final_fallback_url);
+
+    let raw_query = raw_query.unwrap_or_default();
+    let assertion = serde_urlencoded::from_str::<HashMap<String, String>>(&raw_query).unwrap_or_default();
+
+    let validation_state = assertion.get("state").cloned().unwrap_or_default();
+    let session_state = PlatformOAuthState::take(SessionKey::PlatformSteamState, &mut session)
+        .unwrap_or_default()
+        .state;
+    let openid_mode = assertion.get("openid.mode").cloned().unwrap_or_default();
+
+    let mut do_process = true;
+    if openid_mode != "id_res" {
+        tracing::warn!("Steam did not return a positive assertion: {}", openid_mode);
+        do_process = false;
+    }
+
+    if session_state.is_empty() || validation_state != session_state {
+        tracing::warn!(
+            "Validation State and Session state did not match: Steam ({}) || Session({})",
+            validation_state,
+            session_state
+        );
+        do_process = false;
+    }
+
+    // if we are not yet allowed to process then go ahead and simply return immediately to our final redirect url that we know about
+    if !do_process {
+        return Redirect::temporary(final_redirect.as_str());
+    }
+
+    tracing::info!("Validating steam openid assertion");
+    let validation = SteamProvider::exchange_token(&raw_query, "", &state).await;
+    let identity = if let Some(validation) = &validation {
+        SteamProvider::identity(validation, &state).await
+    } else {
+        None
+    };
+
+    if let Some(identity) = identity {
+        let platform_user = SteamProvider::platform_user(&identity);
+        let data = SteamProvider::platform_data(&identity);
+        let outcome = provider::link(SteamProvider::platform(), platform_user, &data, false, &mut session, &state).await;
+        if let provider::LinkOutcome::Conflict = outcome {
+            final_redirect = provider::conflict_redirect(&final_redirect, SteamProvider::platform());
+        }
+    }
+
+    provider::bust_cache(&session, &mut state).await;
+
+    // no matter what we redirect back to our caller
+    Redirect::temporary(final_redirect.as_str())
+}
@@ -1,10 +1,16 @@
 use crate::app::extension::AccountExtension;
+use crate::app::policy::{self, AccessDenial, AccessSubject};
 use crate::app::session::SessionKey;
+use crate::database::account::{Account, AccountMergeResult};
+use crate::database::platform::AccountPlatformType;
+use crate::routes::guards::{ChallengeLimit, RateLimited};
+use crate::routes::responses::{AccessTokenResponse, DiscordRole, LinkGeneratedResponse};
 use crate::{app, database};
-use axum::extract::State;
+use axum::extract::{Path, State};
 use axum::Router;
 use axum::{routing::get, routing::post, Json};
 use axum_sessions::extractors::ReadableSession;
+use levelcrush::alias::RecordId;
 use levelcrush::app::ApplicationState;
 use levelcrush::cache::{CacheDuration, CacheValue};
 use levelcrush::server::APIResponse;
@@ -18,9 +24,18 @@ pub const CACHE_KEY_PROFILE: &str = "profile||";
 
 #[derive(serde::Serialize, Default, Clone, Debug)]
 pub struct ProfileView {
+    /// the account the view belongs to, so challenges can be checked again when they are redeemed
+    #[serde(skip)]
+    pub account: RecordId,
     pub display_name: String,
     pub platforms: HashMap<String, HashMap<String, String>>,
+    /// discord roles keyed by the guild they belong to
+    pub discord_roles: HashMap<String, Vec<DiscordRole>>,
     pub is_admin: bool,
+    /// names of the roles handed to the account
+    pub roles: Vec<String>,
+    /// everything the account is allowed to do through its roles
+    pub permissions: Vec<String>,
     pub challenge: String,
 }
 
@@ -29,15 +44,38 @@ pub struct ChallengePayload {
     pub challenge: String,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+pub struct MergePayload {
+    pub code: String,
+}
+
+#[derive(serde::Serialize, Debug, Default, Clone)]
+pub struct SessionView {
+    pub id: RecordId,
+    pub user_agent: String,
+    pub ip: String,
+    pub last_seen_at: i64,
+    pub created_at: i64,
+    /// the session making the request
+    pub current: bool,
+}
+
 pub fn router() -> Router<ApplicationState<AccountExtension>> {
     Router::new()
         .route("/", get(json_view))
         .route("/json", get(json_view))
         .route("/challenge", post(challenge_view))
+        .route("/token", get(access_token))
+        .route("/merge/code", post(merge_code))
+        .route("/merge", post(merge))
+        .route("/sessions", get(sessions))
+        .route("/sessions/:id/revoke", post(revoke_session))
+        .route("/sessions/revoke-others", post(revoke_other_sessions))
 }
 
 pub async fn challenge_view(
     State(state): State<ApplicationState<AccountExtension>>,
+    _limit: RateLimited<ChallengeLimit>,
     Json(payload): Json<ChallengePayload>,
 ) -> Json<APIResponse<ProfileView>> {
     let mut response = APIResponse::new();
@@ -46,7 +84,202 @@ pub async fn challenge_view(
     if challenge_profile.is_some() {
         tracing::info!("Found challenge match!: {}", payload.challenge);
     }
-    response.data(challenge_profile);
+
+    // the account may have been banned since the challenge was handed out
+    let ban = match &challenge_profile {
+        Some(profile) => database::ban::active(profile.account, &state).await,
+        None => None,
+    };
+    if let Some(ban) = ban {
+        let denial = AccessDenial::from(&ban);
+        tracing::info!("Challenge {} denied: {}", payload.challenge, denial);
+        response.error(denial.reason(), &denial.to_string());
+    } else {
+        response.data(challenge_profile);
+    }
+
+    response.complete();
+    Json(response)
+}
+
+/// Issues a signed access token for the session account that downstream services can verify against our jwks.
+/// Services must also check that `aud` is `jwt::SESSION_TOKEN_AUDIENCE`
+pub async fn access_token(
+    State(state): State<ApplicationState<AccountExtension>>,
+    session: ReadableSession,
+) -> Json<APIResponse<AccessTokenResponse>> {
+    let mut response = APIResponse::new();
+
+    if let Some(account) = app::session::account(&session, &state).await {
+        match view(&account, &state).await {
+            Ok(_) => {
+                let token = app::jwt::access_token(&account, &state).await;
+                if token.is_none() {
+                    response.error("token", "Unable to issue an access token");
+                }
+                response.data(token.map(|(token, expires_at)| AccessTokenResponse { token, expires_at }));
+            }
+            Err(denial) => {
+                response.error(denial.reason(), &denial.to_string());
+            }
+        }
+    } else {
+        response.error("user", "User not found");
+    }
+
+    response.complete();
+    Json(response)
+}
+
+/// Generates a code that lets another account absorb the session account.
+/// The user has to be logged into the account that will be merged away, which proves they own it
+pub async fn merge_code(
+    State(mut state): State<ApplicationState<AccountExtension>>,
+    session: ReadableSession,
+) -> Json<APIResponse<LinkGeneratedResponse>> {
+    let mut response = APIResponse::new();
+
+    let account = app::session::account(&session, &state).await;
+    let ban = match &account {
+        Some(account) => database::ban::active(account.id, &state).await,
+        None => None,
+    };
+
+    if let Some(ban) = ban {
+        let denial = AccessDenial::from(&ban);
+        response.error(denial.reason(), &denial.to_string());
+    } else if let Some(account) = account {
+        let code = app::crypto::random_token(32);
+        state
+            .extension
+            .merge_codes
+            .write(
+                code.clone(),
+                CacheValue::with_duration(account, CacheDuration::FiveMinutes, CacheDuration::FiveMinutes),
+            )
+            .await;
+        response.data(Some(LinkGeneratedResponse { code }));
+    } else {
+        response.error("user", "User not found");
+    }
+
+    response.complete();
+    Json(response)
+}
+
+/// Merges the account the code was generated for into the session account
+pub async fn merge(
+    State(mut state): State<ApplicationState<AccountExtension>>,
+    session: ReadableSession,
+    Json(payload): Json<MergePayload>,
+) -> Json<APIResponse<AccountMergeResult>> {
+    let mut response = APIResponse::new();
+
+    let account = app::session::account(&session, &state).await;
+    let merge_from = state.extension.merge_codes.access(&payload.code).await;
+    match (account, merge_from) {
+        (Some(account), Some(merge_from)) => {
+            // codes are single use
+            state.extension.merge_codes.delete(&payload.code).await;
+
+            // the code may have been handed out before either account was banned. Merging would move the platforms out from under the ban
+            let mut ban = database::ban::active(merge_from.id, &state).await;
+            if ban.is_none() {
+                ban = database::ban::active(account.id, &state).await;
+            }
+            if let Some(ban) = ban {
+                let denial = AccessDenial::from(&ban);
+                tracing::info!("Merge of account {} into {} denied: {}", merge_from.id, account.id, denial);
+                response.error(denial.reason(), &denial.to_string());
+                response.complete();
+                return Json(response);
+            }
+
+            let result = database::account::merge(&merge_from, &account, &state).await;
+            if result.is_none() {
+                response.error("merge", "Unable to merge accounts");
+            }
+            response.data(result);
+
+            let cache_key = format!("{}{}", CACHE_KEY_PROFILE, session.id());
+            state.extension.profiles.delete(&cache_key).await;
+        }
+        (None, _) => {
+            response.error("user", "User not found");
+        }
+        (_, None) => {
+            response.error("code", "Merge code is either expired or incorrect");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+/// Every session the account is logged in with
+pub async fn sessions(
+    State(state): State<ApplicationState<AccountExtension>>,
+    session: ReadableSession,
+) -> Json<APIResponse<Vec<SessionView>>> {
+    let mut response = APIResponse::new();
+
+    if let Some(account) = app::session::account(&session, &state).await {
+        let sessions = database::session::by_account(&account, &state)
+            .await
+            .into_iter()
+            .map(|account_session| SessionView {
+                id: account_session.id,
+                current: account_session.session_id == session.id(),
+                user_agent: account_session.user_agent,
+                ip: account_session.ip,
+                last_seen_at: account_session.last_seen_at,
+                created_at: account_session.created_at,
+            })
+            .collect();
+        response.data(Some(sessions));
+    } else {
+        response.error("user", "User not found");
+    }
+
+    response.complete();
+    Json(response)
+}
+
+/// Logs one of the sessions of the account out
+pub async fn revoke_session(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path(id): Path<RecordId>,
+    session: ReadableSession,
+) -> Json<APIResponse<bool>> {
+    let mut response = APIResponse::new();
+
+    if let Some(account) = app::session::account(&session, &state).await {
+        let revoked = database::session::revoke(id, &account, &state).await;
+        if !revoked {
+            response.error("session", "Session not found");
+        }
+        response.data(Some(revoked));
+    } else {
+        response.error("user", "User not found");
+    }
+
+    response.complete();
+    Json(response)
+}
+
+/// Logs every session of the account out except the one making the request. Returns how many were logged out
+pub async fn revoke_other_sessions(
+    State(state): State<ApplicationState<AccountExtension>>,
+    session: ReadableSession,
+) -> Json<APIResponse<u64>> {
+    let mut response = APIResponse::new();
+
+    if let Some(account) = app::session::account(&session, &state).await {
+        let revoked = database::session::revoke_others(&account, session.id(), &state).await;
+        response.data(Some(revoked));
+    } else {
+        response.error("user", "User not found");
+    }
 
     response.complete();
     Json(response)
@@ -64,6 +297,49 @@ fn generate_challenge(display_name: &str, admin: i8) -> String {
     format!("{:x}", challenge_digest)
 }
 
+/// Builds the profile of the account from its linked platforms. The challenge is left empty for the caller to fill in.
+/// The access policy is checked on every build so losing a role or leaving a clan takes effect
+pub async fn view(account: &Account, state: &ApplicationState<AccountExtension>) -> Result<ProfileView, AccessDenial> {
+    tracing::info!("Fetching platforms from db!: {}", account.token);
+    let platforms = database::account::all_data(account, state).await;
+
+    if let Some(ban) = database::ban::active(account.id, state).await {
+        return Err(AccessDenial::from(&ban));
+    }
+
+    let subject = AccessSubject::from_platforms(account.admin == 1, &platforms);
+    policy::evaluate(&state.extension.access_policy, &subject)?;
+
+    // it is only possible for our account to have one discord linked account at a time
+    let display_name = platforms
+        .get(&AccountPlatformType::Discord.to_string())
+        .and_then(|platform_data| platform_data.get("display_name"))
+        .cloned()
+        .unwrap_or_default();
+
+    let roles = database::role::by_account(account, state)
+        .await
+        .into_iter()
+        .map(|role| role.name)
+        .collect();
+    let permissions = database::role::permissions(account, state)
+        .await
+        .iter()
+        .map(|permission| permission.to_string())
+        .collect();
+
+    Ok(ProfileView {
+        account: account.id,
+        display_name,
+        platforms,
+        discord_roles: subject.guild_roles,
+        is_admin: account.admin == 1,
+        roles,
+        permissions,
+        challenge: String::new(),
+    })
+}
+
 /// output a json view of the data related to the currently logged in session
 pub async fn json_view(
     State(mut state): State<ApplicationState<AccountExtension>>,
@@ -76,14 +352,12 @@ pub async fn json_view(
     // load session and fetch any relevant information
     let account_token =
         app::session::read::<String>(SessionKey::Account, &session).unwrap_or_default();
-    let account_token_secret =
-        app::session::read::<String>(SessionKey::AccountSecret, &session).unwrap_or_default();
 
     let mut profile_view = None;
     let mut account = None;
     let display_name =
         app::session::read::<String>(SessionKey::DisplayName, &session).unwrap_or_default();
-    if !account_token.is_empty() && !account_token_secret.is_empty() {
+    if !account_token.is_empty() {
         tracing::info!(
             "Checking if profile is being fetched already for: {}",
             display_name
@@ -106,7 +380,7 @@ pub async fn json_view(
     }
 
     let mut fetch_profile =
-        profile_view.is_none() && !account_token.is_empty() && !account_token_secret.is_empty();
+        profile_view.is_none() && !account_token.is_empty();
 
     if fetch_profile {
         tracing::info!("Locking  profile request for {}", display_name);
@@ -124,69 +398,54 @@ pub async fn json_view(
 
     if fetch_profile {
         tracing::info!("Fetching info from db!: {}", account_token);
-        account =
-            database::account::get(account_token.as_str(), &account_token_secret, &state).await;
+        account = app::session::account(&session, &state).await;
     }
 
     if fetch_profile {
         if let Some(account) = account {
-            // fetch account related data
-            let mut display_name = String::new();
-
-            tracing::info!("Fetching platforms from db!: {}", account_token);
-            let platforms = database::account::all_data(&account, &state).await;
-
-            // loop through the platform data nad find a platform that is discord related and pull information from there
-            for (platform, platform_data) in platforms.iter() {
-                if platform.contains("discord") {
-                    display_name = match platform_data.get("display_name") {
-                        Some(dn) => dn.clone(),
-                        _ => String::new(),
-                    };
-                    break; // no need to continue, it is only possible for our account to have one discord linked account at a time
+            match view(&account, &state).await {
+                Ok(mut data) => {
+                    // we will only keep this profile in the challenge cache for 5 minutes
+                    tracing::info!("Storing in challenge cache!: {}", data.display_name);
+                    let challenge_hash = generate_challenge(&data.display_name, account.admin);
+                    data.challenge = challenge_hash.clone();
+
+                    // intentionally keep this challenge cache around longer then the profile cache result
+                    state
+                        .extension
+                        .challenges
+                        .write(
+                            challenge_hash,
+                            CacheValue::with_duration(
+                                data.clone(),
+                                CacheDuration::TenMinutes,
+                                CacheDuration::TenMinutes,
+                            ),
+                        )
+                        .await;
+
+                    // save into cache
+                    tracing::info!("Storing in cache!: {}", data.display_name);
+                    state
+                        .extension
+                        .profiles
+                        .write(
+                            cache_key.clone(),
+                            CacheValue::with_duration(
+                                data.clone(),
+                                CacheDuration::Minute,
+                                CacheDuration::FiveMinutes,
+                            ),
+                        )
+                        .await;
+
+                    profile_view = Some(data);
+                }
+                Err(denial) => {
+                    tracing::info!("Profile denied for {}: {}", account_token, denial);
+                    response.error(denial.reason(), &denial.to_string());
                 }
             }
-
-            // we will only keep this profile in the challenge cache for 5 minutes
-            tracing::info!("Storing in challenge cache!: {}", display_name);
-            let challenge_hash = generate_challenge(&display_name, account.admin);
-            let data = ProfileView {
-                display_name,
-                platforms,
-                is_admin: account.admin == 1,
-                challenge: challenge_hash.clone(),
-            };
-
-            // intentionally keep this challenge cache around longer then the profile cache result
-            state
-                .extension
-                .challenges
-                .write(
-                    challenge_hash,
-                    CacheValue::with_duration(
-                        data.clone(),
-                        CacheDuration::TenMinutes,
-                        CacheDuration::TenMinutes,
-                    ),
-                )
-                .await;
-
-            // save into cache
-            tracing::info!("Storing in cache!: {}", data.display_name);
-            state
-                .extension
-                .profiles
-                .write(
-                    cache_key.clone(),
-                    CacheValue::with_duration(
-                        data.clone(),
-                        CacheDuration::Minute,
-                        CacheDuration::FiveMinutes,
-                    ),
-                )
-                .await;
-
-            profile_view = Some(data);
         } else {
             response.error("user", "User not found");
         }
@@ -4,6 +4,12 @@ use levelcrush::macros::ExternalAPIResponse;
 pub struct DiscordValidationResponse {
     pub access_token: String,
     pub refresh_token: String,
+
+    #[serde(default)]
+    pub expires_in: i64,
+
+    #[serde(default)]
+    pub scope: String,
 }
 
 #[ExternalAPIResponse]
@@ -22,6 +28,17 @@ pub struct DiscordRole {
     pub name: String,
 }
 
+#[ExternalAPIResponse]
+pub struct DiscordGuildMember {
+    /// ids of the roles the member has in the guild. The @everyone role is never included
+    #[serde(default)]
+    pub roles: Vec<String>,
+
+    /// empty when the user is not a member of the guild
+    #[serde(default)]
+    pub joined_at: String,
+}
+
 #[ExternalAPIResponse]
 pub struct DiscordGuild {
     pub id: String,
@@ -37,3 +54,9 @@ pub type DiscordUserGuildsResponse = Vec<DiscordGuild>;
 pub struct LinkGeneratedResponse {
     pub code: String,
 }
+
+#[derive(serde::Serialize)]
+pub struct AccessTokenResponse {
+    pub token: String,
+    pub expires_at: i64,
+}
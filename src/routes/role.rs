@@ -0,0 +1,118 @@
+use crate::app::extension::AccountExtension;
+use crate::database;
+use crate::database::role::RoleView;
+use crate::routes::guards::{ManageRoles, RequirePermission, ViewAccounts};
+use axum::extract::{Path, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use levelcrush::app::ApplicationState;
+use levelcrush::server::APIResponse;
+use levelcrush::{axum, tracing};
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
+pub struct RoleAssignmentPayload {
+    /// token of the account the role is handed to or taken from
+    pub account: String,
+    pub role: String,
+}
+
+/// Handing out roles to accounts. The roles themselves are created through the `role` job
+pub fn router() -> Router<ApplicationState<AccountExtension>> {
+    Router::new()
+        .route("/", get(list))
+        .route("/account/:token", get(account_roles))
+        .route("/assign", post(assign))
+        .route("/unassign", post(unassign))
+}
+
+async fn list(
+    State(state): State<ApplicationState<AccountExtension>>,
+    _manager: RequirePermission<ManageRoles>,
+) -> Json<APIResponse<Vec<RoleView>>> {
+    let mut response = APIResponse::new();
+
+    response.data(Some(database::role::all(&state).await));
+
+    response.complete();
+    Json(response)
+}
+
+async fn account_roles(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path(token): Path<String>,
+    _viewer: RequirePermission<ViewAccounts>,
+) -> Json<APIResponse<Vec<RoleView>>> {
+    let mut response = APIResponse::new();
+
+    match database::account::by_token(&token, &state).await {
+        Some(account) => {
+            response.data(Some(database::role::by_account(&account, &state).await));
+        }
+        None => {
+            response.error("account", "Account not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+async fn assign(
+    State(state): State<ApplicationState<AccountExtension>>,
+    RequirePermission { account: manager, .. }: RequirePermission<ManageRoles>,
+    Json(payload): Json<RoleAssignmentPayload>,
+) -> Json<APIResponse<Vec<RoleView>>> {
+    let mut response = APIResponse::new();
+
+    let account = database::account::by_token(&payload.account, &state).await;
+    let role = database::role::by_name(&payload.role, &state).await;
+    match (account, role) {
+        (Some(account), Some(role)) => {
+            tracing::info!("Account {} given the {} role by {}", account.id, role.name, manager.id);
+            if database::role::assign(&account, &role, Some(&manager), &state).await {
+                response.data(Some(database::role::by_account(&account, &state).await));
+            } else {
+                response.error("role", "Unable to assign role");
+            }
+        }
+        (None, _) => {
+            response.error("account", "Account not found");
+        }
+        (_, None) => {
+            response.error("role", "Role not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+async fn unassign(
+    State(state): State<ApplicationState<AccountExtension>>,
+    RequirePermission { account: manager, .. }: RequirePermission<ManageRoles>,
+    Json(payload): Json<RoleAssignmentPayload>,
+) -> Json<APIResponse<Vec<RoleView>>> {
+    let mut response = APIResponse::new();
+
+    let account = database::account::by_token(&payload.account, &state).await;
+    let role = database::role::by_name(&payload.role, &state).await;
+    match (account, role) {
+        (Some(account), Some(role)) => {
+            tracing::info!("Account {} lost the {} role through {}", account.id, role.name, manager.id);
+            if database::role::unassign(&account, &role, &state).await {
+                response.data(Some(database::role::by_account(&account, &state).await));
+            } else {
+                response.error("role", "Account does not have that role");
+            }
+        }
+        (None, _) => {
+            response.error("account", "Account not found");
+        }
+        (_, None) => {
+            response.error("role", "Role not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
@@ -1,6 +1,7 @@
 use crate::app::extension::AccountExtension;
 use crate::database;
 use crate::database::account::AccountLinkedPlatformsResult;
+use crate::routes::guards::{ApiKey, RateLimited, SearchLimit, SearchScope};
 use axum::extract::State;
 use axum::Router;
 use axum::{routing::get, Json};
@@ -21,6 +22,8 @@ pub fn router() -> Router<ApplicationState<AccountExtension>> {
 
 async fn discord_search(
     State(mut state): State<ApplicationState<AccountExtension>>,
+    _client: ApiKey<SearchScope>,
+    _limit: RateLimited<SearchLimit>,
     Path(discord): Path<String>,
 ) -> Json<APIResponse<AccountLinkedPlatformsResult>> {
     let mut response = APIResponse::new();
@@ -60,6 +63,8 @@ async fn discord_search(
 
 pub async fn bungie_search(
     State(mut state): State<ApplicationState<AccountExtension>>,
+    _client: ApiKey<SearchScope>,
+    _limit: RateLimited<SearchLimit>,
     Path(bungie): Path<String>,
 ) -> Json<APIResponse<AccountLinkedPlatformsResult>> {
     let mut response = APIResponse::new();
@@ -99,6 +104,8 @@ pub async fn bungie_search(
 
 pub async fn bungie_search_mass(
     State(mut state): State<ApplicationState<AccountExtension>>,
+    _client: ApiKey<SearchScope>,
+    _limit: RateLimited<SearchLimit>,
     payload: Option<Json<Vec<String>>>,
 ) -> Json<APIResponse<HashMap<String, Option<AccountLinkedPlatformsResult>>>> {
     let mut response = APIResponse::new();
@@ -0,0 +1,192 @@
+use crate::app::extension::AccountExtension;
+use crate::database::platform::AccountPlatformType;
+use crate::database::transfer::AccountTransfer;
+use crate::routes::guards::{self, RequirePermission, ReviewTransfers};
+use crate::{app, database};
+use axum::extract::{Path, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_sessions::extractors::ReadableSession;
+use levelcrush::alias::RecordId;
+use levelcrush::app::ApplicationState;
+use levelcrush::server::APIResponse;
+use levelcrush::{axum, axum_sessions, tracing};
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
+pub struct TransferRequestPayload {
+    /// discord id or username of the discord account that was lost
+    pub discord: String,
+
+    #[serde(default)]
+    pub reason: String,
+}
+
+#[derive(serde::Serialize, Clone, Default, Debug)]
+pub struct TransferView {
+    pub id: RecordId,
+    pub platform: String,
+    pub old_platform_user: String,
+    pub new_platform_user: String,
+    pub reason: String,
+    pub status: String,
+    pub created_at: i64,
+}
+
+impl From<AccountTransfer> for TransferView {
+    fn from(transfer: AccountTransfer) -> Self {
+        TransferView {
+            id: transfer.id,
+            platform: transfer.platform,
+            old_platform_user: transfer.old_platform_user,
+            new_platform_user: transfer.new_platform_user,
+            reason: transfer.reason,
+            status: transfer.status,
+            created_at: transfer.created_at,
+        }
+    }
+}
+
+/// Moving a discord identity from one discord user to another.
+/// The member logs in with their new discord account and requests the transfer, then someone allowed to review transfers approves or denies it
+pub fn router() -> Router<ApplicationState<AccountExtension>> {
+    Router::new()
+        .route(
+            "/request",
+            post(request).route_layer(axum::middleware::from_fn(guards::session_logged_in_json)),
+        )
+        .route("/pending", get(pending))
+        .route("/:id/approve", post(approve))
+        .route("/:id/deny", post(deny))
+}
+
+/// Requests that the account tied to the lost discord account is moved over to the discord account the session logged in with.
+/// The new discord user id comes from the oauth login of the session, so it has already been verified
+async fn request(
+    State(state): State<ApplicationState<AccountExtension>>,
+    session: ReadableSession,
+    Json(payload): Json<TransferRequestPayload>,
+) -> Json<APIResponse<TransferView>> {
+    let mut response = APIResponse::new();
+
+    let requested_by = app::session::account(&session, &state).await;
+    let new_platform = match &requested_by {
+        Some(account) => database::platform::from_account(account, AccountPlatformType::Discord, &state).await,
+        None => None,
+    };
+
+    // the lost discord account can be looked up by id or by username
+    let old_platform = match database::platform::read(AccountPlatformType::Discord, payload.discord.clone(), &state).await {
+        Some(platform) => Some(platform),
+        None => match database::account::by_discord(payload.discord.clone(), &state).await {
+            Some(result) => match database::account::by_token(&result.account_token, &state).await {
+                Some(account) => database::platform::from_account(&account, AccountPlatformType::Discord, &state).await,
+                None => None,
+            },
+            None => None,
+        },
+    };
+    let account = match &old_platform {
+        Some(platform) => database::account::by_id(platform.account, &state).await,
+        None => None,
+    };
+
+    match (requested_by, new_platform, old_platform, account) {
+        (Some(requested_by), Some(new_platform), Some(old_platform), Some(account)) => {
+            if requested_by.id == account.id {
+                response.error("discord", "This discord account is already linked to you");
+            } else {
+                tracing::info!(
+                    "Discord transfer requested from {} to {}",
+                    old_platform.platform_user,
+                    new_platform.platform_user
+                );
+                let transfer = database::transfer::create(
+                    &account,
+                    &requested_by,
+                    &old_platform.platform_user,
+                    &new_platform.platform_user,
+                    &payload.reason,
+                    &state,
+                )
+                .await;
+                response.data(transfer.map(TransferView::from));
+            }
+        }
+        (None, _, _, _) | (_, None, _, _) => {
+            response.error("user", "User not found");
+        }
+        _ => {
+            response.error("discord", "Could not find an account for that discord user");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+async fn pending(
+    State(state): State<ApplicationState<AccountExtension>>,
+    _reviewer: RequirePermission<ReviewTransfers>,
+) -> Json<APIResponse<Vec<TransferView>>> {
+    let mut response = APIResponse::new();
+
+    let transfers = database::transfer::pending(&state).await;
+    response.data(Some(transfers.into_iter().map(TransferView::from).collect()));
+
+    response.complete();
+    Json(response)
+}
+
+async fn approve(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path(id): Path<RecordId>,
+    RequirePermission { account: reviewer, .. }: RequirePermission<ReviewTransfers>,
+) -> Json<APIResponse<TransferView>> {
+    let mut response = APIResponse::new();
+
+    let transfer = database::transfer::get(id, &state).await;
+    match transfer {
+        Some(transfer) => {
+            tracing::info!("Transfer {} approved by {}", transfer.id, reviewer.id);
+            if database::transfer::approve(&transfer, &reviewer, &state).await {
+                let transfer = database::transfer::get(id, &state).await;
+                response.data(transfer.map(TransferView::from));
+            } else {
+                response.error("transfer", "Unable to approve transfer");
+            }
+        }
+        None => {
+            response.error("transfer", "Transfer not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
+
+async fn deny(
+    State(state): State<ApplicationState<AccountExtension>>,
+    Path(id): Path<RecordId>,
+    RequirePermission { account: reviewer, .. }: RequirePermission<ReviewTransfers>,
+) -> Json<APIResponse<TransferView>> {
+    let mut response = APIResponse::new();
+
+    let transfer = database::transfer::get(id, &state).await;
+    match transfer {
+        Some(transfer) => {
+            tracing::info!("Transfer {} denied by {}", transfer.id, reviewer.id);
+            if database::transfer::deny(&transfer, &reviewer, &state).await {
+                let transfer = database::transfer::get(id, &state).await;
+                response.data(transfer.map(TransferView::from));
+            } else {
+                response.error("transfer", "Unable to deny transfer");
+            }
+        }
+        None => {
+            response.error("transfer", "Transfer not found");
+        }
+    }
+
+    response.complete();
+    Json(response)
+}
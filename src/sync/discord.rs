@@ -5,13 +5,23 @@ use crate::{
         platform::{AccountPlatformType, NewAccountPlatform},
         platform_data::NewAccountPlatformData,
     },
-    routes::responses::DiscordUserResponse,
+    routes::responses::{DiscordRole, DiscordUserGuildsResponse, DiscordUserResponse},
 };
-use levelcrush::{app::ApplicationState, tokio, tracing, util::unix_timestamp, uuid::Uuid};
+use levelcrush::{app::ApplicationState, tokio, tracing};
+use std::collections::HashMap;
+
+/// platform data key the guild roles of a discord member are stored under, as json keyed by guild id.
+/// Every guild the member is in has an entry, even when they have no roles there
+pub const GUILD_ROLES_KEY: &str = "guild_roles";
+
+/// platform data key the guilds discord lists for the member through oauth are stored under, comma separated.
+/// Our bot may not be in every guild, so these count as membership too
+pub const OAUTH_GUILDS_KEY: &str = "oauth_guilds";
 
 #[derive(Default, Clone, Debug)]
 pub struct MemberSyncResult {
     pub account_token: String,
+    /// only set when the account was just created or rotated, since the plain text secret is not kept anywhere
     pub account_token_secret: String,
     pub display_name: String,
     pub username: String,
@@ -22,7 +32,7 @@ pub async fn member(
     discord_user: DiscordUserResponse,
     state: &ApplicationState<AccountExtension>,
 ) -> Option<MemberSyncResult> {
-    let discord_user_id = discord_user.id.unwrap_or_default();
+    let discord_user_id = discord_user.id.clone().unwrap_or_default();
     let mut account = database::platform::match_account(
         discord_user_id.clone(),
         AccountPlatformType::Discord,
@@ -33,24 +43,14 @@ pub async fn member(
     let new_account = if account.is_none() {
         // new account
         // no account found. Let's create an account first
-        let timestamp = unix_timestamp();
-        let token_seed = format!(
-            "{}||{}||{}||{}",
-            timestamp,
-            discord_user_id.clone(),
-            discord_user.discriminator.clone(),
-            Uuid::new_v4(),
-        );
-        let token_secret_seed = format!(
-            "..{}..||..{}..||..{}..",
-            token_seed.clone(),
-            Uuid::new_v4(),
-            timestamp
-        );
-
-        // create an account for this
         tracing::info!("Creating account");
-        account = database::account::create(&token_seed, &token_secret_seed, state).await;
+        account = match database::account::create(state).await {
+            Some(created) => {
+                sync_result.account_token_secret = created.secret;
+                Some(created.account)
+            }
+            None => None,
+        };
 
         true
     } else {
@@ -79,54 +79,121 @@ pub async fn member(
                     .await;
         }
 
+        // accounts from before secrets were hashed get their new token the first time they log in
+        let account = if database::account::is_legacy(&account) {
+            match database::account::rotate(&account, true, state).await {
+                Some(rotated) => {
+                    sync_result.account_token_secret = rotated.secret;
+                    rotated.account
+                }
+                None => account,
+            }
+        } else {
+            account
+        };
+
         sync_result.account_token = account.token;
-        sync_result.account_token_secret = account.token_secret;
     }
 
     if let Some(mut account_platform) = account_platform {
         // everytime we log in, we are going to write out this information here
-        let discord_user_name = if discord_user.discriminator == "0" {
-            discord_user.username.clone()
-        } else {
-            format!("{}#{}", discord_user.username, discord_user.discriminator)
-        };
-
-        let display_name = if let Some(discord_display_name) = discord_user.display_name {
-            discord_display_name
-        } else if let Some(global) = discord_user.global_name {
-            global
-        } else {
-            discord_user_name.clone()
-        };
-
-        let data = vec![
-            NewAccountPlatformData {
-                key: "discord_id".to_string(),
-                value: account_platform.platform_user.clone(),
-            },
-            NewAccountPlatformData {
-                key: "username".to_string(),
-                value: discord_user_name.clone(),
-            },
-            NewAccountPlatformData {
-                key: "display_name".to_string(),
-                value: display_name.clone(),
-            },
-            NewAccountPlatformData {
-                key: "avatar".to_string(),
-                value: discord_user.avatar.unwrap_or_default(),
-            },
-        ];
+        let data = platform_data(&discord_user);
 
         // write the metadata out to be linked to the platform
         database::platform_data::write(&account_platform, &data, state).await;
         database::platform::update(&mut account_platform, state).await;
 
-        sync_result.display_name = display_name;
-        sync_result.username = discord_user_name;
+        sync_result.display_name = display_name(&discord_user);
+        sync_result.username = username(&discord_user);
 
         Some(sync_result)
     } else {
         None
     }
 }
+
+/// the discord username, with the discriminator attached for users that have not migrated to the new username system
+pub fn username(discord_user: &DiscordUserResponse) -> String {
+    if discord_user.discriminator == "0" {
+        discord_user.username.clone()
+    } else {
+        format!("{}#{}", discord_user.username, discord_user.discriminator)
+    }
+}
+
+/// the best display name we can find for the discord user
+pub fn display_name(discord_user: &DiscordUserResponse) -> String {
+    if let Some(discord_display_name) = &discord_user.display_name {
+        discord_display_name.clone()
+    } else if let Some(global) = &discord_user.global_name {
+        global.clone()
+    } else {
+        username(discord_user)
+    }
+}
+
+/// maps the discord user into the platform data we store on the discord account platform
+pub fn platform_data(discord_user: &DiscordUserResponse) -> Vec<NewAccountPlatformData> {
+    vec![
+        NewAccountPlatformData {
+            key: "discord_id".to_string(),
+            value: discord_user.id.clone().unwrap_or_default(),
+        },
+        NewAccountPlatformData {
+            key: "username".to_string(),
+            value: username(discord_user),
+        },
+        NewAccountPlatformData {
+            key: "display_name".to_string(),
+            value: display_name(discord_user),
+        },
+        NewAccountPlatformData {
+            key: "avatar".to_string(),
+            value: discord_user.avatar.clone().unwrap_or_default(),
+        },
+    ]
+}
+
+/// Stores the roles a discord member has in each guild on their discord platform
+pub async fn roles(
+    discord_id: &str,
+    guild_roles: &HashMap<String, Vec<DiscordRole>>,
+    state: &ApplicationState<AccountExtension>,
+) {
+    let account_platform =
+        database::platform::read(AccountPlatformType::Discord, discord_id.to_string(), state).await;
+
+    if let Some(account_platform) = account_platform {
+        let data = vec![NewAccountPlatformData {
+            key: GUILD_ROLES_KEY.to_string(),
+            value: serde_json::to_string(guild_roles).unwrap_or_default(),
+        }];
+        database::platform_data::write(&account_platform, &data, state).await;
+    } else {
+        tracing::warn!("No discord platform to store roles on for {}", discord_id);
+    }
+}
+
+/// Stores the guilds discord listed for the member through oauth on their discord platform
+pub async fn oauth_guilds(
+    discord_id: &str,
+    guilds: &DiscordUserGuildsResponse,
+    state: &ApplicationState<AccountExtension>,
+) {
+    let account_platform =
+        database::platform::read(AccountPlatformType::Discord, discord_id.to_string(), state).await;
+
+    if let Some(account_platform) = account_platform {
+        let data = vec![NewAccountPlatformData {
+            key: OAUTH_GUILDS_KEY.to_string(),
+            value: guilds
+                .iter()
+                .map(|guild| guild.id.as_str())
+                .collect::<Vec<&str>>()
+                .join(","),
+        }];
+        database::platform_data::write(&account_platform, &data, state).await;
+    } else {
+        tracing::warn!("No discord platform to store oauth guilds on for {}", discord_id);
+    }
+}